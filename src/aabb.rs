@@ -1,6 +1,10 @@
 use crate::ray::Ray;
 use crate::vec::Vec3;
 
+/// Boxes around flat shapes are padded by this much along any axis they're flat in, since a box with no thickness
+/// can't be hit.
+const PADDING: f64 = 0.0001;
+
 #[derive(Copy, Clone, Debug)]
 pub struct AABB {
     pub min: Vec3,
@@ -8,6 +12,22 @@ pub struct AABB {
 }
 
 impl AABB {
+    /// Return the box around 'points', padded along any axis it's flat in.
+    ///
+    /// Panics if there are no points.
+    pub(crate) fn padded_around(points: &[Vec3]) -> Self {
+        let (min, max) = points[1..]
+            .iter()
+            .fold((points[0], points[0]), |(min, max), &p| {
+                (min.zip_with(p, f64::min), max.zip_with(p, f64::max))
+            });
+        let pad = (max - min).map(|e| if e < PADDING { PADDING / 2.0 } else { 0.0 });
+        AABB {
+            min: min - pad,
+            max: max + pad,
+        }
+    }
+
     pub fn merge(self, other: AABB) -> Self {
         AABB {
             min: self.min.zip_with(other.min, f64::min),
@@ -15,6 +35,17 @@ impl AABB {
         }
     }
 
+    /// Return the box around the space inside both boxes. If they don't overlap, it's an empty box: a single point,
+    /// which no ray can hit.
+    pub fn overlap(self, other: AABB) -> Self {
+        let min = self.min.zip_with(other.min, f64::max);
+        let max = self.max.zip_with(other.max, f64::min);
+        match (max - min).reduce(f64::min) < 0.0 {
+            true => AABB { min, max: min },
+            false => AABB { min, max },
+        }
+    }
+
     pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
         let inv_d = ray.direction.map(|x| 1.0 / x);
         let t0 = (self.min - ray.origin) * inv_d;
@@ -0,0 +1,253 @@
+use crate::colour::Colour;
+use crate::hittable::HitRecord;
+use crate::image::Image;
+use crate::named::named_enum;
+use crate::ray::Ray;
+use crate::sampler::mix_bits;
+
+/// Arbitrary output variables: buffers of first-hit data rendered alongside the beauty pass. Pixels where the camera
+/// ray hits nothing are black.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    Albedo,     // Base colour of the material, ignoring lighting.
+    Normal,     // World space normal, facing the camera.
+    Depth,      // Distance from the camera to the hit, in all three channels.
+    Position,   // World space position of the hit.
+    Uv,         // Surface texture coordinates in R and G.
+    ObjectId, // ID of the object that was hit, in all three channels. Taken from the first sample of the pixel.
+    MaterialId, // ID of the material that was hit, in all three channels. Taken from the first sample of the pixel.
+    Samples, // Number of samples taken for the pixel, in all three channels. Differs between pixels when adaptive.
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::Uv,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Samples,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Samples => "samples",
+        }
+    }
+
+    /// Whether the AOV is averaged over every sample of a pixel. IDs can't be averaged, so only one sample is kept.
+    pub fn is_filtered(self) -> bool {
+        !matches!(self, Aov::ObjectId | Aov::MaterialId | Aov::Samples)
+    }
+
+    /// Return the value of the AOV for a camera ray that hit 'hit', or None if it isn't a property of the hit.
+    pub fn value(self, r: &Ray, hit: &HitRecord) -> Option<Colour> {
+        let c = match self {
+            Aov::Albedo => hit.material.albedo(hit),
+            Aov::Normal => Colour::new(hit.normal.0, hit.normal.1, hit.normal.2),
+            Aov::Depth => {
+                let d = hit.t * r.direction.mag();
+                Colour::new(d, d, d)
+            }
+            Aov::Position => Colour::new(hit.p.0, hit.p.1, hit.p.2),
+            Aov::Uv => Colour::new(hit.u, hit.v, 0.0),
+            Aov::ObjectId => {
+                let id = hit.object_id as f64;
+                Colour::new(id, id, id)
+            }
+            Aov::MaterialId => {
+                let id = hit.material_id as f64;
+                Colour::new(id, id, id)
+            }
+            Aov::Samples => return None,
+        };
+        Some(c)
+    }
+
+    /// Convert a buffer of this AOV into values in [0, 1] that can be viewed in an ordinary image. Normals are mapped
+    /// from [-1, 1], depth and position are scaled by their largest magnitude, each ID gets a random colour and sample
+    /// counts become a heatmap.
+    pub fn to_display(self, image: &Image) -> Image {
+        let max = image
+            .pixels
+            .iter()
+            .map(|c| c.r.abs().max(c.g.abs()).max(c.b.abs()))
+            .fold(0.0, f64::max)
+            .max(f64::MIN_POSITIVE);
+
+        let pixels = image.pixels.iter().map(|&c| match self {
+            Aov::Albedo | Aov::Uv => c,
+            Aov::Normal => Colour::new(c.r * 0.5 + 0.5, c.g * 0.5 + 0.5, c.b * 0.5 + 0.5),
+            Aov::Depth | Aov::Position => {
+                Colour::new(c.r.abs() / max, c.g.abs() / max, c.b.abs() / max)
+            }
+            Aov::ObjectId | Aov::MaterialId => id_colour(c.r as u32),
+            Aov::Samples => heatmap(c.r / max),
+        });
+
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels: pixels.collect(),
+        }
+    }
+}
+
+/// Pick a random but consistent colour for an ID. ID zero, for nothing, is black.
+fn id_colour(id: u32) -> Colour {
+    if id == 0 {
+        return Colour::new(0.0, 0.0, 0.0);
+    }
+    let h = mix_bits(id as u64);
+    let channel = |shift: u32| ((h >> shift) & 0xff) as f64 / 255.0;
+    Colour::new(channel(0), channel(8), channel(16))
+}
+
+/// Map 't' in [0, 1] to a colour running from dark blue through cyan, green and yellow to red.
+fn heatmap(t: f64) -> Colour {
+    const STOPS: [(f64, f64, f64); 5] = [
+        (0.0, 0.0, 0.5),
+        (0.0, 0.8, 1.0),
+        (0.1, 0.8, 0.1),
+        (1.0, 0.9, 0.0),
+        (0.9, 0.0, 0.0),
+    ];
+
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let k = (x as usize).min(STOPS.len() - 2);
+    let f = x - k as f64;
+    let (a, b) = (STOPS[k], STOPS[k + 1]);
+    Colour::new(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+named_enum!(Aov, "AOV");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::BVH;
+    use crate::camera::Camera;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::rect::XYRect;
+    use crate::render::{render_with_aovs, RenderOutput, RenderSettings};
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColour;
+    use crate::vec::Vec3;
+
+    const WALL: Colour = Colour {
+        r: 0.2,
+        g: 0.4,
+        b: 0.6,
+    };
+
+    /// A red sphere in front of a smaller wall, seen from straight ahead. The corners of the image miss both.
+    fn scene() -> (BVH, Camera) {
+        let red = Lambertian::new(SolidColour::new(Colour::new(0.8, 0.1, 0.1)));
+        let objs: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, red)),
+            Box::new(XYRect::new(
+                -3.0,
+                3.0,
+                -3.0,
+                3.0,
+                -2.0,
+                Lambertian::new(SolidColour::new(WALL)),
+            )),
+        ];
+        let camera = Camera::new(
+            Colour::new(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 10.0),
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+            0.0,
+            1.0,
+        );
+        (BVH::new(objs, 0.0, 1.0), camera)
+    }
+
+    fn render(aovs: Vec<Aov>) -> RenderOutput {
+        let (world, camera) = scene();
+        let settings = RenderSettings {
+            width: 32,
+            height: 32,
+            samples: 4,
+            max_depth: 4,
+            aovs,
+            ..RenderSettings::default()
+        };
+        render_with_aovs(&world, &camera, &settings)
+    }
+
+    #[test]
+    fn ids_are_the_ones_the_bvh_gives() {
+        let (world, camera) = scene();
+        let output = render(vec![Aov::ObjectId, Aov::MaterialId]);
+        let mut sampler = SamplerKind::Independent.create(0, 1);
+
+        // The middle of the sphere, the wall beside it and a corner that misses.
+        for (x, y, object_id, material_id) in [(16, 16, 1, 1), (7, 16, 2, 2), (0, 0, 0, 0)] {
+            let (u, v) = ((x as f64 + 0.5) / 32.0, (31.5 - y as f64) / 32.0);
+            let r = camera.get_ray(u, v, sampler.as_mut());
+            let (expected_object, expected_material) =
+                world.hit(&r, 0.001, f64::MAX).map_or((0, 0), |hit| {
+                    (hit.object_id, world.material_id(hit.material))
+                });
+            assert_eq!(
+                (expected_object, expected_material),
+                (object_id, material_id)
+            );
+
+            let id = |aov| output.aov(aov).unwrap().get(x, y).r as u32;
+            assert_eq!(id(Aov::ObjectId), expected_object, "pixel ({x}, {y})");
+            assert_eq!(id(Aov::MaterialId), expected_material, "pixel ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn normal_and_albedo() {
+        let output = render(vec![Aov::Normal, Aov::Albedo]);
+        let normal = output.aov(Aov::Normal).unwrap();
+        let albedo = output.aov(Aov::Albedo).unwrap();
+
+        // The wall faces the camera everywhere, so every sample has the same normal and albedo.
+        assert_eq!(normal.get(7, 16), Colour::new(0.0, 0.0, 1.0));
+        assert_eq!(albedo.get(7, 16), WALL);
+
+        // The middle of the sphere faces the camera too, give or take the spread of the samples over the pixel.
+        let n = normal.get(16, 16);
+        assert!(n.b > 0.95, "{n:?}");
+        assert_eq!(albedo.get(16, 16), Colour::new(0.8, 0.1, 0.1));
+
+        assert_eq!(normal.get(0, 0), Colour::new(0.0, 0.0, 0.0));
+        assert_eq!(albedo.get(0, 0), Colour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn id_colours() {
+        assert_eq!(id_colour(0), Colour::new(0.0, 0.0, 0.0));
+        for id in 1..100 {
+            assert_ne!(id_colour(id), id_colour(id + 1), "ID {id}");
+            assert_ne!(id_colour(id), Colour::new(0.0, 0.0, 0.0), "ID {id}");
+            assert_eq!(id_colour(id), id_colour(id));
+        }
+    }
+}
@@ -1,24 +1,102 @@
+use std::collections::HashMap;
+
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
 
 use crate::ray::Ray;
 use crate::vec::Axis::{self, *};
 
+/// A BVH sorts objects into a tree of bounding boxes so a ray only has to be tested against the objects whose boxes
+/// it passes through. Objects without a bounding box, like an InfinitePlane, can't go in the tree, so they're kept in
+/// a list beside it and tested against every ray.
 pub struct BVH {
+    tree: Option<BVHNode>,
+    unbounded: Vec<(u32, Box<dyn Hittable>)>,
+    material_ids: HashMap<(usize, &'static str), u32>, // Material IDs by the materials' identities.
+}
+
+pub struct BVHNode {
     bounding_box: AABB,
     size: usize,
     contents: BVHContents,
 }
 
 pub enum BVHContents {
-    Node { left: Box<BVH>, right: Box<BVH> },
-    Leaf(Box<dyn Hittable>),
+    Node {
+        left: Box<BVHNode>,
+        right: Box<BVHNode>,
+    },
+    Leaf {
+        id: u32,
+        obj: Box<dyn Hittable>,
+    },
 }
 
 impl BVH {
-    pub fn new(mut objs: Vec<Box<dyn Hittable>>, t0: f64, t1: f64) -> Self {
-        fn axis_range(objs: &[Box<dyn Hittable>], t0: f64, t1: f64, axis: Axis) -> f64 {
-            let range = objs.iter().fold(std::f64::MAX..std::f64::MAX, |range, o| {
+    /// Build a BVH over 'objs'. Hits are tagged with the object's position in 'objs', which makes it the object's ID.
+    /// Materials are numbered in the order they're first found in going through 'objs', which makes it the ID
+    /// 'material_id' gives them.
+    pub fn new(objs: Vec<Box<dyn Hittable>>, t0: f64, t1: f64) -> Self {
+        let mut material_ids = HashMap::new();
+        for material in objs.iter().flat_map(|obj| obj.materials()) {
+            let next_id = material_ids.len() as u32 + 1;
+            material_ids.entry(material.identity()).or_insert(next_id);
+        }
+
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = objs
+            .into_iter()
+            .enumerate()
+            .map(|(i, obj)| (i as u32 + 1, obj))
+            .partition(|(_, obj)| obj.bounding_box(t0, t1).is_some());
+
+        let tree = match bounded.is_empty() {
+            true => None,
+            false => Some(BVHNode::build(bounded, t0, t1)),
+        };
+        Self {
+            tree,
+            unbounded,
+            material_ids,
+        }
+    }
+}
+
+impl Hittable for BVH {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord<'_>> {
+        let mut hit_obj = self.tree.as_ref().and_then(|tree| tree.hit(r, t0, t1));
+        let mut closest = hit_obj.as_ref().map_or(t1, |h| h.t);
+
+        for (id, obj) in &self.unbounded {
+            if let Some(mut hit) = obj.hit(r, t0, closest) {
+                closest = hit.t;
+                hit.object_id = *id;
+                hit_obj = Some(hit);
+            }
+        }
+        hit_obj
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        match self.unbounded.is_empty() {
+            true => self.tree.as_ref().map(|tree| tree.bounding_box),
+            false => None,
+        }
+    }
+
+    fn material_id(&self, material: &dyn Material) -> u32 {
+        self.material_ids
+            .get(&material.identity())
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl BVHNode {
+    /// Build the tree over 'objs', which all have bounding boxes.
+    fn build(mut objs: Vec<(u32, Box<dyn Hittable>)>, t0: f64, t1: f64) -> Self {
+        fn axis_range(objs: &[(u32, Box<dyn Hittable>)], t0: f64, t1: f64, axis: Axis) -> f64 {
+            let range = objs.iter().fold(f64::MAX..f64::MAX, |range, (_, o)| {
                 let bb = o.bounding_box(t0, t1).unwrap();
                 let min = bb.min[axis].min(bb.max[axis]);
                 let max = bb.min[axis].max(bb.max[axis]);
@@ -37,7 +115,7 @@ impl BVH {
             ranges[0].0
         };
 
-        objs.sort_unstable_by(|a, b| {
+        objs.sort_unstable_by(|(_, a), (_, b)| {
             let abb = a.bounding_box(t0, t1).unwrap();
             let bbb = b.bounding_box(t0, t1).unwrap();
             let av = abb.min[axis] + abb.max[axis];
@@ -46,18 +124,21 @@ impl BVH {
         });
 
         match objs.len() {
-            0 => panic!("can't create BVH from 0 objects"),
-            1 => BVH {
-                bounding_box: objs[0].bounding_box(t0, t1).unwrap(),
-                size: 1,
-                contents: BVHContents::Leaf(objs.pop().unwrap()),
-            },
+            0 => panic!("can't create a BVH node from 0 objects"),
+            1 => {
+                let (id, obj) = objs.pop().unwrap();
+                BVHNode {
+                    bounding_box: obj.bounding_box(t0, t1).unwrap(),
+                    size: 1,
+                    contents: BVHContents::Leaf { id, obj },
+                }
+            }
 
             _ => {
-                let right = Box::new(BVH::new(objs.split_off(objs.len() / 2), t0, t1));
-                let left = Box::new(BVH::new(objs, t0, t1));
+                let right = Box::new(BVHNode::build(objs.split_off(objs.len() / 2), t0, t1));
+                let left = Box::new(BVHNode::build(objs, t0, t1));
 
-                BVH {
+                BVHNode {
                     bounding_box: right.bounding_box.merge(left.bounding_box),
                     size: left.size + right.size,
                     contents: BVHContents::Node { left, right },
@@ -67,14 +148,17 @@ impl BVH {
     }
 }
 
-impl Hittable for BVH {
-    fn hit(&self, r: &Ray, t0: f64, mut t1: f64) -> Option<HitRecord> {
+impl Hittable for BVHNode {
+    fn hit(&self, r: &Ray, t0: f64, mut t1: f64) -> Option<HitRecord<'_>> {
         if !self.bounding_box.hit(r, t0, t1) {
             return None;
         }
 
         match &self.contents {
-            BVHContents::Leaf(obj) => obj.hit(r, t0, t1),
+            BVHContents::Leaf { id, obj } => obj.hit(r, t0, t1).map(|mut hit| {
+                hit.object_id = *id;
+                hit
+            }),
             BVHContents::Node { left, right } => {
                 let hit_left = left.hit(r, t0, t1);
                 if let Some(h) = &hit_left {
@@ -100,3 +184,119 @@ impl Hittable for BVH {
         Some(self.bounding_box)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+    use crate::planar::InfinitePlane;
+    use crate::sampler::Sampler;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColour;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn unbounded_objects_are_hit_outside_the_tree() {
+        let grey = || Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)));
+        let objs: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(Vec3(0.0, 1.0, 0.0), 1.0, grey())),
+            Box::new(InfinitePlane::new(
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+                grey(),
+            )),
+            Box::new(Sphere::new(Vec3(4.0, 1.0, 0.0), 1.0, grey())),
+        ];
+        let bvh = BVH::new(objs, 0.0, 1.0);
+        let down = |x: f64, z: f64| {
+            let r = Ray::new(Vec3(x, 10.0, z), Vec3(0.0, -1.0, 0.0), 0.0);
+            bvh.hit(&r, 0.001, f64::MAX)
+                .map(|hit| (hit.t, hit.object_id))
+        };
+
+        // Far outside the spheres' bounds, and between them, only the plane is there to hit.
+        assert_eq!(down(1000.0, -500.0), Some((10.0, 2)));
+        assert_eq!(down(2.0, 0.0), Some((10.0, 2)));
+        // Over a sphere, its top is closer than the plane.
+        assert_eq!(down(0.0, 0.0), Some((8.0, 1)));
+        assert_eq!(down(4.0, 0.0), Some((8.0, 3)));
+        // Looking away from the plane, there's nothing.
+        let up = Ray::new(Vec3(1000.0, 10.0, 0.0), Vec3(0.0, 1.0, 0.0), 0.0);
+        assert!(bvh.hit(&up, 0.001, f64::MAX).is_none());
+    }
+
+    /// A material that holds nothing, so it takes no space and any number of them can share an address.
+    struct Dark<const N: usize>;
+
+    impl<const N: usize> Material for Dark<N> {
+        fn scatter(&self, _: &HitRecord, _: &Ray, _: &mut dyn Sampler) -> Option<(Ray, Colour)> {
+            None
+        }
+
+        fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Colour {
+            Colour::new(0.0, 0.0, 0.0)
+        }
+
+        fn albedo(&self, _rec: &HitRecord) -> Colour {
+            Colour::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn zero_sized_materials_get_their_own_ids() {
+        let sphere = |x: f64, material: Box<dyn Material>| -> Box<dyn Hittable> {
+            Box::new(Sphere::new(Vec3(x, 0.0, 0.0), 1.0, material))
+        };
+        let objs = vec![
+            sphere(0.0, Box::new(Dark::<0>)),
+            sphere(3.0, Box::new(Dark::<1>)),
+            sphere(6.0, Box::new(Dark::<0>)),
+        ];
+        assert_eq!(
+            objs[0].materials()[0].identity().0,
+            objs[1].materials()[0].identity().0
+        );
+
+        let bvh = BVH::new(objs, 0.0, 1.0);
+        let material_id = |x: f64| {
+            let r = Ray::new(Vec3(x, 0.0, 10.0), Vec3(0.0, 0.0, -1.0), 0.0);
+            bvh.hit(&r, 0.001, f64::MAX)
+                .map(|hit| bvh.material_id(hit.material))
+        };
+        assert_eq!(material_id(0.0), Some(1));
+        assert_eq!(material_id(3.0), Some(2));
+        assert_eq!(material_id(6.0), Some(1));
+    }
+
+    /// A sphere that doesn't list its material, like an object from another crate written before materials had IDs.
+    struct Unlisted(Sphere<Lambertian<SolidColour>>);
+
+    impl Hittable for Unlisted {
+        fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord<'_>> {
+            self.0.hit(r, t0, t1)
+        }
+
+        fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+            self.0.bounding_box(t0, t1)
+        }
+    }
+
+    #[test]
+    fn unlisted_materials_get_id_zero() {
+        let sphere = |x: f64| {
+            let grey = Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)));
+            Sphere::new(Vec3(x, 0.0, 0.0), 1.0, grey)
+        };
+        let objs: Vec<Box<dyn Hittable>> =
+            vec![Box::new(Unlisted(sphere(0.0))), Box::new(sphere(3.0))];
+        let bvh = BVH::new(objs, 0.0, 1.0);
+        let material_id = |x: f64| {
+            let r = Ray::new(Vec3(x, 0.0, 10.0), Vec3(0.0, 0.0, -1.0), 0.0);
+            bvh.hit(&r, 0.001, f64::MAX)
+                .map(|hit| bvh.material_id(hit.material))
+        };
+        assert_eq!(material_id(0.0), Some(0));
+        assert_eq!(material_id(3.0), Some(1));
+    }
+}
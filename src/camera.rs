@@ -1,7 +1,5 @@
-use rand::distributions::{Distribution, Uniform};
-use rand::rngs::ThreadRng;
-
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
 
 use crate::colour::Colour;
@@ -10,17 +8,8 @@ pub fn deg_to_rad(deg: f64) -> f64 {
     deg / 180.0 * std::f64::consts::PI
 }
 
-pub fn random_in_unit_disk(dist: &Uniform<f64>, rng: &mut ThreadRng) -> Vec3 {
-    loop {
-        let x = (dist.sample(rng) - 0.5) * 2.0;
-        let y = (dist.sample(rng) - 0.5) * 2.0;
-
-        let p = Vec3(x, y, 0.0);
-        if p.mag_sqr() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    Vec3::random_in_unit_disc(sampler)
 }
 
 pub struct Camera {
@@ -32,10 +21,12 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
-    dist: Uniform<f64>,
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bg: Colour,
         look_from: Vec3,
@@ -64,8 +55,6 @@ impl Camera {
 
         let lens_radius = aperture / 2.0;
 
-        let dist = Uniform::from(time0..time1);
-
         Self {
             bg,
             origin,
@@ -75,14 +64,15 @@ impl Camera {
             u,
             v,
             lens_radius,
-            dist,
+            time0,
+            time1,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64, rng: &mut ThreadRng) -> Ray {
-        let rd = random_in_unit_disk(&self.dist, rng) * self.lens_radius;
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = random_in_unit_disk(sampler) * self.lens_radius;
         let offset = self.u * rd.0 + self.v * rd.1;
-        let time = self.dist.sample(rng);
+        let time = self.time0 + sampler.next_1d() * (self.time1 - self.time0);
 
         Ray::new(
             self.origin + offset,
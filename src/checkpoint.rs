@@ -0,0 +1,365 @@
+//! Reading and writing the checkpoints of a ProgressiveRender.
+//!
+//! A checkpoint is a little endian binary file. It starts with the settings the render was made with, so a resumed
+//! render can check they match, followed by the running totals of every pixel of the crop window, row by row from its
+//! top left, and then the film the beauty pass is developed on.
+
+use std::io::{self, Read, Write};
+
+use crate::colour::Colour;
+use crate::film::{CropWindow, FilmPixel, Filter};
+use crate::named::UnknownName;
+use crate::render::{AdaptiveSettings, PixelState, RenderSettings};
+use crate::sampler::SamplerKind;
+
+const MAGIC: &[u8; 8] = b"RTCHKPT\0";
+const VERSION: u32 = 2;
+
+/// Write 'settings', the state of every pixel and the film's pixels to 'out'.
+pub(crate) fn write(
+    settings: &RenderSettings,
+    pixels: &[PixelState],
+    film: &[FilmPixel],
+    out: &mut impl Write,
+) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    write_settings(settings, out)?;
+
+    for p in pixels {
+        write_colour(p.sum, out)?;
+        write_colour(p.sum_sq, out)?;
+        out.write_all(&p.samples.to_le_bytes())?;
+        out.write_all(&[p.converged as u8])?;
+        for &c in &p.aovs {
+            write_colour(c, out)?;
+        }
+    }
+    for p in film {
+        write_colour(p.sum, out)?;
+        out.write_all(&p.weight.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read the pixels and film of a checkpoint, checking that it was made with the same settings as 'settings'. The
+/// number of samples is allowed to have been raised since, unless the render is stratified or adaptive: the strata
+/// and the adaptive budget both depend on it, so changing it part way through would give a different image.
+pub(crate) fn read(
+    settings: &RenderSettings,
+    input: &mut impl Read,
+) -> io::Result<(Vec<PixelState>, Vec<FilmPixel>)> {
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a checkpoint file".to_string()));
+    }
+    let version = read_u32(input)?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "checkpoint version {} is not supported (expected {})",
+            version, VERSION
+        )));
+    }
+
+    let saved = read_settings(input)?;
+    if saved.samples > settings.samples {
+        return Err(mismatch(format!(
+            "the checkpoint already has {} samples per pixel, more than the {} asked for",
+            saved.samples, settings.samples
+        )));
+    }
+    if saved.samples != settings.samples
+        && (settings.sampler == SamplerKind::Stratified || settings.adaptive.is_some())
+    {
+        return Err(mismatch(format!(
+            "the checkpoint was made with {} samples per pixel, and they can't be changed with a {}",
+            saved.samples,
+            match settings.adaptive {
+                Some(_) => "adaptive render",
+                None => "stratified sampler",
+            }
+        )));
+    }
+    if (RenderSettings {
+        samples: settings.samples,
+        ..saved
+    }) != *settings
+    {
+        return Err(mismatch(
+            "the checkpoint was made with different render settings".to_string(),
+        ));
+    }
+
+    let crop = settings.crop_window();
+    let count = (crop.width() * crop.height()) as usize;
+    let mut pixels = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut p = PixelState::new(settings.aovs.len());
+        p.sum = read_colour(input)?;
+        p.sum_sq = read_colour(input)?;
+        p.samples = read_u32(input)?;
+        p.converged = read_u8(input)? != 0;
+        for c in p.aovs.iter_mut() {
+            *c = read_colour(input)?;
+        }
+        pixels.push(p);
+    }
+
+    let mut film = Vec::with_capacity(count);
+    for _ in 0..count {
+        film.push(FilmPixel {
+            sum: read_colour(input)?,
+            weight: read_f64(input)?,
+        });
+    }
+    Ok((pixels, film))
+}
+
+/// Write every setting that changes the image.
+fn write_settings(settings: &RenderSettings, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&settings.width.to_le_bytes())?;
+    out.write_all(&settings.height.to_le_bytes())?;
+    out.write_all(&settings.samples.to_le_bytes())?;
+    out.write_all(&settings.max_depth.to_le_bytes())?;
+    out.write_all(&settings.seed.to_le_bytes())?;
+    write_str(settings.sampler.name(), out)?;
+
+    out.write_all(&(settings.aovs.len() as u32).to_le_bytes())?;
+    for aov in &settings.aovs {
+        write_str(aov.name(), out)?;
+    }
+
+    match settings.adaptive {
+        None => out.write_all(&[0])?,
+        Some(adaptive) => {
+            out.write_all(&[1])?;
+            out.write_all(&adaptive.min_samples.to_le_bytes())?;
+            out.write_all(&adaptive.max_samples.to_le_bytes())?;
+            out.write_all(&adaptive.threshold.to_le_bytes())?;
+        }
+    }
+
+    write_str(settings.filter.kind.name(), out)?;
+    out.write_all(&settings.filter.radius.to_le_bytes())?;
+
+    match settings.crop {
+        None => out.write_all(&[0]),
+        Some(crop) => {
+            out.write_all(&[1])?;
+            for v in [crop.x0, crop.y0, crop.x1, crop.y1] {
+                out.write_all(&v.to_le_bytes())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Read the settings written by 'write_settings'.
+fn read_settings(input: &mut impl Read) -> io::Result<RenderSettings> {
+    let width = read_u32(input)?;
+    let height = read_u32(input)?;
+    let samples = read_u32(input)?;
+    let max_depth = read_u32(input)?;
+    let seed = read_u64(input)?;
+    let sampler = read_str(input)?
+        .parse()
+        .map_err(|e: UnknownName| invalid_data(e.to_string()))?;
+
+    let mut aovs = Vec::new();
+    for _ in 0..read_u32(input)? {
+        let aov = read_str(input)?
+            .parse()
+            .map_err(|e: UnknownName| invalid_data(e.to_string()))?;
+        aovs.push(aov);
+    }
+
+    let adaptive = match read_u8(input)? {
+        0 => None,
+        _ => Some(AdaptiveSettings {
+            min_samples: read_u32(input)?,
+            max_samples: read_u32(input)?,
+            threshold: read_f64(input)?,
+        }),
+    };
+
+    let kind = read_str(input)?
+        .parse()
+        .map_err(|e: UnknownName| invalid_data(e.to_string()))?;
+    let filter = Filter {
+        kind,
+        radius: read_f64(input)?,
+    };
+
+    let crop = match read_u8(input)? {
+        0 => None,
+        _ => Some(CropWindow {
+            x0: read_u32(input)?,
+            y0: read_u32(input)?,
+            x1: read_u32(input)?,
+            y1: read_u32(input)?,
+        }),
+    };
+
+    Ok(RenderSettings {
+        width,
+        height,
+        samples,
+        max_depth,
+        seed,
+        sampler,
+        aovs,
+        adaptive,
+        filter,
+        crop,
+    })
+}
+
+fn write_str(s: &str, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&(s.len() as u32).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+fn write_colour(c: Colour, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&c.r.to_le_bytes())?;
+    out.write_all(&c.g.to_le_bytes())?;
+    out.write_all(&c.b.to_le_bytes())
+}
+
+fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn read_str(input: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(input)?;
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn read_colour(input: &mut impl Read) -> io::Result<Colour> {
+    Ok(Colour::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn mismatch(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::ProgressiveRender;
+    use crate::testing::cornell_box_world;
+
+    fn settings(sampler: SamplerKind, adaptive: Option<AdaptiveSettings>) -> RenderSettings {
+        RenderSettings {
+            width: 12,
+            height: 12,
+            samples: 40,
+            max_depth: 10,
+            seed: 3,
+            sampler,
+            adaptive,
+            ..RenderSettings::default()
+        }
+    }
+
+    fn checkpoint_of(progressive: &ProgressiveRender) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        progressive.write_checkpoint(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resumed_render_matches_one_shot_render() {
+        let (world, camera) = cornell_box_world();
+        let adaptive = AdaptiveSettings {
+            min_samples: 8,
+            max_samples: 64,
+            threshold: 0.05,
+        };
+
+        for settings in [
+            settings(SamplerKind::Stratified, None),
+            settings(SamplerKind::Sobol, None),
+            settings(SamplerKind::Independent, Some(adaptive)),
+        ] {
+            let mut one_shot = ProgressiveRender::new(&world, &camera, &settings);
+            while one_shot.render_pass() {}
+
+            let mut first = ProgressiveRender::new(&world, &camera, &settings);
+            assert!(first.render_pass());
+            let saved = checkpoint_of(&first);
+            let mut resumed =
+                ProgressiveRender::resume(&world, &camera, &settings, &mut saved.as_slice())
+                    .unwrap();
+            while resumed.render_pass() {}
+
+            // The checkpoint holds the sums of every pixel, so equal checkpoints mean a bit for bit equal render.
+            assert_eq!(
+                checkpoint_of(&resumed),
+                checkpoint_of(&one_shot),
+                "{settings:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn samples_can_only_be_raised_when_they_dont_change_the_image() {
+        let (world, camera) = cornell_box_world();
+
+        for (sampler, adaptive, allowed) in [
+            (SamplerKind::Independent, None, true),
+            (SamplerKind::Sobol, None, true),
+            (SamplerKind::Stratified, None, false),
+            (
+                SamplerKind::Independent,
+                Some(AdaptiveSettings::default()),
+                false,
+            ),
+        ] {
+            let settings = settings(sampler, adaptive);
+            let mut progressive = ProgressiveRender::new(&world, &camera, &settings);
+            progressive.render_pass();
+            let saved = checkpoint_of(&progressive);
+
+            let raised = RenderSettings {
+                samples: settings.samples + 16,
+                ..settings.clone()
+            };
+            let result = ProgressiveRender::resume(&world, &camera, &raised, &mut saved.as_slice());
+            assert_eq!(result.is_ok(), allowed, "{sampler} sampler, {adaptive:?}");
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::tonemap::PostProcess;
+
 use std::cmp::PartialEq;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign};
 
@@ -15,18 +17,21 @@ impl Colour {
     pub fn new(r: f64, g: f64, b: f64) -> Self {
         Self { r, g, b }
     }
+
+    /// Return the relative luminance of a linear Colour, using the Rec. 709 weights.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
 }
 
-/// Print Colour as an RGB tuple with each field a U8 between 0 and 255.
+/// Print Colour as an RGB tuple with each field a U8 between 0 and 255. Uses the default PostProcess.
 impl fmt::Display for Colour {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let r = self.r.sqrt();
-        let g = self.g.sqrt();
-        let b = self.b.sqrt();
+        let c = PostProcess::default().apply(*self);
 
-        let ir = std::cmp::min((255.0 * r) as u8, 255);
-        let ig = std::cmp::min((255.0 * g) as u8, 255);
-        let ib = std::cmp::min((255.0 * b) as u8, 255);
+        let ir = (255.999 * c.r) as u8;
+        let ig = (255.999 * c.g) as u8;
+        let ib = (255.999 * c.b) as u8;
 
         write!(f, "{} {} {}", ir, ig, ib)
     }
@@ -0,0 +1,318 @@
+//! Constructive solid geometry: solids made by combining two closed objects as their union, their intersection or
+//! the first with the second cut out of it.
+
+use crate::aabb::AABB;
+
+use crate::hittable::{HitRecord, Hittable, Span};
+use crate::material::Material;
+use crate::ray::Ray;
+
+/// How a Csg combines its two objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Union,        // Inside either object.
+    Intersection, // Inside both objects.
+    Difference,   // Inside the first object but not the second.
+}
+
+impl Operation {
+    fn contains(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            Operation::Union => in_a || in_b,
+            Operation::Intersection => in_a && in_b,
+            Operation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// A solid made from two closed objects, found by walking along each ray through the spans inside each of them.
+///
+/// Every surface of the solid is part of the surface of one of the objects and keeps that object's material and UVs,
+/// so the faces cut by a Difference have the material of the object taken away. Where an object's surface is turned
+/// inside out, like the walls of a hole, the hit's front is flipped to match, so a Dielectric sees rays going in and
+/// out of the solid rather than of the object the surface came from.
+///
+/// Csgs are closed objects themselves, so they can be combined again.
+pub struct Csg<A: Hittable, B: Hittable> {
+    a: A,
+    b: B,
+    operation: Operation,
+}
+
+impl<A: Hittable, B: Hittable> Csg<A, B> {
+    pub fn new(a: A, b: B, operation: Operation) -> Self {
+        Self { a, b, operation }
+    }
+
+    /// The solid inside either 'a' or 'b'.
+    pub fn union(a: A, b: B) -> Self {
+        Self::new(a, b, Operation::Union)
+    }
+
+    /// The solid inside both 'a' and 'b'.
+    pub fn intersection(a: A, b: B) -> Self {
+        Self::new(a, b, Operation::Intersection)
+    }
+
+    /// The solid left when 'b' is cut out of 'a'.
+    pub fn difference(a: A, b: B) -> Self {
+        Self::new(a, b, Operation::Difference)
+    }
+}
+
+impl<A: Hittable, B: Hittable> Hittable for Csg<A, B> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.spans(r, t_min, t_max)
+            .into_iter()
+            .find_map(|span| span.enter.or(span.exit))
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        let a = self.a.bounding_box(t0, t1);
+        let b = self.b.bounding_box(t0, t1);
+        match self.operation {
+            Operation::Union => Some(a?.merge(b?)),
+            Operation::Intersection => match (a, b) {
+                (Some(a), Some(b)) => Some(a.overlap(b)),
+                (bb, None) | (None, bb) => bb,
+            },
+            Operation::Difference => a,
+        }
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        let mut materials = self.a.materials();
+        materials.extend(self.b.materials());
+        materials
+    }
+
+    fn spans(&self, r: &Ray, t0: f64, t1: f64) -> Vec<Span<'_>> {
+        // Whether the ray starts inside an object is only known from the first surface it crosses, which may be past
+        // 't1', so the objects are walked along the whole ray and the result cut short afterwards.
+        let a = self.a.spans(r, t0, f64::MAX);
+        let b = self.b.spans(r, t0, f64::MAX);
+        let starts_inside = |spans: &[Span]| spans.first().is_some_and(|s| s.enter.is_none());
+        let (mut in_a, mut in_b) = (starts_inside(&a), starts_inside(&b));
+
+        // Each crossing is the object it's on, whether it goes in, and its hit.
+        let mut crossings: Vec<(bool, bool, HitRecord)> = Vec::new();
+        for (on_a, spans) in [(true, &a), (false, &b)] {
+            for span in spans {
+                crossings.extend(span.enter.map(|rec| (on_a, true, rec)));
+                crossings.extend(span.exit.map(|rec| (on_a, false, rec)));
+            }
+        }
+        crossings.sort_unstable_by(|(_, _, x), (_, _, y)| x.t.total_cmp(&y.t));
+
+        let mut inside = self.operation.contains(in_a, in_b);
+        let mut entered = if inside { Some(None) } else { None };
+        let mut spans = Vec::new();
+
+        for (on_a, going_in, mut rec) in crossings {
+            if rec.t >= t1 {
+                break;
+            }
+            match on_a {
+                true => in_a = going_in,
+                false => in_b = going_in,
+            }
+            if self.operation.contains(in_a, in_b) == inside {
+                continue;
+            }
+
+            inside = !inside;
+            rec.front_face = inside;
+            match entered.take() {
+                None => entered = Some(Some(rec)),
+                Some(enter) => spans.push(Span {
+                    enter,
+                    exit: Some(rec),
+                }),
+            }
+        }
+
+        if let Some(enter) = entered {
+            spans.push(Span { enter, exit: None });
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::cuboid::Cuboid;
+    use crate::material::Metal;
+    use crate::sphere::Sphere;
+    use crate::vec::Vec3;
+
+    const RED: Colour = Colour {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+    };
+    const BLUE: Colour = Colour {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+    };
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray::new(origin, direction, 0.0)
+    }
+
+    /// Check a hit's distance, front, normal and, by its colour, which object's material it has.
+    fn check(rec: Option<HitRecord>, t: f64, front_face: bool, normal: Vec3, colour: Colour) {
+        let rec = rec.expect("expected a hit");
+        assert!((rec.t - t).abs() < 1e-9, "t is {}, expected {}", rec.t, t);
+        assert_eq!(rec.front_face, front_face);
+        assert!(
+            (rec.normal - normal).mag() < 1e-9,
+            "normal is {:?}, expected {:?}",
+            rec.normal,
+            normal
+        );
+        assert_eq!(rec.material.albedo(&rec), colour);
+    }
+
+    /// A red unit sphere at the origin with everything where x > 0 cut away by a blue box, leaving a hemisphere with
+    /// its flat face at x = 0.
+    fn hemisphere() -> impl Hittable {
+        Csg::difference(
+            Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, Metal::new(RED, 0.0)),
+            Cuboid::new(
+                Vec3(0.0, -2.0, -2.0),
+                Vec3(2.0, 2.0, 2.0),
+                Metal::new(BLUE, 0.0),
+            ),
+        )
+    }
+
+    /// The lens inside both a red unit sphere at x = -0.5 and a blue one at x = 0.5, reaching from x = -0.5 to 0.5.
+    fn lens() -> impl Hittable {
+        Csg::intersection(
+            Sphere::new(Vec3(-0.5, 0.0, 0.0), 1.0, Metal::new(RED, 0.0)),
+            Sphere::new(Vec3(0.5, 0.0, 0.0), 1.0, Metal::new(BLUE, 0.0)),
+        )
+    }
+
+    #[test]
+    fn difference_from_outside() {
+        let solid = hemisphere();
+        let r = ray(Vec3(-3.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let spans = solid.spans(&r, 0.0, f64::MAX);
+
+        assert_eq!(spans.len(), 1);
+        check(spans[0].enter, 2.0, true, Vec3(-1.0, 0.0, 0.0), RED);
+        // The ray leaves through the face cut by the box, which has the box's material.
+        check(spans[0].exit, 3.0, false, Vec3(-1.0, 0.0, 0.0), BLUE);
+        check(
+            solid.hit(&r, 0.0, f64::MAX),
+            2.0,
+            true,
+            Vec3(-1.0, 0.0, 0.0),
+            RED,
+        );
+    }
+
+    #[test]
+    fn difference_from_inside() {
+        let solid = hemisphere();
+        let r = ray(Vec3(-0.5, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let spans = solid.spans(&r, 0.0, f64::MAX);
+
+        assert_eq!(spans.len(), 1);
+        assert!(spans[0].enter.is_none());
+        check(spans[0].exit, 0.5, false, Vec3(-1.0, 0.0, 0.0), BLUE);
+        check(
+            solid.hit(&r, 0.0, f64::MAX),
+            0.5,
+            false,
+            Vec3(-1.0, 0.0, 0.0),
+            BLUE,
+        );
+    }
+
+    #[test]
+    fn difference_from_removed_region() {
+        let solid = hemisphere();
+
+        // Inside both the sphere and the box, so outside the solid until the cut face.
+        let r = ray(Vec3(0.5, 0.0, 0.0), Vec3(-1.0, 0.0, 0.0));
+        let spans = solid.spans(&r, 0.0, f64::MAX);
+        assert_eq!(spans.len(), 1);
+        check(spans[0].enter, 0.5, true, Vec3(1.0, 0.0, 0.0), BLUE);
+        check(spans[0].exit, 1.5, false, Vec3(1.0, 0.0, 0.0), RED);
+
+        // Leaving the sphere while still in the box never reaches the solid.
+        let r = ray(Vec3(0.5, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(solid.spans(&r, 0.0, f64::MAX).is_empty());
+        assert!(solid.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn intersection_from_outside() {
+        let solid = lens();
+        let r = ray(Vec3(-3.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let spans = solid.spans(&r, 0.0, f64::MAX);
+
+        // The near side of the lens is the far sphere's surface, and the far side the near sphere's.
+        assert_eq!(spans.len(), 1);
+        check(spans[0].enter, 2.5, true, Vec3(-1.0, 0.0, 0.0), BLUE);
+        check(spans[0].exit, 3.5, false, Vec3(-1.0, 0.0, 0.0), RED);
+        check(
+            solid.hit(&r, 0.0, f64::MAX),
+            2.5,
+            true,
+            Vec3(-1.0, 0.0, 0.0),
+            BLUE,
+        );
+    }
+
+    #[test]
+    fn intersection_from_inside() {
+        let solid = lens();
+        let r = ray(Vec3(0.0, 0.0, 0.0), Vec3(-1.0, 0.0, 0.0));
+        let spans = solid.spans(&r, 0.0, f64::MAX);
+
+        assert_eq!(spans.len(), 1);
+        assert!(spans[0].enter.is_none());
+        check(spans[0].exit, 0.5, false, Vec3(1.0, 0.0, 0.0), BLUE);
+    }
+
+    #[test]
+    fn intersection_outside_overlap() {
+        // This passes through both spheres, but not through both at once.
+        let r = ray(Vec3(-3.0, 0.95, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(lens().hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn intersection_bounding_box() {
+        let bb = lens().bounding_box(0.0, 1.0).unwrap();
+        assert!((bb.min - Vec3(-0.5, -1.0, -1.0)).mag() < 1e-9);
+        assert!((bb.max - Vec3(0.5, 1.0, 1.0)).mag() < 1e-9);
+    }
+
+    #[test]
+    fn intersection_bounding_box_without_overlap() {
+        let solid = Csg::intersection(
+            Sphere::new(Vec3(-3.0, 0.0, 0.0), 1.0, Metal::new(RED, 0.0)),
+            Sphere::new(Vec3(3.0, 0.0, 0.0), 1.0, Metal::new(BLUE, 0.0)),
+        );
+        let bb = solid.bounding_box(0.0, 1.0).unwrap();
+
+        // The box is empty rather than turned inside out, so nothing can hit it.
+        assert!((bb.max - bb.min).mag() == 0.0);
+        for origin in [
+            Vec3(-5.0, 0.0, 0.0),
+            Vec3(-5.0, 0.5, 0.0),
+            Vec3(0.0, -5.0, 0.0),
+        ] {
+            let direction = (bb.min - origin).normalise();
+            assert!(!bb.hit(&ray(origin, direction), 0.0, f64::MAX));
+            assert!(solid.hit(&ray(origin, direction), 0.0, f64::MAX).is_none());
+        }
+    }
+}
@@ -1,7 +1,9 @@
+use std::sync::Arc;
+
 use crate::material::Material;
 
-use crate::{Hittable, HitRecord, HittableList};
-use crate::rect::{XYRect, XZRect, YZRect};
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::planar::Quad;
 
 use crate::aabb::AABB;
 
@@ -17,27 +19,38 @@ pub struct Cuboid {
 
 impl Cuboid {
     #[allow(dead_code)]
-    pub fn new<M: Material + Clone + 'static>(p0: Vec3, p1: Vec3, mat: M) -> Self {
+    pub fn new<M: Material + 'static>(p0: Vec3, p1: Vec3, mat: M) -> Self {
+        // The sides share one material rather than having a copy each.
+        let mat = Arc::new(mat);
         let box_min = p0;
         let box_max = p1;
 
         let mut sides = HittableList::new();
+        let (dx, dy, dz) = (
+            Vec3(p1[X] - p0[X], 0.0, 0.0),
+            Vec3(0.0, p1[Y] - p0[Y], 0.0),
+            Vec3(0.0, 0.0, p1[Z] - p0[Z]),
+        );
 
-        sides.push(Box::new(XYRect::new(p0[X], p1[X], p0[Y], p1[Y], p1[Z], mat.clone())));
-        sides.push(Box::new(XYRect::new(p0[X], p1[X], p0[Y], p1[Y], p0[Z], mat.clone())));
-
-        sides.push(Box::new(XZRect::new(p0[X], p1[X], p0[Z], p1[Z], p1[Y], mat.clone())));
-        sides.push(Box::new(XZRect::new(p0[X], p1[X], p0[Z], p1[Z], p0[Y], mat.clone())));
-
-        sides.push(Box::new(YZRect::new(p0[Y], p1[Y], p0[Z], p1[Z], p1[X], mat.clone())));
-        sides.push(Box::new(YZRect::new(p0[Y], p1[Y], p0[Z], p1[Z], p0[X], mat.clone())));
+        // Each side's edges turn anticlockwise seen from outside, so its front faces out of the box.
+        let faces = [
+            (Vec3(p0[X], p0[Y], p1[Z]), dx, dy),  // front
+            (Vec3(p1[X], p0[Y], p1[Z]), -dz, dy), // right
+            (Vec3(p1[X], p0[Y], p0[Z]), -dx, dy), // back
+            (Vec3(p0[X], p0[Y], p0[Z]), dz, dy),  // left
+            (Vec3(p0[X], p1[Y], p1[Z]), dx, -dz), // top
+            (Vec3(p0[X], p0[Y], p0[Z]), dx, dz),  // bottom
+        ];
+        for (origin, u, v) in faces {
+            sides.push(Box::new(Quad::new(origin, u, v, mat.clone())));
+        }
 
         Self { box_min, box_max, sides }
     }
 }
 
 impl Hittable for Cuboid {
-    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord<'_>> {
         self.sides.hit(r, t0, t1)
     }
 
@@ -45,4 +58,8 @@ impl Hittable for Cuboid {
         Some(AABB { min: self.box_min, max: self.box_max })
     }
 
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.sides.materials()
+    }
+
 }
@@ -0,0 +1,242 @@
+use rayon::prelude::*;
+
+use crate::colour::Colour;
+use crate::image::Image;
+
+/// Settings for the edge-avoiding À-Trous wavelet denoiser.
+#[derive(Copy, Clone, Debug)]
+pub struct DenoiseSettings {
+    pub iterations: u32, // Number of wavelet passes. Each pass doubles the gap between the filter taps.
+    pub sigma_colour: f64, // Luminance differences larger than this many standard deviations of noise stop the blur.
+    pub sigma_normal: f64, // Exponent applied to the cosine between two normals. Higher keeps edges sharper.
+    pub sigma_albedo: f64, // Albedo differences larger than this stop the blur.
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_colour: 4.0,
+            sigma_normal: 128.0,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+/// The B3 spline kernel used by each À-Trous pass.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Denoise a rendered image with an edge-avoiding À-Trous wavelet filter, as in "Edge-Avoiding À-Trous Wavelet
+/// Transform for fast Global Illumination Filtering" by Dammertz et al. and its variance guided extension, SVGF.
+///
+/// The image is divided by the albedo so that only the lighting is blurred, then filtered with a sparse 5x5 kernel
+/// whose taps spread further apart with every pass. Each tap is weighted by how similar its luminance, normal and
+/// albedo are to the centre pixel's. Luminance is compared relative to the estimated noise in 'variance', so noisy
+/// pixels are blurred more than converged ones.
+///
+/// Panics if the images aren't all the same size.
+pub fn denoise(
+    beauty: &Image,
+    variance: &Image,
+    albedo: &Image,
+    normal: &Image,
+    settings: &DenoiseSettings,
+) -> Image {
+    for image in [variance, albedo, normal] {
+        assert_eq!(
+            (image.width, image.height),
+            (beauty.width, beauty.height),
+            "denoiser inputs must be the same size"
+        );
+    }
+
+    // Pixels without an albedo, like the background, are filtered as they are.
+    let demodulate = |a: f64| if a > 1e-3 { a } else { 1.0 };
+    let albedo_safe: Vec<Colour> = albedo
+        .pixels
+        .iter()
+        .map(|a| Colour::new(demodulate(a.r), demodulate(a.g), demodulate(a.b)))
+        .collect();
+
+    let mut colour: Vec<Colour> = beauty
+        .pixels
+        .iter()
+        .zip(&albedo_safe)
+        .map(|(c, a)| Colour::new(c.r / a.r, c.g / a.g, c.b / a.b))
+        .collect();
+    let mut var: Vec<f64> = variance
+        .pixels
+        .iter()
+        .zip(&albedo_safe)
+        .map(|(v, a)| {
+            Colour::new(v.r / (a.r * a.r), v.g / (a.g * a.g), v.b / (a.b * a.b)).luminance()
+        })
+        .collect();
+
+    let filter = Filter {
+        width: beauty.width as i64,
+        height: beauty.height as i64,
+        albedo: &albedo.pixels,
+        normal: &normal.pixels,
+        settings,
+    };
+    for pass in 0..settings.iterations {
+        let (c, v) = filter.pass(&colour, &var, 1 << pass);
+        colour = c;
+        var = v;
+    }
+
+    Image {
+        width: beauty.width,
+        height: beauty.height,
+        pixels: colour
+            .iter()
+            .zip(&albedo_safe)
+            .map(|(c, a)| *c * *a)
+            .collect(),
+    }
+}
+
+/// The feature buffers shared by every pass of the filter.
+struct Filter<'a> {
+    width: i64,
+    height: i64,
+    albedo: &'a [Colour],
+    normal: &'a [Colour],
+    settings: &'a DenoiseSettings,
+}
+
+impl Filter<'_> {
+    /// Run one À-Trous pass with taps 'step' pixels apart. Returns the filtered colours and their variance.
+    fn pass(&self, colour: &[Colour], var: &[f64], step: i64) -> (Vec<Colour>, Vec<f64>) {
+        let blurred_var = self.blur_variance(var);
+
+        (0..self.height)
+            .into_par_iter()
+            .flat_map(|y| {
+                (0..self.width)
+                    .map(|x| self.filter_pixel(colour, var, &blurred_var, x, y, step))
+                    .collect::<Vec<(Colour, f64)>>()
+            })
+            .unzip()
+    }
+
+    /// Return the filtered colour and variance of the pixel at (x, y).
+    fn filter_pixel(
+        &self,
+        colour: &[Colour],
+        var: &[f64],
+        blurred_var: &[f64],
+        x: i64,
+        y: i64,
+        step: i64,
+    ) -> (Colour, f64) {
+        let p = (y * self.width + x) as usize;
+        let lum_p = colour[p].luminance();
+        let colour_scale = self.settings.sigma_colour * blurred_var[p].sqrt() + 1e-10;
+
+        let mut sum = Colour::new(0.0, 0.0, 0.0);
+        let mut sum_var = 0.0;
+        let mut total = 0.0;
+
+        for (dy, ky) in KERNEL.iter().enumerate() {
+            for (dx, kx) in KERNEL.iter().enumerate() {
+                let qx = x + (dx as i64 - 2) * step;
+                let qy = y + (dy as i64 - 2) * step;
+                if qx < 0 || qy < 0 || qx >= self.width || qy >= self.height {
+                    continue;
+                }
+                let q = (qy * self.width + qx) as usize;
+
+                let w_colour = (-(lum_p - colour[q].luminance()).abs() / colour_scale).exp();
+                let w_normal = self.normal_weight(self.normal[p], self.normal[q]);
+                let w_albedo = self.albedo_weight(self.albedo[p], self.albedo[q]);
+
+                let w = kx * ky * w_colour * w_normal * w_albedo;
+                sum += colour[q] * w;
+                sum_var += w * w * var[q];
+                total += w;
+            }
+        }
+
+        // The centre tap always has a weight of 3/8 * 3/8, so 'total' is never zero.
+        (sum / total, sum_var / (total * total))
+    }
+
+    /// Return how similar two normals are. Pixels that missed everything have a zero normal and only match each other.
+    fn normal_weight(&self, np: Colour, nq: Colour) -> f64 {
+        if np == nq {
+            return 1.0;
+        }
+        let cos = np.r * nq.r + np.g * nq.g + np.b * nq.b;
+        cos.max(0.0).powf(self.settings.sigma_normal)
+    }
+
+    /// Return how similar two albedos are, from the squared distance between them.
+    fn albedo_weight(&self, ap: Colour, aq: Colour) -> f64 {
+        let d2 = (ap.r - aq.r).powi(2) + (ap.g - aq.g).powi(2) + (ap.b - aq.b).powi(2);
+        (-d2 / (self.settings.sigma_albedo * self.settings.sigma_albedo)).exp()
+    }
+
+    /// Blur the variance with a 3x3 Gaussian so a single unlucky sample doesn't stop the filter.
+    fn blur_variance(&self, var: &[f64]) -> Vec<f64> {
+        const GAUSSIAN: [f64; 3] = [0.25, 0.5, 0.25];
+
+        (0..self.height)
+            .into_par_iter()
+            .flat_map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let mut sum = 0.0;
+                        let mut total = 0.0;
+                        for (dy, ky) in GAUSSIAN.iter().enumerate() {
+                            for (dx, kx) in GAUSSIAN.iter().enumerate() {
+                                let qx = x + dx as i64 - 1;
+                                let qy = y + dy as i64 - 1;
+                                if qx < 0 || qy < 0 || qx >= self.width || qy >= self.height {
+                                    continue;
+                                }
+                                sum += kx * ky * var[(qy * self.width + qx) as usize];
+                                total += kx * ky;
+                            }
+                        }
+                        sum / total
+                    })
+                    .collect::<Vec<f64>>()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::Aov;
+    use crate::render::{render_with_aovs, RenderSettings};
+    use crate::testing::{cornell_box_world, mse, small_render};
+
+    #[test]
+    fn denoised_cornell_box_is_closer_to_reference() {
+        let (world, camera) = cornell_box_world();
+        let settings = |samples, seed| RenderSettings {
+            aovs: vec![Aov::Albedo, Aov::Normal],
+            ..small_render(samples, seed)
+        };
+
+        let reference = render_with_aovs(&world, &camera, &settings(256, 1)).beauty;
+        let noisy = render_with_aovs(&world, &camera, &settings(8, 2));
+        let denoised = denoise(
+            &noisy.beauty,
+            &noisy.variance,
+            noisy.aov(Aov::Albedo).unwrap(),
+            noisy.aov(Aov::Normal).unwrap(),
+            &DenoiseSettings::default(),
+        );
+
+        let (before, after) = (mse(&noisy.beauty, &reference), mse(&denoised, &reference));
+        assert!(
+            after < before,
+            "MSE went from {before} to {after} when denoising"
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::f64::consts::PI;
+use std::sync::Mutex;
+
+use crate::colour::Colour;
+use crate::image::Image;
+use crate::named::named_enum;
+use crate::tile::Tile;
+
+/// The shapes of reconstruction filter a Film can weight samples with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    Box,      // Every sample within the radius counts equally.
+    Tent,     // Weight falls linearly to zero at the radius.
+    Gaussian, // A Gaussian, shifted down so it reaches zero at the radius.
+    Mitchell, // The Mitchell-Netravali cubic with B = C = 1/3. Sharper than a Gaussian, with slight ringing.
+    Lanczos,  // A sinc windowed by a wider sinc. The sharpest, with the most ringing.
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    /// Return the radius, in pixels, the filter is usually used with.
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+named_enum!(FilterKind, "filter");
+
+/// A reconstruction filter, which decides how much a sample counts towards each pixel around it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64, // Distance in pixels, along each axis, beyond which samples have no weight.
+}
+
+impl Filter {
+    /// Create a filter of the given kind with its usual radius.
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    /// Return how many pixels beyond its own a sample can reach.
+    pub fn reach(&self) -> u32 {
+        (self.radius - 0.5).max(0.0).ceil() as u32
+    }
+
+    /// Return the weight of a sample offset by (x, y) pixels from the centre of a pixel.
+    pub fn eval(&self, x: f64, y: f64) -> f64 {
+        self.eval_1d(x) * self.eval_1d(y)
+    }
+
+    /// The filters are separable, so the weight is the product of this along each axis.
+    fn eval_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        match self.kind {
+            // Half open, so a sample on the edge between two pixels only counts towards one of them.
+            FilterKind::Box => (-r < x && x <= r) as u8 as f64,
+            FilterKind::Tent => (r - x.abs()).max(0.0),
+            FilterKind::Gaussian => {
+                const ALPHA: f64 = 2.0;
+                ((-ALPHA * x * x).exp() - (-ALPHA * r * r).exp()).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => match x.abs() < r {
+                true => sinc(x) * sinc(x / r),
+                false => 0.0,
+            },
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::Box)
+    }
+}
+
+/// The Mitchell-Netravali cubic with B = C = 1/3, which is zero beyond |x| = 2.
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let x = x.abs();
+    let y = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    } else if x < 2.0 {
+        (-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        0.0
+    };
+    y / 6.0
+}
+
+/// The normalised sinc function, sin(pi x) / (pi x).
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+/// A rectangle of the image to render instead of the whole thing. Rows count from the top of the image and 'x1' and
+/// 'y1' are exclusive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CropWindow {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl CropWindow {
+    /// A window covering a whole 'width' by 'height' image.
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1.saturating_sub(self.y0)
+    }
+
+    /// Return the part of the window that lies inside a 'width' by 'height' image.
+    pub fn clamp(&self, width: u32, height: u32) -> Self {
+        Self {
+            x0: self.x0.min(width),
+            y0: self.y0.min(height),
+            x1: self.x1.min(width),
+            y1: self.y1.min(height),
+        }
+    }
+}
+
+/// Total weight below which a pixel of a Film is treated as having no samples.
+const MIN_WEIGHT: f64 = 1e-6;
+
+/// The weighted total of the samples splatted into one pixel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FilmPixel {
+    pub sum: Colour,
+    pub weight: f64,
+}
+
+impl FilmPixel {
+    fn zero() -> Self {
+        Self {
+            sum: Colour::new(0.0, 0.0, 0.0),
+            weight: 0.0,
+        }
+    }
+}
+
+/// A Film reconstructs an image from samples at arbitrary positions, weighting each by a Filter centred on it and
+/// adding it to every pixel within the filter's radius. Each pixel is the weighted average of the samples around it.
+///
+/// Tiles are rendered into FilmTiles, which cover the tile plus the filter's radius, and then merged into the Film.
+/// Merging locks the film, so tiles may be merged from any thread. Neighbouring tiles overlap, so merge them in a
+/// fixed order if the result must be the same on every run.
+#[derive(Debug)]
+pub struct Film {
+    pub width: u32, // Size of the full image, even if only a crop window is being rendered.
+    pub height: u32,
+    pub crop: CropWindow, // Pixels of the full image covered by the film.
+    pub filter: Filter,
+    pixels: Mutex<Vec<FilmPixel>>,
+}
+
+impl Film {
+    /// Create an empty film for a 'width' by 'height' image, covering 'crop', or the whole image if there isn't one.
+    pub fn new(width: u32, height: u32, filter: Filter, crop: Option<CropWindow>) -> Self {
+        let crop = crop
+            .unwrap_or_else(|| CropWindow::full(width, height))
+            .clamp(width, height);
+        let pixels = vec![FilmPixel::zero(); (crop.width() * crop.height()) as usize];
+        Self {
+            width,
+            height,
+            crop,
+            filter,
+            pixels: Mutex::new(pixels),
+        }
+    }
+
+    /// Replace the film's pixels with 'pixels', row by row from the top left of the crop window.
+    ///
+    /// Panics if there are the wrong number of pixels.
+    pub fn set_pixels(&mut self, pixels: Vec<FilmPixel>) {
+        let crop = self.crop;
+        assert_eq!(pixels.len(), (crop.width() * crop.height()) as usize);
+        self.pixels = Mutex::new(pixels);
+    }
+
+    /// Return a copy of the film's pixels, row by row from the top left of the crop window.
+    pub fn pixels(&self) -> Vec<FilmPixel> {
+        self.pixels.lock().unwrap().clone()
+    }
+
+    /// Create an empty FilmTile for samples taken in 'tile', which is in full image coordinates.
+    pub fn tile(&self, tile: &Tile) -> FilmTile {
+        // Samples in the tile reach pixels up to the filter's radius outside it.
+        let reach = self.filter.reach();
+        let bounds = Tile {
+            x0: tile.x0.saturating_sub(reach).max(self.crop.x0),
+            y0: tile.y0.saturating_sub(reach).max(self.crop.y0),
+            x1: (tile.x1 + reach).min(self.crop.x1),
+            y1: (tile.y1 + reach).min(self.crop.y1),
+        };
+        FilmTile {
+            bounds,
+            height: self.height,
+            filter: self.filter,
+            pixels: vec![FilmPixel::zero(); (bounds.width() * bounds.height()) as usize],
+        }
+    }
+
+    /// Add the samples in 'tile' to the film.
+    pub fn merge_tile(&self, tile: &FilmTile) {
+        let mut pixels = self.pixels.lock().unwrap();
+        let width = self.crop.width() as usize;
+        let b = tile.bounds;
+
+        for (y, row) in (b.y0..b.y1).zip(tile.pixels.chunks(b.width() as usize)) {
+            let start = (y - self.crop.y0) as usize * width + (b.x0 - self.crop.x0) as usize;
+            for (p, t) in pixels[start..start + row.len()].iter_mut().zip(row) {
+                p.sum += t.sum;
+                p.weight += t.weight;
+            }
+        }
+    }
+
+    /// Return the image developed so far: the weighted average of the samples around each pixel of the crop window.
+    ///
+    /// Filters with negative lobes can leave a pixel with a total weight near or below zero, and dividing by that
+    /// would blow its colour up, so those pixels are black like ones with no samples.
+    pub fn image(&self) -> Image {
+        let pixels = self.pixels.lock().unwrap();
+        Image {
+            width: self.crop.width(),
+            height: self.crop.height(),
+            pixels: pixels
+                .iter()
+                .map(|p| match p.weight > MIN_WEIGHT {
+                    true => p.sum / p.weight,
+                    false => Colour::new(0.0, 0.0, 0.0),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The samples of one tile, waiting to be merged into a Film.
+#[derive(Clone, Debug)]
+pub struct FilmTile {
+    bounds: Tile, // Pixels the tile's samples can reach, in full image coordinates.
+    height: u32,  // Height of the full image.
+    filter: Filter,
+    pixels: Vec<FilmPixel>,
+}
+
+impl FilmTile {
+    /// Splat a sample into the pixels around it. Like the camera's (u, v), the position (x, y) is measured in pixels
+    /// from the bottom left of the full image, so the pixel in column i and row j from the bottom covers
+    /// [i, i + 1) x [j, j + 1).
+    pub fn add_sample(&mut self, x: f64, y: f64, c: Colour) {
+        let r = self.filter.radius;
+        let b = self.bounds;
+
+        // Columns, and rows counted from the bottom, whose centres are within the radius of the sample.
+        let x0 = (x - 0.5 - r).ceil().max(b.x0 as f64) as u32;
+        let x1 = ((x - 0.5 + r).floor() + 1.0).clamp(0.0, b.x1 as f64) as u32;
+        let j0 = (y - 0.5 - r).ceil().max((self.height - b.y1) as f64) as u32;
+        let j1 = ((y - 0.5 + r).floor() + 1.0).clamp(0.0, (self.height - b.y0) as f64) as u32;
+
+        for j in j0..j1 {
+            let py = self.height - 1 - j;
+            for px in x0..x1 {
+                let w = self.filter.eval(px as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if w == 0.0 {
+                    continue;
+                }
+                let p = &mut self.pixels[((py - b.y0) * b.width() + (px - b.x0)) as usize];
+                p.sum += c * w;
+                p.weight += w;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render, RenderSettings};
+    use crate::testing::{cornell_box_world, small_render};
+
+    #[test]
+    fn constant_image_stays_constant_under_every_filter() {
+        let c = Colour::new(0.2, 0.5, 0.9);
+        let (width, height) = (8, 6);
+        for kind in FilterKind::ALL {
+            let film = Film::new(width, height, Filter::new(kind), None);
+            let mut tile = film.tile(&Tile {
+                x0: 0,
+                y0: 0,
+                x1: width,
+                y1: height,
+            });
+
+            // A 4x4 grid of samples in every pixel.
+            for y in 0..4 * height {
+                for x in 0..4 * width {
+                    tile.add_sample((x as f64 + 0.5) / 4.0, (y as f64 + 0.5) / 4.0, c);
+                }
+            }
+            film.merge_tile(&tile);
+
+            for (k, p) in film.image().pixels.iter().enumerate() {
+                let error = (p.r - c.r).abs() + (p.g - c.g).abs() + (p.b - c.b).abs();
+                assert!(error < 1e-9, "{kind} filter made pixel {k} {p:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn pixels_without_enough_weight_are_black() {
+        // The negative lobes of a Lanczos or Mitchell filter can leave a pixel with a weight that's tiny or below zero.
+        let mut film = Film::new(3, 1, Filter::new(FilterKind::Lanczos), None);
+        let pixel = |c: f64, weight: f64| FilmPixel {
+            sum: Colour::new(c, c, c) * weight,
+            weight,
+        };
+        film.set_pixels(vec![pixel(0.5, 2.0), pixel(0.5, 1e-12), pixel(0.5, -0.1)]);
+        assert_eq!(
+            film.image().pixels,
+            vec![
+                Colour::new(0.5, 0.5, 0.5),
+                Colour::new(0.0, 0.0, 0.0),
+                Colour::new(0.0, 0.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn crop_matches_the_same_part_of_the_whole_image() {
+        let (world, camera) = cornell_box_world();
+        for kind in [FilterKind::Box, FilterKind::Mitchell, FilterKind::Lanczos] {
+            let settings = RenderSettings {
+                filter: Filter::new(kind),
+                ..small_render(4, 1)
+            };
+            let crop = CropWindow {
+                x0: 5,
+                y0: 7,
+                x1: 17,
+                y1: 15,
+            };
+            let whole = render(&world, &camera, &settings);
+            let part = render(
+                &world,
+                &camera,
+                &RenderSettings {
+                    crop: Some(crop),
+                    ..settings
+                },
+            );
+
+            assert_eq!((part.width, part.height), (crop.width(), crop.height()));
+            for y in 0..crop.height() {
+                for x in 0..crop.width() {
+                    let (a, b) = (part.get(x, y), whole.get(x + crop.x0, y + crop.y0));
+                    let error = (a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs();
+                    assert!(
+                        error < 1e-9,
+                        "{kind} filter: ({x}, {y}) is {a:?}, not {b:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Loading glTF 2.0 scenes, either as '.gltf' files with their buffers and images embedded or alongside them, or as
+//! binary '.glb' files.
+//!
+//! Each primitive of a mesh becomes a TriangleMesh, built once and placed by an Instance at every node that uses the
+//! mesh, and the scene's first perspective camera becomes the Camera. Metallic-roughness materials are mapped
+//! onto the renderer's own materials:
+//!
+//! - An emissive colour makes a DiffuseLight, scaled by 'KHR_materials_emissive_strength' if it's there.
+//! - Any transmission ('KHR_materials_transmission') or blended alpha below 1 makes a Dielectric with the index of
+//!   refraction from 'KHR_materials_ior', or 1.5 if there isn't one.
+//! - A metallic factor of at least 0.5 makes a Metal of the base colour, as fuzzy as the material is rough.
+//! - Anything else is Lambertian, textured with the base colour texture or the vertex colours if it has them.
+//!
+//! Textures other than the base colour texture are ignored, as are points, lines and orthographic cameras.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ::gltf::image::Format;
+use ::gltf::material::AlphaMode;
+use ::gltf::mesh::Mode;
+use ::gltf::{buffer, camera::Projection, image, Document, Mesh, Node, Primitive};
+
+use crate::aabb::AABB;
+use crate::camera::Camera;
+use crate::colour::Colour;
+use crate::hittable::{Hittable, HittableList};
+use crate::image::Image;
+use crate::instance::Instance;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::mesh::TriangleMesh;
+use crate::texture::{ImageTexture, SolidColour, VertexColourTexture};
+use crate::tonemap::Transfer;
+use crate::transform::Transform;
+use crate::vec::Vec3;
+
+/// Vertical field of view of the camera made for scenes that don't have one, in degrees.
+const DEFAULT_FOV: f64 = 40.0;
+
+/// Error returned when a glTF file can't be read or doesn't make sense.
+#[derive(Clone, Debug)]
+pub struct GltfError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+/// Load the default scene of the glTF file at 'path', or its first scene if it doesn't name a default. Buffers and
+/// images in other files are looked for relative to it.
+///
+/// The camera is given the image's aspect ratio rather than the one in the file. Scenes without a perspective camera
+/// are viewed from along +Z, framed to fit everything in.
+pub fn load_gltf(path: &Path, aspect_ratio: f64) -> Result<(HittableList, Camera), GltfError> {
+    let error = |message: String| GltfError {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let (document, buffers, images) = ::gltf::import(path).map_err(|e| error(e.to_string()))?;
+    build_scene(&document, &buffers, &images, aspect_ratio).map_err(error)
+}
+
+/// Build the world and camera of a glTF document whose buffers and images have been loaded.
+fn build_scene(
+    document: &Document,
+    buffers: &[buffer::Data],
+    images: &[image::Data],
+    aspect_ratio: f64,
+) -> Result<(HittableList, Camera), String> {
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or("there are no scenes")?;
+
+    let mut importer = Importer {
+        buffers,
+        images,
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        world: HittableList::new(),
+        bounds: None,
+        camera: None,
+    };
+    for node in scene.nodes() {
+        importer.node(&node, &Transform::identity())?;
+    }
+
+    let bounds = importer.bounds.ok_or("the scene has no triangles")?;
+    let camera = match importer.camera {
+        Some((m, yfov)) => Camera::new(
+            Colour::new(0.7, 0.8, 1.0),
+            m.point(Vec3(0.0, 0.0, 0.0)),
+            m.point(Vec3(0.0, 0.0, -1.0)),
+            m.vector(Vec3(0.0, 1.0, 0.0)),
+            yfov.to_degrees(),
+            aspect_ratio,
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+        ),
+        None => {
+            // Back off until the sphere around the bounds fits in the narrower of the two fields of view.
+            let centre = (bounds.min + bounds.max) * 0.5;
+            let radius = (bounds.max - bounds.min).mag() / 2.0;
+            let half_fov = DEFAULT_FOV.to_radians() / 2.0;
+            let half_fov = half_fov.min((half_fov.tan() * aspect_ratio).atan());
+            let distance = radius / half_fov.sin();
+            Camera::new(
+                Colour::new(0.7, 0.8, 1.0),
+                centre + Vec3(0.0, 0.0, distance),
+                centre,
+                Vec3(0.0, 1.0, 0.0),
+                DEFAULT_FOV,
+                aspect_ratio,
+                0.0,
+                distance,
+                0.0,
+                1.0,
+            )
+        }
+    };
+
+    Ok((importer.world, camera))
+}
+
+/// A primitive of a glTF mesh.
+type GltfMesh = TriangleMesh<Arc<dyn Material>>;
+
+/// The state kept while walking the scene's nodes.
+struct Importer<'a> {
+    buffers: &'a [buffer::Data],
+    images: &'a [image::Data],
+    materials: HashMap<(Option<usize>, bool), Arc<dyn Material>>, // Keyed by material index and use of vertex colours.
+    meshes: HashMap<usize, Vec<Arc<GltfMesh>>>, // The primitives of each mesh, by mesh index.
+    world: HittableList,
+    bounds: Option<AABB>,
+    camera: Option<(Transform, f64)>, // Transform and vertical field of view of the first perspective camera.
+}
+
+impl Importer<'_> {
+    /// Add the node and its children to the world, under the transform 'parent'. Nodes scaled down to nothing are
+    /// left out, along with their children.
+    fn node(&mut self, node: &Node, parent: &Transform) -> Result<(), String> {
+        // glTF matrices are stored column by column.
+        let columns = node.transform().matrix();
+        let rows = [0, 1, 2, 3].map(|i| columns.map(|col| col[i] as f64));
+        let m = match Transform::from_matrix(rows) {
+            Some(local) => *parent * local,
+            None => return Ok(()),
+        };
+
+        if let Some(camera) = node.camera() {
+            if let (Projection::Perspective(p), None) = (camera.projection(), &self.camera) {
+                self.camera = Some((m, p.yfov() as f64));
+            }
+        }
+        if let Some(mesh) = node.mesh() {
+            for primitive in self.mesh(&mesh)? {
+                let instance = Instance::new(primitive, m);
+                if let Some(bounds) = instance.bounding_box(0.0, 1.0) {
+                    self.bounds = Some(self.bounds.map_or(bounds, |b| b.merge(bounds)));
+                }
+                self.world.push(Box::new(instance));
+            }
+        }
+        for child in node.children() {
+            self.node(&child, &m)?;
+        }
+        Ok(())
+    }
+
+    /// Return the primitives of 'mesh' that have triangles, building them the first time the mesh is used.
+    fn mesh(&mut self, mesh: &Mesh) -> Result<Vec<Arc<GltfMesh>>, String> {
+        if let Some(primitives) = self.meshes.get(&mesh.index()) {
+            return Ok(primitives.clone());
+        }
+
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            let built = self.primitive(&primitive).map_err(|e| match mesh.name() {
+                Some(name) => format!("mesh '{}': {}", name, e),
+                None => format!("mesh {}: {}", mesh.index(), e),
+            })?;
+            primitives.extend(built.map(Arc::new));
+        }
+        self.meshes.insert(mesh.index(), primitives.clone());
+        Ok(primitives)
+    }
+
+    /// Build a TriangleMesh from a primitive, or return None if it doesn't have any triangles.
+    fn primitive(&mut self, primitive: &Primitive) -> Result<Option<GltfMesh>, String> {
+        let mode = primitive.mode();
+        if !matches!(
+            mode,
+            Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+        ) {
+            return Ok(None);
+        }
+
+        let reader = primitive.reader(|b| self.buffers.get(b.index()).map(|data| &data[..]));
+        let positions: Vec<Vec3> = reader
+            .read_positions()
+            .ok_or("a primitive has no positions")?
+            .map(|p| Vec3(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            return Err(format!(
+                "a primitive refers to vertex {} but there are only {}",
+                i,
+                positions.len()
+            ));
+        }
+
+        let faces: Vec<[u32; 3]> = match mode {
+            Mode::TriangleStrip => (2..indices.len())
+                .map(|i| match i % 2 {
+                    0 => [indices[i - 2], indices[i - 1], indices[i]],
+                    _ => [indices[i - 1], indices[i - 2], indices[i]],
+                })
+                .collect(),
+            Mode::TriangleFan => (2..indices.len())
+                .map(|i| [indices[0], indices[i - 1], indices[i]])
+                .collect(),
+            _ => indices
+                .chunks_exact(3)
+                .map(|f| [f[0], f[1], f[2]])
+                .collect(),
+        };
+        if faces.is_empty() {
+            return Ok(None);
+        }
+
+        let pbr = primitive.material().pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let base = Colour::new(r as f64, g as f64, b as f64);
+        let uv_set = pbr.base_color_texture().map_or(0, |t| t.tex_coord());
+        let colours: Option<Vec<Colour>> = reader.read_colors(0).map(|colours| {
+            colours
+                .into_rgb_f32()
+                .map(|c| Colour::new(c[0] as f64, c[1] as f64, c[2] as f64) * base)
+                .collect()
+        });
+
+        let material = self.material(primitive, colours.is_some())?;
+        let mut mesh = TriangleMesh::new(positions, faces, material);
+        if let Some(normals) = reader.read_normals() {
+            mesh = mesh.with_normals(
+                normals
+                    .map(|n| Vec3(n[0] as f64, n[1] as f64, n[2] as f64))
+                    .collect(),
+            );
+        }
+        if let Some(uvs) = reader.read_tex_coords(uv_set) {
+            // glTF puts (0, 0) at the top left of an image, but ImageTexture puts it at the bottom left.
+            mesh = mesh.with_uvs(
+                uvs.into_f32()
+                    .map(|uv| (uv[0] as f64, 1.0 - uv[1] as f64))
+                    .collect(),
+            );
+        }
+        if let Some(colours) = colours {
+            mesh = mesh.with_colours(colours);
+        }
+
+        Ok(Some(mesh))
+    }
+
+    /// Return the renderer's material for the primitive's material, creating it the first time it's used and sharing
+    /// it after that. Primitives with vertex colours get a separate material that uses them.
+    fn material(
+        &mut self,
+        primitive: &Primitive,
+        vertex_colours: bool,
+    ) -> Result<Arc<dyn Material>, String> {
+        let material = primitive.material();
+        let key = (material.index(), vertex_colours);
+        if let Some(loaded) = self.materials.get(&key) {
+            return Ok(loaded.clone());
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, alpha] = pbr.base_color_factor();
+        let base = Colour::new(r as f64, g as f64, b as f64);
+        let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+        let [r, g, b] = material.emissive_factor();
+        let emissive = Colour::new(r as f64, g as f64, b as f64) * strength;
+        let transmission = material
+            .transmission()
+            .map_or(0.0, |t| t.transmission_factor());
+
+        let loaded: Arc<dyn Material> = if emissive.r.max(emissive.g).max(emissive.b) > 0.0 {
+            Arc::new(DiffuseLight::new(SolidColour::new(emissive)))
+        } else if transmission > 0.0 || (material.alpha_mode() == AlphaMode::Blend && alpha < 1.0) {
+            Arc::new(Dielectric::new(material.ior().unwrap_or(1.5) as f64))
+        } else if pbr.metallic_factor() >= 0.5 {
+            Arc::new(Metal::new(base, pbr.roughness_factor() as f64))
+        } else if let Some(info) = pbr.base_color_texture() {
+            let index = info.texture().source().index();
+            let data = self
+                .images
+                .get(index)
+                .ok_or_else(|| format!("image {} wasn't loaded", index))?;
+            Arc::new(Lambertian::new(ImageTexture::new(convert_image(
+                data, base,
+            )?)))
+        } else if vertex_colours {
+            Arc::new(Lambertian::new(VertexColourTexture::new(base)))
+        } else {
+            Arc::new(Lambertian::new(SolidColour::new(base)))
+        };
+
+        self.materials.insert(key, loaded.clone());
+        Ok(loaded)
+    }
+}
+
+/// Convert a decoded glTF image to linear Colours multiplied by 'factor'. Integer formats are sRGB encoded, as base
+/// colour textures are, while float formats are already linear. Any alpha channel is ignored.
+fn convert_image(data: &image::Data, factor: Colour) -> Result<Image, String> {
+    let (channels, bytes) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let count = (data.width * data.height) as usize;
+    if data.pixels.len() < count * channels * bytes {
+        return Err("an image has fewer pixels than its size".to_string());
+    }
+
+    let sample = |i: usize| -> f64 {
+        let b = &data.pixels[i * bytes..(i + 1) * bytes];
+        match bytes {
+            1 => Transfer::Srgb.decode(b[0] as f64 / 255.0),
+            2 => Transfer::Srgb.decode(u16::from_ne_bytes([b[0], b[1]]) as f64 / 65535.0),
+            _ => f32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        }
+    };
+
+    // Grey images have one colour channel and the rest have three.
+    let pixels = (0..count)
+        .map(|p| {
+            let i = p * channels;
+            let c = match channels {
+                1 | 2 => Colour::new(sample(i), sample(i), sample(i)),
+                _ => Colour::new(sample(i), sample(i + 1), sample(i + 2)),
+            };
+            c * factor
+        })
+        .collect();
+
+    Ok(Image {
+        width: data.width,
+        height: data.height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    /// Encode 'bytes' as base64, for a buffer embedded in a data URI.
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |n, (k, &b)| n | (b as u32) << (16 - 8 * k));
+            for k in 0..4 {
+                encoded.push(match k <= chunk.len() {
+                    true => ALPHABET[(n >> (18 - 6 * k)) as usize & 63] as char,
+                    false => '=',
+                });
+            }
+        }
+        encoded
+    }
+
+    /// The corners of a unit square on the XY plane, facing +Z when wound anticlockwise.
+    const SQUARE: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+    ];
+
+    /// Load a glTF document with one mesh of the square, drawn with primitive 'mode' and 'indices' and placed by
+    /// 'nodes'. The scene is node 0.
+    fn load(mode: u32, indices: &[u16], nodes: &str) -> Result<(HittableList, Camera), String> {
+        let mut buffer: Vec<u8> = SQUARE
+            .iter()
+            .flatten()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        let text = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": {nodes},
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "mode": {mode} }}] }}],
+                "buffers": [{{ "byteLength": {length}, "uri": "data:application/octet-stream;base64,{data}" }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+                    {{ "buffer": 0, "byteOffset": 48, "byteLength": {index_length} }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0] }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": {count}, "type": "SCALAR" }}
+                ]
+            }}"#,
+            length = buffer.len(),
+            data = base64(&buffer),
+            index_length = 2 * indices.len(),
+            count = indices.len(),
+        );
+        let (document, buffers, images) = ::gltf::import_slice(text.as_bytes()).unwrap();
+        build_scene(&document, &buffers, &images, 1.0)
+    }
+
+    /// Return whether a ray straight down the Z axis through (x, y) hits the front of something.
+    fn front_hit(world: &HittableList, x: f64, y: f64) -> Option<bool> {
+        let r = Ray::new(Vec3(x, y, 5.0), Vec3(0.0, 0.0, -1.0), 0.0);
+        world.hit(&r, 0.001, f64::MAX).map(|rec| rec.front_face)
+    }
+
+    #[test]
+    fn every_triangle_mode_winds_faces_the_same_way() {
+        for (mode, indices) in [
+            (4, &[0, 1, 2, 2, 1, 3][..]),
+            (5, &[0, 1, 2, 3][..]), // The second triangle of a strip is (2, 1, 3), not (1, 2, 3).
+            (6, &[0, 1, 3, 2][..]),
+        ] {
+            let (world, _) = load(mode, indices, r#"[{ "mesh": 0 }]"#).unwrap();
+            assert_eq!(front_hit(&world, 0.25, 0.25), Some(true), "mode {mode}");
+            assert_eq!(front_hit(&world, 0.75, 0.75), Some(true), "mode {mode}");
+            assert_eq!(front_hit(&world, 1.5, 0.5), None, "mode {mode}");
+        }
+
+        // Points and lines have no triangles.
+        assert_eq!(
+            load(0, &[0, 1, 2], r#"[{ "mesh": 0 }]"#).err().as_deref(),
+            Some("the scene has no triangles")
+        );
+    }
+
+    #[test]
+    fn indices_must_refer_to_vertices_that_exist() {
+        assert_eq!(
+            load(4, &[0, 1, 4], r#"[{ "mesh": 0 }]"#).err().as_deref(),
+            Some("mesh 0: a primitive refers to vertex 4 but there are only 4")
+        );
+    }
+
+    #[test]
+    fn child_nodes_are_placed_inside_their_parents() {
+        // The child turns the square a quarter turn about Z, to x in [-1, 0], and moves it up to y in [1, 2]. The
+        // parent then doubles it, to x in [-2, 0] and y in [2, 4], and moves it along to x in [8, 10].
+        let nodes = r#"[
+            { "translation": [10, 0, 0], "scale": [2, 2, 2], "children": [1] },
+            { "rotation": [0, 0, 0.70710678, 0.70710678], "translation": [0, 1, 0], "mesh": 0 }
+        ]"#;
+        let (world, _) = load(4, &[0, 1, 2, 2, 1, 3], nodes).unwrap();
+
+        let bounds = world.bounding_box(0.0, 1.0).unwrap();
+        for (got, expected) in [
+            (bounds.min, Vec3(8.0, 2.0, 0.0)),
+            (bounds.max, Vec3(10.0, 4.0, 0.0)),
+        ] {
+            assert!(
+                (got - expected).mag() < 1e-3,
+                "{:?} isn't near {:?}",
+                got,
+                expected
+            );
+        }
+        assert_eq!(front_hit(&world, 9.0, 3.0), Some(true));
+        assert_eq!(front_hit(&world, 0.5, 0.5), None);
+    }
+}
@@ -1,17 +1,128 @@
+use std::sync::Arc;
+
 use crate::aabb::AABB;
+use crate::colour::Colour;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec::Vec3;
 
-use crate::colour::Colour;
-
 /// All shapes have to implement the Hittable trait in order to calculate ray intersections.
-pub trait Hittable: Sync {
+pub trait Hittable: Send + Sync {
     /// Calculate if an object was intersected.
-    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord<'_>>;
 
     /// Calculate the bounding box for an object.
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB>;
+
+    /// Return every material the object's hits can have, in a fixed order. A BVH numbers them by where they're first
+    /// found to give materials IDs. Objects that don't list their materials give them all the ID zero.
+    fn materials(&self) -> Vec<&dyn Material> {
+        Vec::new()
+    }
+
+    /// Return the ID the object gives 'material', counting from one, or zero if it doesn't number its materials. Only
+    /// a BVH does, and it's only asked about the camera rays that fill the material ID AOV, so bounces don't pay for
+    /// looking IDs up.
+    fn material_id(&self, _material: &dyn Material) -> u32 {
+        0
+    }
+
+    /// Find every stretch of the ray between 't0' and 't1' that's inside the object, in order along the ray. This only
+    /// makes sense for closed objects, whose fronts face out.
+    ///
+    /// By default the object's hits are walked along the ray, so each surface it crosses is found with 'hit'. A
+    /// front face is a way in and a back face a way out.
+    fn spans(&self, r: &Ray, t0: f64, t1: f64) -> Vec<Span<'_>> {
+        let mut spans = Vec::new();
+        let mut entered = None; // The way into the current span while inside the object.
+        let mut t = t0;
+
+        while let Some(rec) = self.hit(r, t, t1) {
+            t = rec.t + SPAN_STEP * rec.t.abs().max(1.0);
+            match (rec.front_face, entered.take()) {
+                (true, None) => entered = Some(Some(rec)),
+                (false, Some(enter)) => spans.push(Span {
+                    enter,
+                    exit: Some(rec),
+                }),
+                // Leaving before going in means the ray started inside.
+                (false, None) if spans.is_empty() => spans.push(Span {
+                    enter: None,
+                    exit: Some(rec),
+                }),
+                // Faces that don't match the last one, from surfaces that aren't closed, are skipped.
+                (true, Some(enter)) => entered = Some(enter),
+                (false, None) => {}
+            }
+        }
+
+        if let Some(enter) = entered {
+            spans.push(Span { enter, exit: None });
+        }
+        spans
+    }
+}
+
+/// How far past a hit, relative to its distance along the ray, the next one is looked for when walking through an
+/// object, so the same surface isn't found twice.
+const SPAN_STEP: f64 = 1e-9;
+
+/// A stretch of a ray that's inside an object, from where the ray goes in to where it comes out. 'enter' is None if
+/// the ray was already inside at the start of the range it was tested over, and 'exit' is None if it's still inside at
+/// the end.
+///
+/// The records' normals face against the ray like any hit's, so the normal where the ray comes out points inwards.
+#[derive(Copy, Clone)]
+pub struct Span<'a> {
+    pub enter: Option<HitRecord<'a>>,
+    pub exit: Option<HitRecord<'a>>,
+}
+
+/// Boxed objects, so an Instance can hold an object chosen at run time.
+impl<H: Hittable + ?Sized> Hittable for Box<H> {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t0, t1)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        (**self).materials()
+    }
+
+    fn material_id(&self, material: &dyn Material) -> u32 {
+        (**self).material_id(material)
+    }
+
+    fn spans(&self, r: &Ray, t0: f64, t1: f64) -> Vec<Span<'_>> {
+        (**self).spans(r, t0, t1)
+    }
+}
+
+/// Shared objects, so many Instances can place one object, like a large mesh, without copying it or building its
+/// BVH again.
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t0, t1)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        (**self).materials()
+    }
+
+    fn material_id(&self, material: &dyn Material) -> u32 {
+        (**self).material_id(material)
+    }
+
+    fn spans(&self, r: &Ray, t0: f64, t1: f64) -> Vec<Span<'_>> {
+        (**self).spans(r, t0, t1)
+    }
 }
 
 /// A HitRecord records a collision between an object and a ray.
@@ -24,6 +135,9 @@ pub struct HitRecord<'a> {
     pub normal: Vec3,     // Normal vector of the intersected object.
     pub front_face: bool, // Flag for detemrining whether the ray hit the inside or outside of an objeect.
     pub material: &'a dyn Material, // The material assigned to the intersected object.
+    pub object_id: u32, // Index of the intersected object in the world, plus one. Zero until set by a container.
+    pub material_id: u32, // ID of the material within the world, counting from one. Only set for the AOVs.
+    pub colour: Option<Colour>, // Vertex colour interpolated at the hit, for meshes that have them.
 }
 
 impl<'a> HitRecord<'a> {
@@ -44,15 +158,25 @@ impl<'a> HitRecord<'a> {
             normal,
             front_face,
             material,
+            object_id: 0,
+            material_id: 0,
+            colour: None,
         }
     }
 }
 
 /// A HittableList stores a collection of HitRecords and has functionality for finding the closes hit to the camera.
+/// Hits are tagged with the position of the object in the list, which makes it the object's ID.
 pub struct HittableList {
     pub list: Vec<Box<dyn Hittable>>,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         HittableList { list: Vec::new() }
@@ -64,13 +188,14 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut hit_obj: Option<HitRecord> = None;
         let mut closest = t_max;
 
-        for hittable in self.list.iter() {
-            if let Some(hit) = hittable.hit(r, t_min, closest) {
+        for (i, hittable) in self.list.iter().enumerate() {
+            if let Some(mut hit) = hittable.hit(r, t_min, closest) {
                 closest = hit.t;
+                hit.object_id = i as u32 + 1;
                 hit_obj = Some(hit);
             }
         }
@@ -79,18 +204,17 @@ impl Hittable for HittableList {
 
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
         match self.list.first() {
-            Some(first) => {
-                match first.bounding_box(t0, t1) {
-                    Some(bbox) => self.list.iter().skip(1).try_fold(bbox, |acc, hittable| {
-                        match hittable.bounding_box(t0, t1) {
-                            Some(bbox) => Some(acc.merge(bbox)),
-                            _ => None,
-                        }
-                    }),
-                    _ => None,
-                }
-            }
+            Some(first) => match first.bounding_box(t0, t1) {
+                Some(bbox) => self.list.iter().skip(1).try_fold(bbox, |acc, hittable| {
+                    hittable.bounding_box(t0, t1).map(|bbox| acc.merge(bbox))
+                }),
+                _ => None,
+            },
             _ => None,
         }
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.list.iter().flat_map(|h| h.materials()).collect()
+    }
 }
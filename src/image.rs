@@ -0,0 +1,30 @@
+use crate::colour::Colour;
+
+/// An Image is a framebuffer of linear Colours. Pixels are stored row by row, starting from the top left.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Colour>,
+}
+
+impl Image {
+    /// Create a black image of the given size.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Colour::new(0.0, 0.0, 0.0); (width * height) as usize],
+        }
+    }
+
+    /// Return the Colour of the pixel at column 'x' and row 'y'. Row 0 is the top of the image.
+    pub fn get(&self, x: u32, y: u32) -> Colour {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Set the Colour of the pixel at column 'x' and row 'y'. Row 0 is the top of the image.
+    pub fn set(&mut self, x: u32, y: u32, c: Colour) {
+        self.pixels[(y * self.width + x) as usize] = c;
+    }
+}
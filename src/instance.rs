@@ -0,0 +1,179 @@
+use crate::aabb::AABB;
+
+use crate::hittable::{HitRecord, Hittable, Span};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::transform::Transform;
+
+/// An Instance places a Hittable in the world with a Transform, so it can be moved, rotated and scaled without the
+/// object itself having to support it.
+///
+/// Rays are moved into the object's own space to be tested against it, and the hit point and normal are moved back
+/// out again. Give each Instance an Arc of the same object to place it many times while only storing it once.
+pub struct Instance<H: Hittable> {
+    object: H,
+    transform: Transform,
+}
+
+impl<H: Hittable> Instance<H> {
+    pub fn new(object: H, transform: Transform) -> Self {
+        Self { object, transform }
+    }
+
+    /// Move a hit on the object out into the world.
+    fn place<'a>(&self, mut rec: HitRecord<'a>) -> HitRecord<'a> {
+        rec.p = self.transform.point(rec.p);
+        rec.normal = self.transform.normal(rec.normal).normalise();
+        rec
+    }
+}
+
+impl<H: Hittable> Hittable for Instance<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // The direction isn't normalised in object space, so hits are at the same 't' along both rays.
+        let local = self.transform.inverse().ray(r);
+        let rec = self.object.hit(&local, t_min, t_max)?;
+        Some(self.place(rec))
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.object
+            .bounding_box(t0, t1)
+            .map(|bb| self.transform.bounding_box(bb))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.object.materials()
+    }
+
+    fn spans(&self, r: &Ray, t0: f64, t1: f64) -> Vec<Span<'_>> {
+        let local = self.transform.inverse().ray(r);
+        self.object
+            .spans(&local, t0, t1)
+            .into_iter()
+            .map(|span| Span {
+                enter: span.enter.map(|rec| self.place(rec)),
+                exit: span.exit.map(|rec| self.place(rec)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::bvh::BVH;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+    use crate::mesh::TriangleMesh;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColour;
+    use crate::vec::Vec3;
+
+    /// A unit sphere stretched to twice its length along X, tipped over about Z and moved away from the origin.
+    fn ellipsoid() -> (Instance<Sphere<Lambertian<SolidColour>>>, Transform) {
+        let transform = Transform::translate(Vec3(3.0, 1.0, -2.0))
+            * Transform::rotate_z(30.0)
+            * Transform::scale(Vec3(2.0, 1.0, 1.0));
+        let material = Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, material);
+        (Instance::new(sphere, transform), transform)
+    }
+
+    #[test]
+    fn hits_are_on_the_transformed_surface() {
+        let (instance, transform) = ellipsoid();
+        let inverse = transform.inverse();
+        for direction in [
+            Vec3(-1.0, 0.0, 0.0),
+            Vec3(-1.0, -0.3, 0.2),
+            Vec3(0.1, -1.0, 0.4),
+        ] {
+            let target = transform.point(Vec3(0.0, 0.0, 0.0));
+            let r = Ray::new(target - direction * 10.0, direction, 0.0);
+            let rec = instance.hit(&r, 0.001, f64::MAX).unwrap();
+            assert!((rec.p - r.point_at(rec.t)).mag() < 1e-9);
+
+            // Back in the sphere's own space the hit is on the unit sphere, and its normal is the surface normal
+            // there, moved out by the inverse transpose rather than tilted by the stretch.
+            let local = inverse.point(rec.p);
+            assert!((local.mag() - 1.0).abs() < 1e-9, "{:?}", local);
+            let expected = transform.normal(local).normalise();
+            assert!((rec.normal - expected).mag() < 1e-9);
+            assert!(rec.front_face);
+            assert!((transform.vector(local).normalise() - expected).mag() > 1e-3);
+        }
+    }
+
+    #[test]
+    fn bounding_box_holds_the_transformed_object() {
+        let (instance, transform) = ellipsoid();
+        let bb = instance.bounding_box(0.0, 1.0).unwrap();
+        for i in 0..200 {
+            // Points spread over the unit sphere on a spiral.
+            let z = 1.0 - (i as f64 + 0.5) / 100.0;
+            let angle = i as f64 * 2.399963;
+            let r = (1.0 - z * z).sqrt();
+            let p = transform.point(Vec3(r * angle.cos(), r * angle.sin(), z));
+            for (x, min, max) in [
+                (p.0, bb.min.0, bb.max.0),
+                (p.1, bb.min.1, bb.max.1),
+                (p.2, bb.min.2, bb.max.2),
+            ] {
+                assert!(
+                    min <= x && x <= max,
+                    "{:?} is outside {:?} to {:?}",
+                    p,
+                    bb.min,
+                    bb.max
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn instances_share_one_mesh() {
+        // A unit square facing +Z, placed in a row of copies 3 apart along X.
+        let positions = vec![
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(1.0, 1.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+        ];
+        let material = Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)));
+        let mesh = Arc::new(TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [0, 2, 3]],
+            material,
+        ));
+
+        let n = 5;
+        let objs: Vec<Box<dyn Hittable>> = (0..n)
+            .map(|i| {
+                let transform = Transform::translate(Vec3(3.0 * i as f64, 0.0, -1.0));
+                Box::new(Instance::new(mesh.clone(), transform)) as Box<dyn Hittable>
+            })
+            .collect();
+        // Only the pointers were copied: the mesh, with its BVH, is stored once.
+        assert_eq!(Arc::strong_count(&mesh), n + 1);
+
+        let world = BVH::new(objs, 0.0, 1.0);
+        let down = |x: f64| {
+            let r = Ray::new(Vec3(x, 0.5, 4.0), Vec3(0.0, 0.0, -1.0), 0.0);
+            world.hit(&r, 0.001, f64::MAX)
+        };
+        let first = down(0.5).unwrap().material.identity();
+        for i in 0..n {
+            let x = 3.0 * i as f64;
+            let rec = down(x + 0.5).unwrap();
+            assert!((rec.t - 5.0).abs() < 1e-9);
+            assert!((rec.p - Vec3(x + 0.5, 0.5, -1.0)).mag() < 1e-9);
+            assert_eq!(rec.object_id, i as u32 + 1);
+            assert_eq!(rec.material.identity(), first);
+            // Between the copies there's nothing.
+            assert!(down(x + 2.0).is_none());
+        }
+    }
+}
@@ -0,0 +1,45 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod aabb;
+pub mod aov;
+pub mod bvh;
+pub mod camera;
+mod checkpoint;
+pub mod colour;
+pub mod csg;
+pub mod cuboid;
+pub mod denoise;
+pub mod film;
+pub mod gltf;
+pub mod hittable;
+pub mod image;
+pub mod instance;
+pub mod material;
+pub mod mesh;
+pub mod named;
+pub mod obj;
+pub mod output;
+pub mod perlin;
+pub mod planar;
+pub mod ply;
+pub mod progress;
+pub mod quadric;
+pub mod ray;
+pub mod rect;
+pub mod render;
+pub mod sampler;
+pub mod scenes;
+pub mod sphere;
+pub mod texture;
+#[cfg(test)]
+mod testing;
+pub mod tile;
+pub mod tonemap;
+pub mod transform;
+pub mod triangle;
+pub mod vec;
+
+pub use render::{
+    ray_colour, render, render_with_aovs, AdaptiveSettings, ProgressiveRender, RenderBudget,
+    RenderOutput, RenderSettings,
+};
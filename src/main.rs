@@ -1,111 +1,545 @@
-mod aabb;
-mod camera;
-mod material;
-mod perlin;
-mod sphere;
-mod texture;
-mod vec;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-mod cuboid;
-mod rect;
+use clap::{Args, Parser, Subcommand};
 
-mod scenes;
-use scenes::*;
+use ray_tracing_with_rust::aov::Aov;
+use ray_tracing_with_rust::bvh::BVH;
+use ray_tracing_with_rust::denoise::{denoise, DenoiseSettings};
+use ray_tracing_with_rust::film::{CropWindow, Filter, FilterKind};
+use ray_tracing_with_rust::gltf::load_gltf;
+use ray_tracing_with_rust::image::Image;
+use ray_tracing_with_rust::named::UnknownName;
+use ray_tracing_with_rust::output::{
+    self, BitDepth, ExrCompression, ImageFormat, Layer, OutputOptions,
+};
+use ray_tracing_with_rust::progress::{CancelToken, Progress, RenderObserver};
+use ray_tracing_with_rust::sampler::SamplerKind;
+use ray_tracing_with_rust::scenes::Scene;
+use ray_tracing_with_rust::tile::{TileOrder, TileSettings};
+use ray_tracing_with_rust::tonemap::{PostProcess, ToneMap, Transfer};
+use ray_tracing_with_rust::{AdaptiveSettings, ProgressiveRender, RenderBudget, RenderSettings};
 
-mod hittable;
-use hittable::*;
+/// Render the scenes from the Ray Tracing in One Weekend series.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render a scene to an image.
+    Render(Box<RenderArgs>),
+
+    /// List the names of the built-in scenes.
+    Scenes,
+}
+
+/// A scene to render: either a built-in one or one loaded from a glTF file.
+#[derive(Clone)]
+enum SceneArg {
+    BuiltIn(Scene),
+    Gltf(PathBuf),
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// Name of the scene to render, or a glTF file ending in .gltf or .glb. Use the 'scenes' subcommand to list the
+    /// built-in scenes.
+    #[arg(default_value = "cornell_box", value_parser = parse_scene)]
+    scene: SceneArg,
+
+    /// Width of the image in pixels.
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
 
-mod bvh;
-use bvh::*;
+    /// Height of the image in pixels.
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    height: u32,
 
-mod colour;
-use colour::*;
+    /// Number of samples traced per pixel. Defaults to 200, or no limit with '--time' or '--noise', which the stratified
+    /// sampler can't have.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    samples: Option<u32>,
 
-mod ray;
-use ray::*;
+    /// Keep adding passes for this long instead of stopping at a number of samples, e.g. '90s', '10m' or '1h30m'.
+    #[arg(long, value_parser = parse_duration)]
+    time: Option<Duration>,
 
-use rand::distributions::{Distribution, Uniform};
-use rand::rngs::ThreadRng;
+    /// Keep adding passes until the RMS relative noise of the image falls to this level, e.g. 0.05.
+    #[arg(long, value_parser = parse_positive)]
+    noise: Option<f64>,
 
-use rayon::prelude::*;
+    /// Sample adaptively: stop sampling pixels once their relative noise is below this threshold, e.g. 0.05, and spend
+    /// the samples saved on noisier pixels. '--samples' becomes the average per pixel.
+    #[arg(long, value_parser = parse_non_negative)]
+    adaptive: Option<f64>,
 
-fn ray_colour(
-    r: &Ray,
-    bg: Colour,
-    world: &Box<dyn Hittable>,
-    dist: &Uniform<f64>,
-    rng: &mut ThreadRng,
+    /// Samples taken in every pixel before adaptive sampling checks for convergence.
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(2..))]
+    min_samples: u32,
+
+    /// Most samples any one pixel may take when sampling adaptively. Defaults to eight times '--samples'.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_samples: Option<u32>,
+
+    /// Maximum number of times a ray may bounce.
+    #[arg(short, long, default_value_t = 50)]
     depth: u32,
-) -> Colour {
-    if let Some(hit) = world.hit(r, 0.001, f64::MAX) {
-        let emitted = hit.material.emitted(hit.u, hit.v, hit.p, dist, rng);
-        if depth > 0 {
-            if let Some((scattered, attenuation)) = hit.material.scatter(&hit, r, dist, rng) {
-                return emitted
-                    + attenuation * ray_colour(&scattered, bg, world, dist, rng, depth - 1);
+
+    /// File to write the image to. The format is chosen by the extension: .png, .ppm, .pfm, .hdr or
+    /// .exr.
+    /// Writes a binary PPM to stdout if not given.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Bits per channel when writing a PNG.
+    #[arg(long, default_value = "8")]
+    bit_depth: BitDepth,
+
+    /// Exposure compensation in stops, applied before tone mapping.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f64,
+
+    /// Tone mapping operator.
+    #[arg(long, default_value = "clamp")]
+    tonemap: ToneMap,
+
+    /// Linear value mapped to white by the extended-reinhard and uncharted2 operators.
+    #[arg(long, default_value_t = 11.2, value_parser = parse_positive)]
+    white_point: f64,
+
+    /// Transfer function used to encode display images.
+    #[arg(long, default_value = "srgb")]
+    transfer: Transfer,
+
+    /// Compression used when writing an EXR.
+    #[arg(long, default_value = "zip")]
+    exr_compression: ExrCompression,
+
+    /// Number of render threads. Defaults to one per CPU.
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// Sampler used to generate samples.
+    #[arg(long, default_value = "independent")]
+    sampler: SamplerKind,
+
+    /// Reconstruction filter used to develop the image.
+    #[arg(long, default_value = "box")]
+    filter: FilterKind,
+
+    /// Radius of the reconstruction filter in pixels. Defaults to the usual radius for the filter.
+    #[arg(long)]
+    filter_radius: Option<f64>,
+
+    /// Only render the pixels in 'x0,y0,x1,y1', counting from the top left with 'x1' and 'y1' exclusive. The image
+    /// written is just the cropped part.
+    #[arg(long, value_parser = parse_crop)]
+    crop: Option<CropWindow>,
+
+    /// Extra buffers to write alongside the image, separated by commas. EXRs store them as layers, other formats as
+    /// '<stem>.<aov>.<ext>' files. Needs '--output', since they can't be written to stdout.
+    #[arg(long, value_delimiter = ',', requires = "output")]
+    aovs: Vec<Aov>,
+
+    /// Denoise the image with an edge-avoiding wavelet filter guided by the albedo and normal buffers.
+    #[arg(long)]
+    denoise: bool,
+
+    /// Save the render to this file every '--checkpoint-interval' seconds and when it finishes, so it can be
+    /// resumed with '--resume'.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints.
+    #[arg(long, default_value_t = 60)]
+    checkpoint_interval: u64,
+
+    /// Carry on from the render saved in the '--checkpoint' file. The other options must match the ones it was
+    /// started with, except '--samples', which may be raised to add more samples unless the sampler is stratified or
+    /// the render is adaptive.
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Width and height of the tiles each pass is split into, in pixels.
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    tile_size: u32,
+
+    /// Order tiles are rendered in.
+    #[arg(long, default_value = "spiral")]
+    tile_order: TileOrder,
+
+    /// Don't show a progress bar.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Seed for scene generation and sampling. Renders with the same seed are identical.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Render(args) => run_render(*args),
+        Command::Scenes => {
+            for scene in Scene::ALL.iter() {
+                println!("{}", scene);
             }
+            Ok(())
         }
-        emitted
-    } else {
-        bg
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
     }
 }
 
-fn main() {
-    // Constants
-    //const ASPECT_RATIO: f64 = 16.0 / 9.0;
-    const ASPECT_RATIO: f64 = 1.0;
-    const IMAGE_WIDTH: u32 = 300;
-    const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
-    const NUM_SAMPLES: u32 = 200;
-    const MAX_DEPTH: u32 = 50;
+fn run_render(args: RenderArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()?;
+    }
+
+    // Check the output format before rendering so a bad path doesn't waste a render.
+    if let Some(path) = &args.output {
+        ImageFormat::from_path(path)?;
+    }
+    if let Some(crop) = args.crop {
+        if crop.clamp(args.width, args.height) != crop || crop.width() == 0 || crop.height() == 0 {
+            return Err(format!(
+                "crop window {},{},{},{} isn't inside the {}x{} image",
+                crop.x0, crop.y0, crop.x1, crop.y1, args.width, args.height
+            )
+            .into());
+        }
+    }
+    let filter = Filter {
+        kind: args.filter,
+        radius: args.filter_radius.unwrap_or(args.filter.default_radius()),
+    };
+    if !filter.radius.is_finite() || filter.radius <= 0.0 {
+        return Err(format!("filter radius {} isn't positive", filter.radius).into());
+    }
+    // Stratified samples are spread over a set number of samples, so a budget with no limit on them would silently
+    // leave the pixels unstratified.
+    if args.sampler == SamplerKind::Stratified
+        && args.samples.is_none()
+        && (args.time.is_some() || args.noise.is_some())
+    {
+        return Err("the stratified sampler needs '--samples' with '--time' or '--noise'".into());
+    }
+    let options = OutputOptions {
+        bit_depth: args.bit_depth,
+        exr_compression: args.exr_compression,
+        post: PostProcess {
+            exposure: args.exposure,
+            tone_map: args.tonemap,
+            white_point: args.white_point,
+            transfer: args.transfer,
+        },
+    };
 
     // Scene creation
-    let choice = 5;
-    let (world, camera) = match choice {
-        1 => random_scene(ASPECT_RATIO),
-        2 => two_checkered_spheres(ASPECT_RATIO),
-        3 => two_perlin_spheres(ASPECT_RATIO),
-        4 => simple_light(ASPECT_RATIO),
-        5 => cornell_box(ASPECT_RATIO),
-        _ => panic!("invalid scene selection"),
+    let aspect_ratio = args.width as f64 / args.height as f64;
+    let (world, camera) = match &args.scene {
+        SceneArg::BuiltIn(scene) => scene.build(aspect_ratio, args.seed),
+        SceneArg::Gltf(path) => load_gltf(path, aspect_ratio)?,
     };
 
     // BVH
-    let world = Box::new(BVH::new(world.list, 0.0, 1.0)) as Box<dyn Hittable>;
-
-    // Render
-    let image = (0..IMAGE_HEIGHT)
-        .into_par_iter()
-        .rev()
-        .flat_map(|j| {
-            let mut rng = rand::thread_rng();
-            let dist = Uniform::from(0.0..1.0);
-
-            (0..IMAGE_WIDTH)
-                .flat_map(|i| {
-                    let mut c = Colour::new(0.0, 0.0, 0.0);
-
-                    for _ in 0..NUM_SAMPLES {
-                        let u = (i as f64 + dist.sample(&mut rng)) / IMAGE_WIDTH as f64;
-                        let v = (j as f64 + dist.sample(&mut rng)) / IMAGE_HEIGHT as f64;
-                        let r = camera.get_ray(u, v, &mut rng);
-                        c += ray_colour(&r, camera.bg, &world, &dist, &mut rng, MAX_DEPTH);
-                    }
-
-                    vec![
-                        (255.999 * (c.r / NUM_SAMPLES as f64).sqrt().max(0.0).min(1.0)) as u8,
-                        (255.999 * (c.g / NUM_SAMPLES as f64).sqrt().max(0.0).min(1.0)) as u8,
-                        (255.999 * (c.b / NUM_SAMPLES as f64).sqrt().max(0.0).min(1.0)) as u8,
-                    ]
-                })
-                .collect::<Vec<u8>>()
-        })
-        .collect::<Vec<u8>>();
+    let world = BVH::new(world.list, 0.0, 1.0);
+
+    // Render. The denoiser needs the albedo and normal buffers even if they weren't asked for.
+    let mut aovs = args.aovs.clone();
+    if args.denoise {
+        for aov in [Aov::Albedo, Aov::Normal] {
+            if !aovs.contains(&aov) {
+                aovs.push(aov);
+            }
+        }
+    }
+    let budget = RenderBudget {
+        time: args.time,
+        noise: args.noise,
+    };
+    let samples = match (
+        args.samples,
+        budget.time.is_some() || budget.noise.is_some(),
+    ) {
+        (Some(samples), _) => samples,
+        (None, true) => u32::MAX,
+        (None, false) => 200,
+    };
+    let settings = RenderSettings {
+        width: args.width,
+        height: args.height,
+        samples,
+        max_depth: args.depth,
+        seed: args.seed,
+        sampler: args.sampler,
+        aovs,
+        adaptive: args.adaptive.map(|threshold| AdaptiveSettings {
+            min_samples: args.min_samples,
+            max_samples: args
+                .max_samples
+                .unwrap_or_else(|| samples.saturating_mul(8)),
+            threshold,
+        }),
+        filter,
+        crop: args.crop,
+    };
+    // The first Ctrl-C stops the render after the current tiles and saves what's been done. The second quits.
+    let cancel = CancelToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            process::exit(130);
+        }
+        handler_cancel.cancel();
+    })?;
+
+    let progress_bar = ProgressBar::new();
+    let tiles = TileSettings {
+        size: args.tile_size,
+        order: args.tile_order,
+    };
+    let progressive = match &args.checkpoint {
+        Some(path) if args.resume => {
+            let mut input = BufReader::new(File::open(path)?);
+            ProgressiveRender::resume(&world, &camera, &settings, &mut input)
+                .map_err(|e| format!("can't resume from '{}': {}", path.display(), e))?
+        }
+        _ => ProgressiveRender::new(&world, &camera, &settings),
+    };
+    let mut progressive = progressive
+        .with_tiles(tiles)
+        .with_budget(budget)
+        .with_cancel(cancel.clone());
+    if !args.quiet && io::stderr().is_terminal() {
+        progressive = progressive.with_observer(&progress_bar);
+    }
+
+    let interval = Duration::from_secs(args.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
+    while progressive.render_pass() {
+        if let Some(path) = &args.checkpoint {
+            if last_checkpoint.elapsed() >= interval {
+                save_checkpoint(&progressive, path)?;
+                last_checkpoint = Instant::now();
+            }
+        }
+    }
+    progress_bar.finish();
+    if cancel.is_cancelled() {
+        eprintln!("interrupted: saving the passes finished so far");
+    }
+    if let Some(path) = &args.checkpoint {
+        save_checkpoint(&progressive, path)?;
+    }
+    let mut output = progressive.output();
+
+    // Denoise
+    if args.denoise {
+        let albedo = output.aov(Aov::Albedo).unwrap();
+        let normal = output.aov(Aov::Normal).unwrap();
+        output.beauty = denoise(
+            &output.beauty,
+            &output.variance,
+            albedo,
+            normal,
+            &DenoiseSettings::default(),
+        );
+        output.aovs.retain(|(aov, _)| args.aovs.contains(aov));
+    }
 
     // Write
-    println!("P3\n {} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT);
-    for colour in image.chunks(3) {
-        println!("{} {} {}", colour[0], colour[1], colour[2]);
+    match &args.output {
+        Some(path) => {
+            // Display formats can't hold raw AOV data, so convert it to something viewable.
+            let is_hdr = ImageFormat::from_path(path)?.is_hdr();
+            let aov_images: Vec<(Aov, Image)> = output
+                .aovs
+                .into_iter()
+                .map(|(aov, image)| match is_hdr {
+                    true => (aov, image),
+                    false => (aov, aov.to_display(&image)),
+                })
+                .collect();
+            let layers: Vec<Layer> = aov_images
+                .iter()
+                .map(|(aov, image)| Layer {
+                    name: aov.name(),
+                    image,
+                })
+                .collect();
+
+            output::write_image(&output.beauty, &layers, path, &options)
+                .map_err(|e| format!("can't write '{}': {}", path.display(), e))?
+        }
+        None => {
+            let mut out = BufWriter::new(io::stdout());
+            output::write_ppm(&options.post.apply_image(&output.beauty), &mut out)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a checkpoint next to 'path' and then move it into place, so a crash while writing can't lose the last one.
+/// The temporary file is 'path' with '.tmp' added, so it's never the checkpoint itself.
+fn save_checkpoint(progressive: &ProgressiveRender, path: &Path) -> Result<(), String> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let result = File::create(&temp).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        progressive.write_checkpoint(&mut out)?;
+        out.flush()?;
+        fs::rename(&temp, path)
+    });
+    result.map_err(|e| format!("can't write checkpoint '{}': {}", path.display(), e))
+}
+
+/// A progress bar drawn on stderr, showing how much of the render is done and how long is left.
+struct ProgressBar {
+    last_draw: Mutex<Option<Instant>>,
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 30;
+
+    fn new() -> Self {
+        Self {
+            last_draw: Mutex::new(None),
+        }
+    }
+
+    fn draw(&self, progress: &Progress, force: bool) {
+        let mut last_draw = self.last_draw.lock().unwrap();
+        if !force && last_draw.is_some_and(|t| t.elapsed() < Duration::from_millis(100)) {
+            return;
+        }
+        *last_draw = Some(Instant::now());
+
+        let filled = (progress.fraction() * Self::WIDTH as f64) as usize;
+        let eta = progress.eta.map_or("-".to_string(), format_duration);
+        eprint!(
+            "\r[{}{}] {:5.1}%  pass {}  {} elapsed  {} left ",
+            "#".repeat(filled),
+            " ".repeat(Self::WIDTH - filled),
+            100.0 * progress.fraction(),
+            progress.pass,
+            format_duration(progress.elapsed),
+            eta
+        );
+    }
+
+    /// Move past the bar, if it was drawn, so later messages start on their own line.
+    fn finish(&self) {
+        if self.last_draw.lock().unwrap().is_some() {
+            eprintln!();
+        }
+    }
+}
+
+impl RenderObserver for ProgressBar {
+    fn tile_finished(&self, progress: &Progress) {
+        self.draw(progress, false);
+    }
+
+    fn pass_finished(&self, progress: &Progress) {
+        self.draw(progress, true);
+    }
+}
+
+/// Parse a duration made of numbers followed by 'h', 'm' or 's', e.g. '1h30m' or '90s'. A bare number is seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let error = || format!("'{}' isn't a duration like '90s', '10m' or '1h30m'", s);
+    if let Ok(secs) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).map_err(|_| error());
+    }
+
+    let mut total = 0.0;
+    let mut number = String::new();
+    for c in s.chars() {
+        let unit = match c {
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => {
+                number.push(c);
+                continue;
+            }
+        };
+        total += number.parse::<f64>().map_err(|_| error())? * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(error());
+    }
+    Duration::try_from_secs_f64(total).map_err(|_| error())
+}
+
+/// Parse a scene argument. Paths ending in .gltf or .glb are glTF files and anything else must name a built-in scene.
+fn parse_scene(s: &str) -> Result<SceneArg, String> {
+    let path = PathBuf::from(s);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("gltf") || e.eq_ignore_ascii_case("glb") => {
+            Ok(SceneArg::Gltf(path))
+        }
+        _ => s
+            .parse()
+            .map(SceneArg::BuiltIn)
+            .map_err(|e: UnknownName| e.to_string()),
+    }
+}
+
+/// Parse a crop window written as 'x0,y0,x1,y1'.
+fn parse_crop(s: &str) -> Result<CropWindow, String> {
+    let error = || format!("'{}' isn't a crop window like '0,0,100,50'", s);
+    let values: Vec<u32> = s
+        .split(',')
+        .map(|v| v.trim().parse().map_err(|_| error()))
+        .collect::<Result<_, _>>()?;
+    match values[..] {
+        [x0, y0, x1, y1] => Ok(CropWindow { x0, y0, x1, y1 }),
+        _ => Err(error()),
+    }
+}
+
+/// Parse a number of at least 0, like an adaptive threshold.
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value >= 0.0 => Ok(value),
+        _ => Err(format!("'{}' isn't a number of at least 0", s)),
+    }
+}
+
+/// Parse a number greater than 0, like a noise level.
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(value),
+        _ => Err(format!("'{}' isn't a number greater than 0", s)),
+    }
+}
+
+/// Format a duration as hours, minutes and seconds, e.g. '1h02m03s' or '4m05s'.
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
     }
 }
@@ -1,9 +1,9 @@
-use rand::distributions::{Distribution, Uniform};
-use rand::rngs::ThreadRng;
+use std::sync::Arc;
 
 use crate::colour::Colour;
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::{reflect, refract, Vec3};
 
 use crate::texture::Texture;
@@ -14,18 +14,79 @@ fn schlick(cos: f64, ior: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cos).powi(5)
 }
 
-pub trait Material: Sync {
+pub trait Material: Send + Sync {
     /// Given an input ray and a record of a collision, calculate the reflected ray and the Colour of the point.
     fn scatter(
         &self,
         rec: &HitRecord,
         ray: &Ray,
-        dist: &Uniform<f64>,
-        rng: &mut ThreadRng,
+        sampler: &mut dyn Sampler,
     ) -> Option<(Ray, Colour)>;
 
     /// Return how much light is emitted from the material. Black for anything that isn't a light source.
-    fn emitted(&self, u: f64, v: f64, p: Vec3, dist: &Uniform<f64>, rng: &mut ThreadRng) -> Colour;
+    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Colour;
+
+    /// Return the base colour of the material at the point of collision, ignoring lighting.
+    fn albedo(&self, rec: &HitRecord) -> Colour;
+
+    /// Return the address and type of the material, which tell materials apart when a BVH gives them IDs. Boxes and
+    /// Arcs return those of the material they hold, so objects sharing a material through an Arc share its ID. The
+    /// type matters for zero-sized materials, which can have the same address as a different material; two of the
+    /// same type hold nothing to tell them apart, so they get the same ID.
+    fn identity(&self) -> (usize, &'static str) {
+        (
+            self as *const Self as *const () as usize,
+            std::any::type_name::<Self>(),
+        )
+    }
+}
+
+/// Boxed materials, so objects can be given a material chosen at run time, like one read from a file.
+impl<M: Material + ?Sized> Material for Box<M> {
+    fn scatter(
+        &self,
+        rec: &HitRecord,
+        ray: &Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Colour)> {
+        (**self).scatter(rec, ray, sampler)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Colour {
+        (**self).emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        (**self).albedo(rec)
+    }
+
+    fn identity(&self) -> (usize, &'static str) {
+        (**self).identity()
+    }
+}
+
+/// Shared materials, so any number of objects can use one material without each having a copy of it.
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(
+        &self,
+        rec: &HitRecord,
+        ray: &Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Colour)> {
+        (**self).scatter(rec, ray, sampler)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Colour {
+        (**self).emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        (**self).albedo(rec)
+    }
+
+    fn identity(&self) -> (usize, &'static str) {
+        (**self).identity()
+    }
 }
 
 /// Lambertian materials a diffuse. For this program, they reflect 50% of light.
@@ -45,29 +106,25 @@ impl<T: Texture> Material for Lambertian<T> {
         &self,
         rec: &HitRecord,
         ray: &Ray,
-        dist: &Uniform<f64>,
-        rng: &mut ThreadRng,
+        sampler: &mut dyn Sampler,
     ) -> Option<(Ray, Colour)> {
         let scattered_ray = Ray::new(
             rec.p,
-            rec.normal + Vec3::random_in_unit_sphere(dist, rng),
+            rec.normal + Vec3::random_in_unit_sphere(sampler),
             ray.time,
         );
 
-        let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
+        let attenuation = self.albedo.value_at(rec);
         Some((scattered_ray, attenuation))
     }
 
-    fn emitted(
-        &self,
-        _u: f64,
-        _v: f64,
-        _p: Vec3,
-        _dist: &Uniform<f64>,
-        _rng: &mut ThreadRng,
-    ) -> Colour {
+    fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Colour {
         Colour::new(0.0, 0.0, 0.0)
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        self.albedo.value_at(rec)
+    }
 }
 
 /// Metallic materials are reflective. They have a colour and a 'fuzz' value, determining how crisp the reflections are.
@@ -88,15 +145,14 @@ impl Material for Metal {
         &self,
         rec: &HitRecord,
         ray: &Ray,
-        dist: &Uniform<f64>,
-        rng: &mut ThreadRng,
+        sampler: &mut dyn Sampler,
     ) -> Option<(Ray, Colour)> {
         let reflected_ray = reflect(ray.direction.normalise(), rec.normal);
 
         if reflected_ray.dot(rec.normal) > 0.0 {
             let scattered_ray = Ray::new(
                 rec.p,
-                reflected_ray + Vec3::random_in_unit_sphere(dist, rng) * self.fuzz,
+                reflected_ray + Vec3::random_in_unit_sphere(sampler) * self.fuzz,
                 ray.time,
             );
             Some((scattered_ray, self.albedo))
@@ -105,16 +161,13 @@ impl Material for Metal {
         }
     }
 
-    fn emitted(
-        &self,
-        _u: f64,
-        _v: f64,
-        _p: Vec3,
-        _dist: &Uniform<f64>,
-        _rng: &mut ThreadRng,
-    ) -> Colour {
+    fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Colour {
         Colour::new(0.0, 0.0, 0.0)
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
 }
 
 /// Dielectric materials are transparent. Given an IOR they will refract light, or reflect it where applicable.
@@ -134,8 +187,7 @@ impl Material for Dielectric {
         &self,
         rec: &HitRecord,
         ray: &Ray,
-        dist: &Uniform<f64>,
-        rng: &mut ThreadRng,
+        sampler: &mut dyn Sampler,
     ) -> Option<(Ray, Colour)> {
         let attenuation = Colour::new(1.0, 1.0, 1.0);
 
@@ -154,7 +206,7 @@ impl Material for Dielectric {
             Some(refracted) => {
                 // If Shlick's approximation says reflection is highly likely, use Uniform dist decide whether to reflect
                 let reflect_prob = schlick(cos, ni_over_nt);
-                if dist.sample(rng) < reflect_prob {
+                if sampler.next_1d() < reflect_prob {
                     let reflected = reflect(unit_direction, rec.normal);
                     let scattered = Ray::new(rec.p, reflected, ray.time);
                     return Some((scattered, attenuation));
@@ -174,16 +226,14 @@ impl Material for Dielectric {
         }
     }
 
-    fn emitted(
-        &self,
-        _u: f64,
-        _v: f64,
-        _p: Vec3,
-        _dist: &Uniform<f64>,
-        _rng: &mut ThreadRng,
-    ) -> Colour {
+    fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Colour {
         Colour::new(0.0, 0.0, 0.0)
     }
+
+    /// Clear glass doesn't tint the light passing through it, so its albedo is white.
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        Colour::new(1.0, 1.0, 1.0)
+    }
 }
 
 /// DiffuseLight materials emit light of a specified colour.
@@ -203,20 +253,18 @@ impl<T: Texture> Material for DiffuseLight<T> {
         &self,
         _rec: &HitRecord,
         _ray: &Ray,
-        _dist: &Uniform<f64>,
-        _rng: &mut ThreadRng,
+        _sampler: &mut dyn Sampler,
     ) -> Option<(Ray, Colour)> {
         None
     }
 
-    fn emitted(
-        &self,
-        u: f64,
-        v: f64,
-        p: Vec3,
-        _dist: &Uniform<f64>,
-        _rng: &mut ThreadRng,
-    ) -> Colour {
+    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Colour {
         self.emit.value(u, v, p)
     }
+
+    /// Lights are given the colour of their emission, clamped to the range an albedo can take.
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        let c = self.emit.value_at(rec);
+        Colour::new(c.r.min(1.0), c.g.min(1.0), c.b.min(1.0))
+    }
 }
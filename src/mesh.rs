@@ -0,0 +1,397 @@
+use crate::aabb::AABB;
+use crate::colour::Colour;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle::{self, TriangleHit};
+
+use crate::vec::Axis::{self, *};
+use crate::vec::Vec3;
+
+/// Most triangles kept in one leaf of a mesh's BVH.
+const LEAF_SIZE: usize = 4;
+
+/// A TriangleMesh is a set of triangles sharing one buffer of vertices and one material. Each face is three indices
+/// into the vertex buffers, so a vertex used by several faces is only stored once.
+///
+/// The mesh builds its own BVH over its faces when it's created. It's stored as a flat list of nodes rather than
+/// a tree of boxed Hittables, so even meshes with millions of faces stay compact, and the whole mesh is a single
+/// object to the world's BVH.
+pub struct TriangleMesh<M: Material> {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,   // Shading normals, one per vertex.
+    uvs: Option<Vec<(f64, f64)>>, // Texture coordinates, one per vertex.
+    colours: Option<Vec<Colour>>, // Vertex colours, for a VertexColourTexture.
+    faces: Vec<[u32; 3]>,         // Vertex indices of each face, in the order of the BVH leaves.
+    nodes: Vec<MeshNode>,
+    material: M,
+}
+
+/// A node of a mesh's BVH. The first child of an interior node always comes straight after it.
+struct MeshNode {
+    bounds: AABB,
+    contents: MeshNodeContents,
+}
+
+enum MeshNodeContents {
+    Leaf { start: usize, count: usize }, // Range of 'faces' in the leaf.
+    Interior { second: usize, axis: Axis }, // Index of the second child and the axis the children were split on.
+}
+
+/// A face with the bounds used to sort it into the BVH.
+struct BuildFace {
+    bounds: AABB,
+    centroid: Vec3,
+    face: [u32; 3],
+}
+
+impl<M: Material> TriangleMesh<M> {
+    /// Create a mesh from a vertex buffer and the indices of the three vertices of each face. Faces wind
+    /// anticlockwise around their front. Without any UVs, each face gets the default UVs of a Triangle.
+    ///
+    /// Panics if a face refers to a vertex that doesn't exist.
+    pub fn new(positions: Vec<Vec3>, faces: Vec<[u32; 3]>, material: M) -> Self {
+        if let Some(i) = faces
+            .iter()
+            .flatten()
+            .find(|&&i| i as usize >= positions.len())
+        {
+            panic!(
+                "mesh face refers to vertex {} but there are only {}",
+                i,
+                positions.len()
+            );
+        }
+
+        let mut build: Vec<BuildFace> = faces
+            .into_iter()
+            .map(|face| {
+                let bounds = AABB::padded_around(&face.map(|i| positions[i as usize]));
+                BuildFace {
+                    bounds,
+                    centroid: (bounds.min + bounds.max) * 0.5,
+                    face,
+                }
+            })
+            .collect();
+
+        let mut nodes = Vec::new();
+        if !build.is_empty() {
+            Self::build(&mut nodes, &mut build, 0);
+        }
+
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+            colours: None,
+            faces: build.into_iter().map(|f| f.face).collect(),
+            nodes,
+            material,
+        }
+    }
+
+    /// Interpolate the given per-vertex normals across each face.
+    ///
+    /// Panics if there isn't one normal for each vertex.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(
+            normals.len(),
+            self.positions.len(),
+            "a mesh needs one normal per vertex"
+        );
+        self.normals = Some(normals.into_iter().map(Vec3::normalise).collect());
+        self
+    }
+
+    /// Interpolate the given per-vertex texture coordinates across each face.
+    ///
+    /// Panics if there isn't one UV for each vertex.
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Self {
+        assert_eq!(
+            uvs.len(),
+            self.positions.len(),
+            "a mesh needs one UV per vertex"
+        );
+        self.uvs = Some(uvs);
+        self
+    }
+
+    /// Interpolate the given per-vertex colours across each face. They're passed to the material in the HitRecord,
+    /// where a VertexColourTexture picks them up.
+    ///
+    /// Panics if there isn't one colour for each vertex.
+    pub fn with_colours(mut self, colours: Vec<Colour>) -> Self {
+        assert_eq!(
+            colours.len(),
+            self.positions.len(),
+            "a mesh needs one colour per vertex"
+        );
+        self.colours = Some(colours);
+        self
+    }
+
+    /// Return the number of faces in the mesh.
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    /// Build the BVH over 'faces', which start at 'offset' in the mesh's list of faces, by splitting them in half
+    /// along the axis their centres are most spread out on. Returns the index of the new node.
+    fn build(nodes: &mut Vec<MeshNode>, faces: &mut [BuildFace], offset: usize) -> usize {
+        let bounds = faces[1..]
+            .iter()
+            .fold(faces[0].bounds, |acc, f| acc.merge(f.bounds));
+        let index = nodes.len();
+
+        if faces.len() <= LEAF_SIZE {
+            nodes.push(MeshNode {
+                bounds,
+                contents: MeshNodeContents::Leaf {
+                    start: offset,
+                    count: faces.len(),
+                },
+            });
+            return index;
+        }
+
+        let (min, max) =
+            faces[1..]
+                .iter()
+                .fold((faces[0].centroid, faces[0].centroid), |(min, max), f| {
+                    (
+                        min.zip_with(f.centroid, f64::min),
+                        max.zip_with(f.centroid, f64::max),
+                    )
+                });
+        let extent = max - min;
+        let axis = match (
+            extent[X] >= extent[Y],
+            extent[X] >= extent[Z],
+            extent[Y] >= extent[Z],
+        ) {
+            (true, true, _) => X,
+            (false, _, true) => Y,
+            _ => Z,
+        };
+
+        let mid = faces.len() / 2;
+        faces.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+
+        // Push this node before its children so the first child comes straight after it.
+        nodes.push(MeshNode {
+            bounds,
+            contents: MeshNodeContents::Interior { second: 0, axis },
+        });
+        let (left, right) = faces.split_at_mut(mid);
+        Self::build(nodes, left, offset);
+        let second = Self::build(nodes, right, offset + mid);
+        nodes[index].contents = MeshNodeContents::Interior { second, axis };
+        index
+    }
+
+    fn vertices(&self, face: [u32; 3]) -> [Vec3; 3] {
+        face.map(|i| self.positions[i as usize])
+    }
+}
+
+impl<M: Material> Hittable for TriangleMesh<M> {
+    fn hit(&self, r: &Ray, t_min: f64, mut t_max: f64) -> Option<HitRecord<'_>> {
+        // The BVH is balanced, so its depth is about log2 of the number of leaves and this never overflows.
+        let mut stack = [0usize; 64];
+        let mut len = if self.nodes.is_empty() { 0 } else { 1 };
+        let mut closest: Option<(TriangleHit, [u32; 3])> = None;
+
+        while len > 0 {
+            len -= 1;
+            let index = stack[len];
+            let node = &self.nodes[index];
+            if !node.bounds.hit(r, t_min, t_max) {
+                continue;
+            }
+
+            match node.contents {
+                MeshNodeContents::Leaf { start, count } => {
+                    for &face in &self.faces[start..start + count] {
+                        if let Some(hit) =
+                            triangle::intersect(&self.vertices(face), r, t_min, t_max)
+                        {
+                            t_max = hit.t;
+                            closest = Some((hit, face));
+                        }
+                    }
+                }
+                MeshNodeContents::Interior { second, axis } => {
+                    // Visit the child nearer the ray's origin first, so hits in it cut the far one short.
+                    let (near, far) = match r.direction[axis] < 0.0 {
+                        true => (second, index + 1),
+                        false => (index + 1, second),
+                    };
+                    stack[len] = far;
+                    stack[len + 1] = near;
+                    len += 2;
+                }
+            }
+        }
+
+        let (hit, face) = closest?;
+        let normals = self.normals.as_ref().map(|n| face.map(|i| n[i as usize]));
+        let uvs = self.uvs.as_ref().map(|uv| face.map(|i| uv[i as usize]));
+        let mut rec = hit.record(
+            r,
+            &self.vertices(face),
+            normals.as_ref(),
+            uvs.as_ref(),
+            &self.material,
+        );
+        rec.colour = self.colours.as_ref().map(|c| {
+            let [b0, b1, b2] = hit.b;
+            c[face[0] as usize] * b0 + c[face[1] as usize] * b1 + c[face[2] as usize] * b2
+        });
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg32;
+
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColour;
+
+    type Matte = Lambertian<SolidColour>;
+
+    fn matte() -> Matte {
+        Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)))
+    }
+
+    /// A unit square in the XY plane facing +Z, split along its diagonal from (0, 0) to (1, 1).
+    fn square() -> TriangleMesh<Matte> {
+        let positions = vec![
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(1.0, 1.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+        ];
+        TriangleMesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], matte())
+    }
+
+    fn random_vec(rng: &mut Pcg32, scale: f64) -> Vec3 {
+        Vec3(rng.gen(), rng.gen(), rng.gen()).map(|x: f64| (2.0 * x - 1.0) * scale)
+    }
+
+    #[test]
+    fn hits_nearest_face_like_brute_force() {
+        let mut rng = Pcg32::seed_from_u64(7);
+        let count = 300;
+        let mut positions = Vec::new();
+        for _ in 0..count {
+            let centre = random_vec(&mut rng, 5.0);
+            for _ in 0..3 {
+                positions.push(centre + random_vec(&mut rng, 0.5));
+            }
+        }
+        let faces: Vec<[u32; 3]> = (0..count as u32)
+            .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+            .collect();
+        // Every vertex of a face has the face's index as its 'u', so hits say which face they're on.
+        let uvs = (0..3 * count).map(|i| ((i / 3) as f64, 0.0)).collect();
+        let mesh = TriangleMesh::new(positions.clone(), faces.clone(), matte()).with_uvs(uvs);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            // Aim at the cloud of triangles from all around it, so rays run both ways along every axis.
+            let origin = random_vec(&mut rng, 10.0);
+            let r = Ray::new(origin, random_vec(&mut rng, 5.0) - origin, 0.0);
+
+            let nearest = faces
+                .iter()
+                .enumerate()
+                .filter_map(|(i, face)| {
+                    let vertices = face.map(|v| positions[v as usize]);
+                    triangle::intersect(&vertices, &r, 0.001, f64::MAX).map(|hit| (hit.t, i))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let rec = mesh.hit(&r, 0.001, f64::MAX);
+
+            match (nearest, rec) {
+                (None, None) => {}
+                (Some((t, face)), Some(rec)) => {
+                    hits += 1;
+                    assert_eq!(rec.t, t, "ray {r:?}");
+                    assert_eq!(rec.u.round() as usize, face, "ray {r:?}");
+                }
+                (nearest, rec) => panic!(
+                    "ray {r:?} hit {nearest:?} but the mesh found {:?}",
+                    rec.map(|rec| rec.t)
+                ),
+            }
+        }
+        assert!(hits > 200, "only {hits} rays hit anything");
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let mesh = TriangleMesh::new(Vec::new(), Vec::new(), matte());
+        assert!(mesh.is_empty());
+        assert!(mesh.bounding_box(0.0, 1.0).is_none());
+        let r = Ray::new(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0), 0.0);
+        assert!(mesh.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "refers to vertex 3")]
+    fn face_out_of_range() {
+        let positions = vec![Vec3(0.0, 0.0, 0.0); 3];
+        TriangleMesh::new(positions, vec![[0, 1, 3]], matte());
+    }
+
+    #[test]
+    #[should_panic(expected = "one normal per vertex")]
+    fn too_few_normals() {
+        square().with_normals(vec![Vec3(0.0, 0.0, 1.0); 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "one UV per vertex")]
+    fn too_many_uvs() {
+        square().with_uvs(vec![(0.0, 0.0); 5]);
+    }
+
+    #[test]
+    fn interpolated_uvs_and_normals() {
+        let s = 0.5f64.sqrt();
+        let mesh = square()
+            .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+            .with_normals(vec![
+                Vec3(0.0, 0.0, 1.0),
+                Vec3(1.0, 0.0, 1.0),
+                Vec3(0.0, 1.0, 1.0),
+                Vec3(-1.0, 0.0, 1.0),
+            ]);
+
+        // In the second face, where vertices 0, 2 and 3 have weights 0.25, 0.25 and 0.5.
+        let r = Ray::new(Vec3(0.25, 0.75, 1.0), Vec3(0.0, 0.0, -1.0), 0.0);
+        let rec = mesh.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12, "u is {}", rec.u);
+        assert!((rec.v - 0.75).abs() < 1e-12, "v is {}", rec.v);
+        let normal = Vec3(-0.5 * s, 0.25 * s, 0.25 + 0.75 * s).normalise();
+        assert!((rec.normal - normal).mag() < 1e-9, "{:?}", rec.normal);
+    }
+}
@@ -0,0 +1,65 @@
+//! Enums whose values are chosen by name, e.g. on the command line or in a checkpoint.
+
+use std::fmt;
+
+/// Error returned when parsing a name that none of the values of an enum have.
+#[derive(Clone, Debug)]
+pub struct UnknownName {
+    pub kind: &'static str, // What the name was for, e.g. "scene".
+    pub name: String,
+    pub expected: Vec<&'static str>, // The names that would have been accepted.
+}
+
+impl fmt::Display for UnknownName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown {} '{}' (expected one of: {})",
+            self.kind,
+            self.name,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownName {}
+
+/// Implement Display, FromStr and clap's ValueEnum for an enum with an 'ALL' array of its values and a 'name' method,
+/// so it's printed as and parsed from its name. 'kind' says what the names are for in error messages.
+macro_rules! named_enum {
+    ($type:ident, $kind:literal) => {
+        impl std::fmt::Display for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl std::str::FromStr for $type {
+            type Err = $crate::named::UnknownName;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::ALL
+                    .iter()
+                    .copied()
+                    .find(|value| value.name() == s)
+                    .ok_or_else(|| $crate::named::UnknownName {
+                        kind: $kind,
+                        name: s.to_string(),
+                        expected: Self::ALL.iter().map(|value| value.name()).collect(),
+                    })
+            }
+        }
+
+        impl clap::ValueEnum for $type {
+            fn value_variants<'a>() -> &'a [Self] {
+                &Self::ALL
+            }
+
+            fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+                Some(clap::builder::PossibleValue::new(self.name()))
+            }
+        }
+    };
+}
+
+pub(crate) use named_enum;
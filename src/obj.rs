@@ -0,0 +1,717 @@
+//! Loading Wavefront OBJ models and the MTL material libraries they use.
+//!
+//! Faces are grouped into one TriangleMesh for each object or group and material, with polygons split into fans of
+//! triangles. MTL materials are mapped onto the renderer's own materials:
+//!
+//! - An emissive colour ('Ke') makes a DiffuseLight.
+//! - Any transparency ('d' below 1 or 'Tr' above 0) makes a Dielectric with the index of refraction 'Ni', or 1.5 if
+//!   there isn't one.
+//! - A specular colour ('Ks') brighter than the diffuse colour ('Kd') makes a Metal, rougher the lower the specular
+//!   exponent 'Ns' is.
+//! - Anything else is Lambertian, textured with 'map_Kd' times 'Kd' if it has a texture.
+//!
+//! Faces using a material that isn't in any MTL file get the default grey Lambertian.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::colour::Colour;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::mesh::TriangleMesh;
+use crate::texture::{ImageTexture, SolidColour};
+use crate::vec::Vec3;
+
+/// Error returned when an OBJ or MTL file can't be read or doesn't make sense.
+#[derive(Clone, Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: Option<usize>, // Line of the file the problem is on, starting from 1, if it's on one.
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// One mesh of an OBJ model: the faces of an object or group that use the same material.
+pub struct ObjMesh {
+    pub name: String, // Name of the object or group, or empty if the faces weren't in one.
+    pub material: String, // Name of the MTL material. Empty or unknown names get the default grey Lambertian.
+    pub mesh: TriangleMesh<Arc<dyn Material>>, // Meshes with the same material share it.
+}
+
+/// Load the OBJ model at 'path', along with any MTL files and textures it refers to. Those are looked for relative to
+/// the file that names them.
+pub fn load_obj(path: &Path) -> Result<Vec<ObjMesh>, ObjError> {
+    let (builders, library) = parse_obj(&read_text(path)?, path)?;
+
+    let default: Arc<dyn Material> = Arc::new(Lambertian::new(SolidColour::new(Colour::new(
+        0.8, 0.8, 0.8,
+    ))));
+    Ok(builders
+        .into_iter()
+        .map(|b| {
+            let material = library.materials.get(&b.material).unwrap_or(&default);
+            let mut mesh = TriangleMesh::new(b.positions, b.faces, material.clone());
+            if b.has_uvs {
+                mesh = mesh.with_uvs(b.uvs);
+            }
+            if !b.missing_normals {
+                mesh = mesh.with_normals(b.normals);
+            }
+            ObjMesh {
+                name: b.name,
+                material: b.material,
+                mesh,
+            }
+        })
+        .collect())
+}
+
+/// Parse the text of the OBJ file at 'path' into the vertex buffers of each of its meshes, loading the MTL files it
+/// names.
+fn parse_obj(text: &str, path: &Path) -> Result<(Vec<MeshBuilder>, MaterialLibrary), ObjError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let error = |line: usize, message: String| ObjError {
+        path: path.to_path_buf(),
+        line: Some(line),
+        message,
+    };
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut library = MaterialLibrary::default();
+
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut lookup: HashMap<(String, String), usize> = HashMap::new();
+    let mut group = String::new();
+    let mut material = String::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let mut tokens = line.split('#').next().unwrap().split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let v = parse_floats(&args, 3, 3).map_err(|e| error(number, e))?;
+                positions.push(Vec3(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let uv = parse_floats(&args, 1, 2).map_err(|e| error(number, e))?;
+                uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "vn" => {
+                let v = parse_floats(&args, 3, 3).map_err(|e| error(number, e))?;
+                normals.push(Vec3(v[0], v[1], v[2]));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(
+                        number,
+                        "a face needs at least 3 vertices".to_string(),
+                    ));
+                }
+                let counts = (positions.len(), uvs.len(), normals.len());
+                let corners = args
+                    .iter()
+                    .map(|corner| parse_corner(corner, counts))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(|e| error(number, e))?;
+
+                let key = (group.clone(), material.clone());
+                let index = *lookup.entry(key).or_insert_with(|| {
+                    builders.push(MeshBuilder::new(&group, &material));
+                    builders.len() - 1
+                });
+                let builder = &mut builders[index];
+                let corners: Vec<u32> = corners
+                    .into_iter()
+                    .map(|c| builder.vertex(c, &positions, &uvs, &normals))
+                    .collect();
+                for k in 1..corners.len() - 1 {
+                    builder.faces.push([corners[0], corners[k], corners[k + 1]]);
+                }
+            }
+            "o" | "g" => group = args.join(" "),
+            "usemtl" => material = args.join(" "),
+            "mtllib" => {
+                for file in &args {
+                    // Errors inside the MTL file point there. Otherwise point at the line that named it.
+                    library.load(&dir.join(file)).map_err(|e| match e.line {
+                        Some(_) => e,
+                        None => error(number, format!("can't read '{}': {}", file, e.message)),
+                    })?;
+                }
+            }
+            // Smoothing groups, lines, points, curves and so on don't affect triangles.
+            _ => (),
+        }
+    }
+    Ok((builders, library))
+}
+
+/// The indices of a face's corner into the vertex buffers, counting from zero.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Parse a face corner like '1', '1/2', '1//3' or '1/2/3'. OBJ indices start at 1, and negative ones count back from
+/// the end of what's been read so far. 'counts' is the number of positions, UVs and normals read so far.
+fn parse_corner(corner: &str, counts: (usize, usize, usize)) -> Result<Corner, String> {
+    let resolve = |s: &str, count: usize, what: &str| -> Result<usize, String> {
+        let i: i64 = s
+            .parse()
+            .map_err(|_| format!("'{}' isn't a valid {} index", s, what))?;
+        let index = match i {
+            i if i > 0 => i - 1,
+            i if i < 0 => count as i64 + i,
+            _ => return Err(format!("{} indices start at 1, not 0", what)),
+        };
+        if index < 0 || index >= count as i64 {
+            return Err(format!(
+                "{} index {} is out of range, there are {}",
+                what, i, count
+            ));
+        }
+        Ok(index as usize)
+    };
+
+    let mut parts = corner.split('/');
+    let position = resolve(parts.next().unwrap(), counts.0, "vertex")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(s) => Some(resolve(s, counts.1, "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(s) => Some(resolve(s, counts.2, "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("'{}' isn't a valid face vertex", corner));
+    }
+    Ok(Corner {
+        position,
+        uv,
+        normal,
+    })
+}
+
+/// Parse between 'min' and 'max' numbers from 'args', ignoring any after 'max'. Returns 'max' numbers, padded with
+/// zeros if there were fewer.
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, String> {
+    if args.len() < min {
+        return Err(format!("expected at least {} numbers", min));
+    }
+    let mut values = args
+        .iter()
+        .take(max)
+        .map(|s| s.parse().map_err(|_| format!("'{}' isn't a number", s)))
+        .collect::<Result<Vec<f64>, String>>()?;
+    values.resize(max, 0.0);
+    Ok(values)
+}
+
+/// Return the file name of a texture map from the arguments of a statement like 'map_Kd'. Options like '-s 1 1 1'
+/// come before it and are skipped, and the rest is the file name, which may contain spaces.
+fn texture_file(args: &[&str]) -> Option<String> {
+    let mut rest = args;
+    while let Some((option, after)) = rest.split_first() {
+        let count = match *option {
+            "-blendu" | "-blendv" | "-boost" | "-cc" | "-clamp" | "-texres" | "-bm"
+            | "-imfchan" | "-type" => 1,
+            "-mm" => 2,
+            // Offsets, scales and turbulence have one to three numbers.
+            "-o" | "-s" | "-t" => after
+                .iter()
+                .take(3)
+                .take_while(|a| a.parse::<f64>().is_ok())
+                .count(),
+            _ => break,
+        };
+        rest = &after[count.min(after.len())..];
+    }
+    match rest.is_empty() {
+        true => None,
+        false => Some(rest.join(" ")),
+    }
+}
+
+/// Read a text file. Invalid UTF-8, which shows up in the comments of some exporters, is replaced.
+fn read_text(path: &Path) -> Result<String, ObjError> {
+    fs::read(path)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .map_err(|e| ObjError {
+            path: path.to_path_buf(),
+            line: None,
+            message: e.to_string(),
+        })
+}
+
+/// The vertex buffers of one mesh as it's read. OBJ faces index positions, UVs and normals separately, so each
+/// distinct combination becomes one vertex of the mesh.
+struct MeshBuilder {
+    name: String,
+    material: String,
+    positions: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    faces: Vec<[u32; 3]>,
+    vertices: HashMap<Corner, u32>,
+    has_uvs: bool,         // Some corner had a UV. Corners without one get (0, 0).
+    missing_normals: bool, // Some corner had no normal, so the mesh is shaded flat.
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            material: material.to_string(),
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            faces: Vec::new(),
+            vertices: HashMap::new(),
+            has_uvs: false,
+            missing_normals: false,
+        }
+    }
+
+    /// Return the index of the mesh's vertex for 'corner', adding it if it's new.
+    fn vertex(
+        &mut self,
+        corner: Corner,
+        positions: &[Vec3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> u32 {
+        if let Some(&index) = self.vertices.get(&corner) {
+            return index;
+        }
+        let index = self.positions.len() as u32;
+        self.positions.push(positions[corner.position]);
+        self.uvs.push(corner.uv.map_or((0.0, 0.0), |i| uvs[i]));
+        self.normals
+            .push(corner.normal.map_or(Vec3(0.0, 0.0, 0.0), |i| normals[i]));
+        self.has_uvs |= corner.uv.is_some();
+        self.missing_normals |= corner.normal.is_none();
+        self.vertices.insert(corner, index);
+        index
+    }
+}
+
+/// The properties of an MTL material that the renderer uses.
+struct MtlProperties {
+    kd: Option<Colour>,
+    ks: Colour,
+    ke: Colour,
+    ns: f64,
+    ni: Option<f64>,
+    opacity: f64,
+    map_kd: Option<ImageTexture>,
+}
+
+impl Default for MtlProperties {
+    fn default() -> Self {
+        Self {
+            kd: None,
+            ks: Colour::new(0.0, 0.0, 0.0),
+            ke: Colour::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: None,
+            opacity: 1.0,
+            map_kd: None,
+        }
+    }
+}
+
+impl MtlProperties {
+    fn material(self) -> Arc<dyn Material> {
+        let max = |c: Colour| c.r.max(c.g).max(c.b);
+        let kd = self.kd.unwrap_or(Colour::new(0.8, 0.8, 0.8));
+        if max(self.ke) > 0.0 {
+            Arc::new(DiffuseLight::new(SolidColour::new(self.ke)))
+        } else if self.opacity < 1.0 {
+            Arc::new(Dielectric::new(self.ni.unwrap_or(1.5)))
+        } else if max(self.ks) > max(kd) {
+            // Map the Phong exponent to a roughness as in "Microfacet Models for Refraction" by Walter et al.
+            let fuzz = (2.0 / (self.ns.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else {
+            // A texture is multiplied by 'Kd' only if there is one, as the default grey would darken it.
+            match self.map_kd {
+                Some(texture) => Arc::new(Lambertian::new(
+                    texture.tinted(self.kd.unwrap_or(Colour::new(1.0, 1.0, 1.0))),
+                )),
+                None => Arc::new(Lambertian::new(SolidColour::new(kd))),
+            }
+        }
+    }
+}
+
+/// The materials read from every MTL file an OBJ names, and the textures they use.
+#[derive(Default)]
+struct MaterialLibrary {
+    materials: HashMap<String, Arc<dyn Material>>,
+    textures: HashMap<PathBuf, ImageTexture>, // Textures already loaded, so materials sharing one share the image.
+}
+
+impl MaterialLibrary {
+    /// Read the materials in the MTL file at 'path'.
+    fn load(&mut self, path: &Path) -> Result<(), ObjError> {
+        let text = read_text(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let error = |line: usize, message: String| ObjError {
+            path: path.to_path_buf(),
+            line: Some(line),
+            message,
+        };
+
+        let mut current: Option<(String, MtlProperties)> = None;
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let mut tokens = line.split('#').next().unwrap().split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let args: Vec<&str> = tokens.collect();
+
+            if keyword == "newmtl" {
+                if let Some((name, properties)) = current.take() {
+                    self.materials.insert(name, properties.material());
+                }
+                current = Some((args.join(" "), MtlProperties::default()));
+                continue;
+            }
+            let properties = match &mut current {
+                Some((_, properties)) => properties,
+                None if is_property(keyword) => {
+                    return Err(error(number, format!("'{}' before any 'newmtl'", keyword)))
+                }
+                None => continue,
+            };
+
+            let colour = |args: &[&str]| -> Result<Colour, ObjError> {
+                // A single value is a grey. 'spectral' and 'xyz' colours aren't supported.
+                let c = parse_floats(args, 1, 3).map_err(|e| error(number, e))?;
+                Ok(match args.len() {
+                    1 => Colour::new(c[0], c[0], c[0]),
+                    _ => Colour::new(c[0], c[1], c[2]),
+                })
+            };
+            let number_arg = |args: &[&str]| -> Result<f64, ObjError> {
+                Ok(parse_floats(args, 1, 1).map_err(|e| error(number, e))?[0])
+            };
+
+            match keyword {
+                "Kd" => properties.kd = Some(colour(&args)?),
+                "Ks" => properties.ks = colour(&args)?,
+                "Ke" => properties.ke = colour(&args)?,
+                "Ns" => properties.ns = number_arg(&args)?,
+                "Ni" => properties.ni = Some(number_arg(&args)?),
+                "d" => properties.opacity = number_arg(&args)?,
+                "Tr" => properties.opacity = 1.0 - number_arg(&args)?,
+                "map_Kd" => {
+                    let file = texture_file(&args)
+                        .ok_or_else(|| error(number, "'map_Kd' needs a file name".to_string()))?;
+                    let file = dir.join(file);
+                    let texture = match self.textures.get(&file) {
+                        Some(texture) => texture.clone(),
+                        None => {
+                            let texture = ImageTexture::open(&file).map_err(|e| {
+                                error(number, format!("can't load '{}': {}", file.display(), e))
+                            })?;
+                            self.textures.insert(file, texture.clone());
+                            texture
+                        }
+                    };
+                    properties.map_kd = Some(texture);
+                }
+                _ => (),
+            }
+        }
+
+        if let Some((name, properties)) = current {
+            self.materials.insert(name, properties.material());
+        }
+        Ok(())
+    }
+}
+
+/// Whether 'keyword' sets one of the properties of a material that the renderer uses.
+fn is_property(keyword: &str) -> bool {
+    matches!(
+        keyword,
+        "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "map_Kd"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::image::Image;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    fn parse(text: &str) -> Result<Vec<MeshBuilder>, ObjError> {
+        parse_obj(text, Path::new("test.obj")).map(|(builders, _)| builders)
+    }
+
+    fn parse_error(text: &str) -> ObjError {
+        match parse(text) {
+            Ok(_) => panic!("expected an error parsing {:?}", text),
+            Err(e) => e,
+        }
+    }
+
+    /// Return the components of each vector, to compare them.
+    fn components(vectors: &[Vec3]) -> Vec<(f64, f64, f64)> {
+        vectors.iter().map(|v| (v.0, v.1, v.2)).collect()
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    /// Write 'files' to a new directory named after the test, run 'f' on the directory, then remove it.
+    fn with_files<R>(test: &str, files: &[(&str, &str)], f: impl FnOnce(&Path) -> R) -> R {
+        let dir = std::env::temp_dir().join(format!(
+            "ray_tracing_with_rust_{}_{}",
+            std::process::id(),
+            test
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let result = f(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    /// Return the name of a material's type, without its module or type parameters.
+    fn type_name(material: &dyn Material) -> &'static str {
+        let name = material.identity().1;
+        name.split('<').next().unwrap().rsplit("::").next().unwrap()
+    }
+
+    #[test]
+    fn quad_becomes_two_triangles() {
+        let builders = parse(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        assert_eq!(builders.len(), 1);
+        assert_eq!(builders[0].faces, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(builders[0].positions.len(), 4);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_end() {
+        let builders = parse(&format!("{}f -1 -2 -3\n", SQUARE)).unwrap();
+        assert_eq!(
+            components(&builders[0].positions),
+            vec![(0.0, 1.0, 0.0), (1.0, 1.0, 0.0), (1.0, 0.0, 0.0)]
+        );
+        assert_eq!(builders[0].faces, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn corners_can_have_uvs_and_normals() {
+        let text = format!(
+            "{}vt 0.25 0.5\nvt 0.75 0.5\nvn 0 0 1\nvn 0 0 -1\n\
+             f 1/1/1 2/2/1 3/1/1\ng back\nf 1//2 3//2 2//2\n",
+            SQUARE
+        );
+        let builders = parse(&text).unwrap();
+        assert_eq!(builders.len(), 2);
+
+        let (front, back) = (&builders[0], &builders[1]);
+        assert!(front.has_uvs && !front.missing_normals);
+        assert_eq!(front.uvs, vec![(0.25, 0.5), (0.75, 0.5), (0.25, 0.5)]);
+        assert_eq!(components(&front.normals), vec![(0.0, 0.0, 1.0); 3]);
+
+        assert_eq!(back.name, "back");
+        assert!(!back.has_uvs && !back.missing_normals);
+        assert_eq!(components(&back.normals), vec![(0.0, 0.0, -1.0); 3]);
+    }
+
+    #[test]
+    fn corners_shared_between_faces_are_one_vertex() {
+        // Corners only share a vertex when their UVs and normals match as well as their positions.
+        let text = format!(
+            "{}vt 0 0\nvt 1 1\nf 1/1 2/1 3/1\nf 1/1 3/1 4/2\nf 1/2 2/2 4/2\n",
+            SQUARE
+        );
+        let builders = parse(&text).unwrap();
+        assert_eq!(builders[0].positions.len(), 6);
+        assert_eq!(builders[0].faces, vec![[0, 1, 2], [0, 2, 3], [4, 5, 3]]);
+    }
+
+    #[test]
+    fn errors_give_the_line_they_are_on() {
+        let error = parse_error("v 0 0 0\n\n# comment\nv 1 0 0\nv 0 1 0\nf 1 2 4\n");
+        assert_eq!(error.line, Some(6));
+        assert!(error.message.contains("out of range"), "{}", error.message);
+        assert_eq!(error.to_string(), format!("test.obj:6: {}", error.message));
+
+        let error = parse_error("v 0 0 0\nvt x 0\n");
+        assert_eq!(error.line, Some(2));
+        let error = parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 0 2\n");
+        assert_eq!(error.line, Some(4));
+    }
+
+    #[test]
+    fn unknown_materials_are_not_an_error() {
+        let builders = parse(&format!("{}usemtl missing\nf 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(builders[0].material, "missing");
+    }
+
+    #[test]
+    fn texture_options_are_skipped() {
+        let file = |s: &str| texture_file(&s.split_whitespace().collect::<Vec<_>>());
+        assert_eq!(file("wood.png").as_deref(), Some("wood.png"));
+        assert_eq!(file("my wood.png").as_deref(), Some("my wood.png"));
+        assert_eq!(
+            file("-s 2 2 1 -o 0.5 -clamp on -mm 0 1 my wood.png").as_deref(),
+            Some("my wood.png")
+        );
+        assert_eq!(file("-blendu off").as_deref(), None);
+        assert_eq!(file("").as_deref(), None);
+    }
+
+    #[test]
+    fn texture_is_multiplied_by_kd() {
+        let texture = ImageTexture::new(Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Colour::new(0.5, 1.0, 0.25)],
+        });
+        let albedo = |kd| {
+            let material = MtlProperties {
+                kd,
+                map_kd: Some(texture.clone()),
+                ..MtlProperties::default()
+            }
+            .material();
+            let sphere = Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, material);
+            let r = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0), 0.0);
+            let rec = sphere.hit(&r, 0.001, f64::MAX).unwrap();
+            rec.material.albedo(&rec)
+        };
+        assert_eq!(albedo(None), Colour::new(0.5, 1.0, 0.25));
+        assert_eq!(
+            albedo(Some(Colour::new(0.5, 0.5, 1.0))),
+            Colour::new(0.25, 0.5, 0.25)
+        );
+    }
+
+    #[test]
+    fn mtl_properties_choose_the_material() {
+        let mtl = "newmtl plain\nKd 0.2 0.4 0.6\n\
+                   newmtl shiny\nKd 0.1 0.1 0.1\nKs 0.9 0.9 0.9\nNs 200\n\
+                   newmtl dull\nKd 0.5 0.5 0.5\nKs 0.2 0.2 0.2\nNs 10\n\
+                   newmtl lamp\nKd 0.5 0.5 0.5\nKe 4 4 4\n\
+                   newmtl glass\nKs 1 1 1\nd 0.5\nNi 1.3\n\
+                   newmtl water\nTr 0.9\n\
+                   newmtl opaque\nd 1\nTr 0\n";
+        let library = with_files("mtl_properties", &[("test.mtl", mtl)], |dir| {
+            let mut library = MaterialLibrary::default();
+            library.load(&dir.join("test.mtl")).unwrap();
+            library
+        });
+
+        let mut types: Vec<(&str, &str)> = library
+            .materials
+            .iter()
+            .map(|(name, material)| (name.as_str(), type_name(material.as_ref())))
+            .collect();
+        types.sort();
+        assert_eq!(
+            types,
+            vec![
+                ("dull", "Lambertian"),
+                ("glass", "Dielectric"),
+                ("lamp", "DiffuseLight"),
+                ("opaque", "Lambertian"),
+                ("plain", "Lambertian"),
+                ("shiny", "Metal"),
+                ("water", "Dielectric"),
+            ]
+        );
+    }
+
+    #[test]
+    fn mtllib_and_usemtl_name_the_materials_of_faces() {
+        let obj = format!(
+            "mtllib test.mtl\n{}f 1 2 3 4\nusemtl shiny\nf 1 2 3 4\nusemtl red\nf 1 2 3 4\n\
+             usemtl missing\nf 1 2 3 4\n",
+            SQUARE
+        );
+        let mtl = "newmtl red\nKd 1 0 0\n\nnewmtl shiny\nKs 0.9 0.8 0.7\nNs 100\n";
+        let meshes = with_files(
+            "mtllib_and_usemtl",
+            &[("test.obj", &obj), ("test.mtl", mtl)],
+            |dir| load_obj(&dir.join("test.obj")).unwrap(),
+        );
+
+        let r = Ray::new(Vec3(0.25, 0.25, 5.0), Vec3(0.0, 0.0, -1.0), 0.0);
+        let materials: Vec<(&str, &str, Colour)> = meshes
+            .iter()
+            .map(|m| {
+                let rec = m.mesh.hit(&r, 0.001, f64::MAX).unwrap();
+                let albedo = rec.material.albedo(&rec);
+                (m.material.as_str(), type_name(rec.material), albedo)
+            })
+            .collect();
+        let grey = Colour::new(0.8, 0.8, 0.8);
+        assert_eq!(
+            materials,
+            vec![
+                ("", "Lambertian", grey),
+                ("shiny", "Metal", Colour::new(0.9, 0.8, 0.7)),
+                ("red", "Lambertian", Colour::new(1.0, 0.0, 0.0)),
+                ("missing", "Lambertian", grey),
+            ]
+        );
+    }
+
+    #[test]
+    fn mtl_errors_give_the_line_they_are_on() {
+        let obj = format!("mtllib test.mtl\n{}f 1 2 3\n", SQUARE);
+        let mtl = "# Exported by hand\n\nillum 2\nKd 1 1 1\nnewmtl red\nKd 1 0 0\n";
+        let (dir, error) = with_files(
+            "mtl_errors",
+            &[("test.obj", &obj), ("test.mtl", mtl)],
+            |dir| {
+                let error = match load_obj(&dir.join("test.obj")) {
+                    Ok(_) => panic!("expected an error loading the MTL file"),
+                    Err(e) => e,
+                };
+                (dir.to_path_buf(), error)
+            },
+        );
+        assert_eq!(error.path, dir.join("test.mtl"));
+        assert_eq!(error.line, Some(4));
+        assert!(
+            error.message.contains("'Kd' before any 'newmtl'"),
+            "{}",
+            error.message
+        );
+    }
+}
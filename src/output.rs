@@ -0,0 +1,578 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use exr::prelude as exrs;
+use exr::prelude::WritableImage;
+
+use crate::colour::Colour;
+use crate::image::Image;
+use crate::named::named_enum;
+use crate::tonemap::PostProcess;
+
+/// The file formats an Image can be written as.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png, // 8 or 16 bits per channel, post processed for display.
+    Ppm, // Binary (P6) PPM, 8 bits per channel, post processed for display.
+    Pfm, // Portable float map, linear 32 bit floats.
+    Hdr, // Radiance RGBE, linear.
+    Exr, // OpenEXR, linear 32 bit floats. Stores extra layers alongside the beauty pass.
+}
+
+impl ImageFormat {
+    /// Choose a format from the extension of 'path'.
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match ext.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("exr") => Ok(ImageFormat::Exr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "can't tell the image format of '{}' (expected a .png, .ppm, .pfm, .hdr or .exr extension)",
+                    path.display()
+                ),
+            )),
+        }
+    }
+
+    /// Whether the format stores linear values rather than values encoded for display.
+    pub fn is_hdr(self) -> bool {
+        matches!(self, ImageFormat::Pfm | ImageFormat::Hdr | ImageFormat::Exr)
+    }
+}
+
+/// Number of bits used for each channel of a PNG.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    pub const ALL: [BitDepth; 2] = [BitDepth::Eight, BitDepth::Sixteen];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BitDepth::Eight => "8",
+            BitDepth::Sixteen => "16",
+        }
+    }
+}
+
+named_enum!(BitDepth, "bit depth");
+
+/// Compression used for the pixel data of an EXR.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExrCompression {
+    None,
+    Rle,  // Run length encoding.
+    Zips, // Zlib, one scanline per block.
+    Zip,  // Zlib, sixteen scanlines per block.
+}
+
+impl ExrCompression {
+    pub const ALL: [ExrCompression; 4] = [
+        ExrCompression::None,
+        ExrCompression::Rle,
+        ExrCompression::Zips,
+        ExrCompression::Zip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExrCompression::None => "none",
+            ExrCompression::Rle => "rle",
+            ExrCompression::Zips => "zips",
+            ExrCompression::Zip => "zip",
+        }
+    }
+}
+
+named_enum!(ExrCompression, "EXR compression");
+
+impl From<ExrCompression> for exrs::Compression {
+    fn from(c: ExrCompression) -> Self {
+        match c {
+            ExrCompression::None => exrs::Compression::Uncompressed,
+            ExrCompression::Rle => exrs::Compression::RLE,
+            ExrCompression::Zips => exrs::Compression::ZIP1,
+            ExrCompression::Zip => exrs::Compression::ZIP16,
+        }
+    }
+}
+
+/// Format specific settings for writing images.
+#[derive(Copy, Clone, Debug)]
+pub struct OutputOptions {
+    pub bit_depth: BitDepth,             // Only used for PNGs.
+    pub exr_compression: ExrCompression, // Only used for EXRs.
+    pub post: PostProcess,               // Only used for formats that aren't HDR.
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            bit_depth: BitDepth::Eight,
+            exr_compression: ExrCompression::Zip,
+            post: PostProcess::default(),
+        }
+    }
+}
+
+/// An auxiliary image written alongside the beauty pass, such as a normal or depth buffer.
+#[derive(Copy, Clone, Debug)]
+pub struct Layer<'a> {
+    pub name: &'a str,
+    pub image: &'a Image,
+}
+
+/// Quantise a display image to 8 bits per channel, three bytes per pixel. Values are clamped to [0, 1].
+pub fn to_rgb8(image: &Image) -> Vec<u8> {
+    image
+        .pixels
+        .iter()
+        .flat_map(|c| [c.r, c.g, c.b])
+        .map(|c| (255.999 * c.clamp(0.0, 1.0)) as u8)
+        .collect()
+}
+
+/// Quantise a display image to 16 bits per channel, three channels per pixel. Values are clamped to [0, 1].
+pub fn to_rgb16(image: &Image) -> Vec<u16> {
+    image
+        .pixels
+        .iter()
+        .flat_map(|c| [c.r, c.g, c.b])
+        .map(|c| (65535.999 * c.clamp(0.0, 1.0)) as u16)
+        .collect()
+}
+
+/// Write the linear image to 'path', choosing the format from the file extension. Formats that aren't HDR are post
+/// processed for display first.
+///
+/// EXRs store 'layers' in the same file as the image. Other formats write each layer to its own file next to the
+/// image, named '<stem>.<layer>.<ext>'. Layers are never post processed, so for formats that aren't HDR they should
+/// already hold values in [0, 1].
+pub fn write_image(
+    image: &Image,
+    layers: &[Layer],
+    path: &Path,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    let format = ImageFormat::from_path(path)?;
+    if format == ImageFormat::Exr {
+        return write_exr(image, layers, path, options.exr_compression);
+    }
+
+    if format.is_hdr() {
+        write_single(image, path, format, options)?;
+    } else {
+        write_single(&options.post.apply_image(image), path, format, options)?;
+    }
+
+    for layer in layers {
+        write_single(layer.image, &layer_path(path, layer.name), format, options)?;
+    }
+    Ok(())
+}
+
+/// Return the path a layer is written to for formats that only hold one image: '<stem>.<layer>.<ext>'.
+pub fn layer_path(path: &Path, layer: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    path.with_file_name(format!("{}.{}.{}", stem, layer, ext))
+}
+
+/// Write one image in a format that only holds one image. Display formats expect values that are already post
+/// processed.
+fn write_single(
+    image: &Image,
+    path: &Path,
+    format: ImageFormat,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    if format == ImageFormat::Exr {
+        return write_exr(image, &[], path, options.exr_compression);
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png => write_png(image, &mut out, options.bit_depth)?,
+        ImageFormat::Ppm => write_ppm(image, &mut out)?,
+        ImageFormat::Pfm => write_pfm(image, &mut out)?,
+        ImageFormat::Hdr => write_hdr(image, &mut out)?,
+        ImageFormat::Exr => unreachable!(),
+    }
+    out.flush()
+}
+
+/// Write a display image as a PNG with 8 or 16 bits per channel.
+pub fn write_png(image: &Image, out: &mut impl Write, depth: BitDepth) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgb);
+
+    let data = match depth {
+        BitDepth::Eight => {
+            encoder.set_depth(png::BitDepth::Eight);
+            to_rgb8(image)
+        }
+        BitDepth::Sixteen => {
+            encoder.set_depth(png::BitDepth::Sixteen);
+            to_rgb16(image)
+                .iter()
+                .flat_map(|c| c.to_be_bytes())
+                .collect()
+        }
+    };
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Write a display image as a binary (P6) PPM.
+pub fn write_ppm(image: &Image, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", image.width, image.height)?;
+    out.write_all(&to_rgb8(image))
+}
+
+/// Write the image as a little endian PFM. PFM stores rows from the bottom of the image to the top.
+pub fn write_pfm(image: &Image, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+
+    for row in image.pixels.chunks(image.width as usize).rev() {
+        for c in row {
+            out.write_all(&(c.r as f32).to_le_bytes())?;
+            out.write_all(&(c.g as f32).to_le_bytes())?;
+            out.write_all(&(c.b as f32).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert a Colour to Radiance's shared exponent format: an 8 bit mantissa per channel and one common exponent.
+/// Channels that aren't finite are written as 0, and ones too bright for the format's largest exponent as the
+/// brightest value it can hold.
+fn to_rgbe(c: Colour) -> [u8; 4] {
+    let channel = |x: f64| if x.is_finite() { x.max(0.0) } else { 0.0 };
+    let (r, g, b) = (channel(c.r), channel(c.g), channel(c.b));
+    let max = r.max(g).max(b);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // Split 'max' into a mantissa in [0.5, 1) and a power of two exponent. Past the largest exponent, the mantissas
+    // saturate at 255 when they're cast.
+    let exponent = (max.log2().floor() as i32 + 1).min(127);
+    let scale = 256.0 / 2f64.powi(exponent);
+
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+/// Write the image as an uncompressed Radiance RGBE (.hdr) file.
+pub fn write_hdr(image: &Image, out: &mut impl Write) -> io::Result<()> {
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )?;
+
+    for c in image.pixels.iter() {
+        out.write_all(&to_rgbe(*c))?;
+    }
+    Ok(())
+}
+
+/// Split an image into three flat channels of 32 bit floats named '<prefix>R', '<prefix>G' and '<prefix>B'.
+fn exr_channels(image: &Image, prefix: &str) -> [exrs::AnyChannel<exrs::FlatSamples>; 3] {
+    let channel = |name: &str, f: fn(&Colour) -> f64| {
+        let samples = image.pixels.iter().map(|c| f(c) as f32).collect();
+        exrs::AnyChannel::new(
+            format!("{}{}", prefix, name).as_str(),
+            exrs::FlatSamples::F32(samples),
+        )
+    };
+
+    [
+        channel("R", |c| c.r),
+        channel("G", |c| c.g),
+        channel("B", |c| c.b),
+    ]
+}
+
+/// Write the image as a single part scanline OpenEXR. The image is stored in the R, G and B channels and each layer
+/// in channels prefixed with its name, e.g. 'albedo.R', which compositing packages show as separate layers.
+pub fn write_exr(
+    image: &Image,
+    layers: &[Layer],
+    path: &Path,
+    compression: ExrCompression,
+) -> io::Result<()> {
+    let mut channels: Vec<_> = exr_channels(image, "").into_iter().collect();
+    for layer in layers {
+        if (layer.image.width, layer.image.height) != (image.width, image.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("layer '{}' is not the same size as the image", layer.name),
+            ));
+        }
+        channels.extend(exr_channels(layer.image, &format!("{}.", layer.name)));
+    }
+
+    let encoding = exrs::Encoding {
+        compression: compression.into(),
+        ..exrs::Encoding::default()
+    };
+
+    let layer = exrs::Layer::new(
+        (image.width as usize, image.height as usize),
+        exrs::LayerAttributes::default(),
+        encoding,
+        exrs::AnyChannels::sort(channels.into()),
+    );
+
+    exrs::Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|e| match e {
+            exr::error::Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image whose pixels all differ, with values that aren't exact in fewer than 32 bits.
+    fn gradient(width: u32, height: u32, offset: f64) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let c = Colour::new(
+                    offset + x as f64 / 7.0,
+                    offset - y as f64 * 1.1,
+                    (x * y) as f64 * 1e-3 + offset * 100.0,
+                );
+                image.set(x, y, c);
+            }
+        }
+        image
+    }
+
+    /// Return the samples of the channel called 'name' in an EXR layer read back from a file.
+    fn channel<'a>(
+        layer: &'a exrs::Layer<exrs::AnyChannels<exrs::FlatSamples>>,
+        name: &str,
+    ) -> &'a [f32] {
+        let channel = layer
+            .channel_data
+            .list
+            .iter()
+            .find(|c| c.name == *name)
+            .unwrap_or_else(|| panic!("no channel '{name}'"));
+        match &channel.sample_data {
+            exrs::FlatSamples::F32(samples) => samples,
+            _ => panic!("channel '{name}' isn't 32 bit floats"),
+        }
+    }
+
+    /// A display image with two rows, including values outside [0, 1] that have to be clamped.
+    fn display_image() -> Image {
+        let mut image = Image::new(3, 2);
+        image.set(0, 0, Colour::new(1.0, 0.0, 0.5));
+        image.set(1, 0, Colour::new(0.25, 2.0, -1.0));
+        image.set(2, 0, Colour::new(0.1, 0.2, 0.3));
+        image.set(0, 1, Colour::new(0.0, 0.0, 0.0));
+        image.set(1, 1, Colour::new(0.75, 1.0, 0.999));
+        image.set(2, 1, Colour::new(0.5, 0.5, 0.5));
+        image
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let image = display_image();
+        for depth in [BitDepth::Eight, BitDepth::Sixteen] {
+            let mut bytes = Vec::new();
+            write_png(&image, &mut bytes, depth).unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buf).unwrap();
+            assert_eq!((info.width, info.height), (3, 2));
+            assert_eq!(info.color_type, png::ColorType::Rgb);
+
+            let expected: Vec<u8> = match depth {
+                BitDepth::Eight => to_rgb8(&image),
+                BitDepth::Sixteen => to_rgb16(&image)
+                    .iter()
+                    .flat_map(|c| c.to_be_bytes())
+                    .collect(),
+            };
+            assert_eq!(&buf[..info.buffer_size()], expected);
+        }
+        assert_eq!(to_rgb8(&image)[..6], [255, 0, 127, 63, 255, 0]);
+        assert_eq!(to_rgb16(&image)[..3], [65535, 0, 32767]);
+    }
+
+    #[test]
+    fn ppm_bytes() {
+        let mut bytes = Vec::new();
+        write_ppm(&display_image(), &mut bytes).unwrap();
+        let mut expected = b"P6\n3 2\n255\n".to_vec();
+        expected.extend(to_rgb8(&display_image()));
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn pfm_is_little_endian_from_the_bottom_row() {
+        let image = gradient(3, 2, 0.5);
+        let mut bytes = Vec::new();
+        write_pfm(&image, &mut bytes).unwrap();
+
+        // A negative scale marks the data as little endian.
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        let expected: Vec<f32> = [1, 0]
+            .iter()
+            .flat_map(|&y| (0..3).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let c = image.get(x, y);
+                [c.r as f32, c.g as f32, c.b as f32]
+            })
+            .collect();
+        assert_eq!(floats, expected);
+    }
+
+    #[test]
+    fn rgbe_shares_the_largest_channel_exponent() {
+        assert_eq!(to_rgbe(Colour::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(Colour::new(1e-40, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(Colour::new(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+        assert_eq!(to_rgbe(Colour::new(3.0, 0.0, -1.0)), [192, 0, 0, 130]);
+        assert_eq!(to_rgbe(Colour::new(0.0, 0.0, 0.1)), [0, 0, 204, 125]);
+
+        // Decoding gives back each channel to within one step of the shared mantissa.
+        for c in [
+            Colour::new(0.3, 7.5, 1e-3),
+            Colour::new(1234.5, 0.001, 99.0),
+        ] {
+            let [r, g, b, e] = to_rgbe(c);
+            let step = 2f64.powi(e as i32 - 136);
+            for (channel, value) in [(r, c.r), (g, c.g), (b, c.b)] {
+                let decoded = channel as f64 * step;
+                assert!(
+                    decoded <= value && value < decoded + step,
+                    "{value} became {decoded}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rgbe_of_non_finite_and_huge_values() {
+        assert_eq!(
+            to_rgbe(Colour::new(f64::INFINITY, 1e40, 1.0)),
+            [0, 255, 0, 255]
+        );
+        assert_eq!(
+            to_rgbe(Colour::new(f64::NAN, f64::NEG_INFINITY, 0.5)),
+            [0, 0, 128, 128]
+        );
+        assert_eq!(to_rgbe(Colour::new(f64::INFINITY, 0.0, 0.0)), [0, 0, 0, 0]);
+
+        let mut image = Image::new(1, 1);
+        image.set(0, 0, Colour::new(f64::INFINITY, 1e40, 1.0));
+        let mut bytes = Vec::new();
+        write_hdr(&image, &mut bytes).unwrap();
+        assert_eq!(bytes[bytes.len() - 4..], [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn hdr_is_written_from_the_top_row() {
+        let image = display_image();
+        let mut bytes = Vec::new();
+        write_hdr(&image, &mut bytes).unwrap();
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        let pixels: Vec<u8> = image.pixels.iter().flat_map(|&c| to_rgbe(c)).collect();
+        assert_eq!(bytes[header.len()..], pixels[..]);
+        assert_eq!(bytes[header.len()..header.len() + 4], [128, 0, 64, 129]);
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let (width, height) = (5, 3);
+        let image = gradient(width, height, 0.5);
+        let albedo = gradient(width, height, 0.25);
+        let depth = gradient(width, height, 12.0);
+        let layers = [
+            Layer {
+                name: "albedo",
+                image: &albedo,
+            },
+            Layer {
+                name: "depth",
+                image: &depth,
+            },
+        ];
+
+        for compression in ExrCompression::ALL {
+            let path = std::env::temp_dir().join(format!(
+                "ray_tracing_with_rust_{}_{}.exr",
+                std::process::id(),
+                compression
+            ));
+            write_exr(&image, &layers, &path, compression).unwrap();
+            let read = exrs::read_all_flat_layers_from_file(&path);
+            std::fs::remove_file(&path).unwrap();
+            let read = read.unwrap();
+
+            assert_eq!(read.layer_data.len(), 1);
+            let layer = &read.layer_data[0];
+            assert_eq!(layer.size, exrs::Vec2(width as usize, height as usize));
+            assert_eq!(layer.encoding.compression, compression.into());
+
+            let names: Vec<String> = layer
+                .channel_data
+                .list
+                .iter()
+                .map(|c| c.name.to_string())
+                .collect();
+            let expected = [
+                "B", "G", "R", "albedo.B", "albedo.G", "albedo.R", "depth.B", "depth.G", "depth.R",
+            ];
+            assert_eq!(names, expected, "{compression} compression");
+
+            for (prefix, image) in [("", &image), ("albedo.", &albedo), ("depth.", &depth)] {
+                let pixels = |f: fn(&Colour) -> f64| -> Vec<f32> {
+                    image.pixels.iter().map(|c| f(c) as f32).collect()
+                };
+                assert_eq!(channel(layer, &format!("{prefix}R")), pixels(|c| c.r));
+                assert_eq!(channel(layer, &format!("{prefix}G")), pixels(|c| c.g));
+                assert_eq!(channel(layer, &format!("{prefix}B")), pixels(|c| c.b));
+            }
+        }
+    }
+}
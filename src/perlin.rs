@@ -3,8 +3,7 @@ use crate::vec::Vec3;
 
 use rand::Rng;
 
-fn perlin_generate() -> Vec<f64> {
-    let mut rng = rand::thread_rng();
+fn perlin_generate(rng: &mut impl Rng) -> Vec<f64> {
     let mut p = Vec::with_capacity(256);
 
     for _ in 0..256 {
@@ -13,20 +12,19 @@ fn perlin_generate() -> Vec<f64> {
     p
 }
 
-fn permute(p: &mut [usize], n: usize) {
-    let mut rng = rand::thread_rng();
+fn permute(p: &mut [usize], n: usize, rng: &mut impl Rng) {
     for i in (0..n).rev() {
         let target = rng.gen_range(0, i + 1);
         p.swap(i, target);
     }
 }
 
-fn perlin_generate_perm() -> Vec<usize> {
+fn perlin_generate_perm(rng: &mut impl Rng) -> Vec<usize> {
     let mut p = Vec::with_capacity(256);
     for i in 0..256 {
         p.push(i);
     }
-    permute(&mut p, 256);
+    permute(&mut p, 256, rng);
     p
 }
 
@@ -39,12 +37,13 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    /// Generate a new random lattice. The same 'rng' state always produces the same noise.
+    pub fn new(rng: &mut impl Rng) -> Self {
         Self {
-            ran_float: perlin_generate(),
-            perm_x: perlin_generate_perm(),
-            perm_y: perlin_generate_perm(),
-            perm_z: perlin_generate_perm(),
+            ran_float: perlin_generate(rng),
+            perm_x: perlin_generate_perm(rng),
+            perm_y: perlin_generate_perm(rng),
+            perm_z: perlin_generate_perm(rng),
         }
     }
 
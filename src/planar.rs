@@ -0,0 +1,354 @@
+//! Flat shapes: parallelograms, disks, rings and whole planes. They can be hit from either side, but each has a front,
+//! the side its normal points to, which Dielectrics use to tell rays going in from rays coming out.
+
+use std::f64::consts::PI;
+
+use crate::aabb::AABB;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+
+use crate::vec::Vec3;
+
+/// A Quad is a parallelogram with one corner at 'origin' and the two edges from it 'u' and 'v'. Its front is the side
+/// 'u' turns anticlockwise towards 'v' when seen from, and the UV at a point is how far along each edge it is.
+///
+/// The axis-aligned rectangles in 'rect' are Quads along the axes.
+#[derive(Debug, Clone, Copy)]
+pub struct Quad<M: Material> {
+    plane: Plane,
+    material: M,
+}
+
+impl<M: Material> Quad<M> {
+    /// Create a Quad with a corner at 'origin' and the edges 'u' and 'v'.
+    ///
+    /// Panics if the edges are parallel, which would make the Quad a line.
+    pub fn new(origin: Vec3, u: Vec3, v: Vec3, material: M) -> Self {
+        Self {
+            plane: Plane::new(origin, u, v),
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Quad<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, alpha, beta) = self.plane.intersect(r, t_min, t_max)?;
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(self.plane.record(r, t, alpha, beta, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let Plane { origin, u, v, .. } = self.plane;
+        Some(AABB::padded_around(&[
+            origin,
+            origin + u,
+            origin + v,
+            origin + u + v,
+        ]))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// A flat Disk facing along 'normal'. UVs go around the Disk's edge anticlockwise, seen from the front, and out from
+/// its centre: 'u' is the angle as a fraction of a turn and 'v' the distance from the centre as a fraction of the
+/// radius.
+#[derive(Debug, Clone, Copy)]
+pub struct Disk<M: Material> {
+    ring: Annulus<M>,
+}
+
+impl<M: Material> Disk<M> {
+    pub fn new(centre: Vec3, normal: Vec3, radius: f64, material: M) -> Self {
+        Self {
+            ring: Annulus::new(centre, normal, 0.0, radius, material),
+        }
+    }
+}
+
+impl<M: Material> Hittable for Disk<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.ring.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.ring.bounding_box(t0, t1)
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.ring.materials()
+    }
+}
+
+/// A flat ring between two circles around 'centre', facing along 'normal'. UVs are like a Disk's, except that 'v'
+/// runs from 0 on the inner edge to 1 on the outer one.
+#[derive(Debug, Clone, Copy)]
+pub struct Annulus<M: Material> {
+    plane: Plane, // Centred on the ring, with unit edges along the directions of zero and a quarter turn.
+    inner: f64,
+    outer: f64,
+    material: M,
+}
+
+impl<M: Material> Annulus<M> {
+    /// Create a ring between the radii 'inner' and 'outer'.
+    ///
+    /// Panics unless 0 <= 'inner' < 'outer'.
+    pub fn new(centre: Vec3, normal: Vec3, inner: f64, outer: f64, material: M) -> Self {
+        assert!(
+            0.0 <= inner && inner < outer,
+            "an annulus needs 0 <= inner radius < outer radius, not {} and {}",
+            inner,
+            outer
+        );
+        let (u, v) = perpendicular_basis(normal.normalise());
+        Self {
+            plane: Plane::new(centre, u, v),
+            inner,
+            outer,
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Annulus<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, x, y) = self.plane.intersect(r, t_min, t_max)?;
+        let distance = x.hypot(y);
+        if distance < self.inner || distance > self.outer {
+            return None;
+        }
+
+        let u = y.atan2(x).rem_euclid(2.0 * PI) / (2.0 * PI);
+        let v = (distance - self.inner) / (self.outer - self.inner);
+        Some(self.plane.record(r, t, u, v, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        // The circle reaches out by its radius times the sine of the angle between the normal and each axis.
+        let n = self.plane.normal;
+        let extent = n.map(|x| self.outer * (1.0 - x * x).max(0.0).sqrt());
+        let centre = self.plane.origin;
+        Some(AABB::padded_around(&[centre - extent, centre + extent]))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// A plane through 'point' that goes on forever, facing along 'normal'. The UV at a point is how far it is from
+/// 'point' along two directions in the plane, X and -Z for a plane facing up, so UVs aren't limited to 0 to 1.
+///
+/// It has no bounding box, so a BVH keeps it beside the tree rather than in it.
+#[derive(Debug, Clone, Copy)]
+pub struct InfinitePlane<M: Material> {
+    plane: Plane, // Through 'point', with unit edges along the directions UVs are measured in.
+    material: M,
+}
+
+impl<M: Material> InfinitePlane<M> {
+    pub fn new(point: Vec3, normal: Vec3, material: M) -> Self {
+        let (u, v) = perpendicular_basis(normal.normalise());
+        Self {
+            plane: Plane::new(point, u, v),
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for InfinitePlane<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, u, v) = self.plane.intersect(r, t_min, t_max)?;
+        Some(self.plane.record(r, t, u, v, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        None
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// The plane through 'origin' along the edges 'u' and 'v', which points on it are measured along.
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3, // Unit normal, 'u' cross 'v'.
+    w: Vec3, // 'u' cross 'v' divided by its squared length, for finding where points are along the edges.
+}
+
+impl Plane {
+    fn new(origin: Vec3, u: Vec3, v: Vec3) -> Self {
+        let n = u.cross(v);
+        assert!(
+            n.mag_sqr() > 0.0,
+            "the edges of a flat shape can't be parallel"
+        );
+        Self {
+            origin,
+            u,
+            v,
+            normal: n.normalise(),
+            w: n / n.mag_sqr(),
+        }
+    }
+
+    /// Find where the ray crosses the plane. Returns the distance along the ray and how far along 'u' and 'v' from the
+    /// origin the point is.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.dot(r.direction);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(self.origin - r.origin) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = r.point_at(t) - self.origin;
+        Some((t, self.w.dot(p.cross(self.v)), self.w.dot(self.u.cross(p))))
+    }
+
+    fn record<'a>(
+        &self,
+        r: &Ray,
+        t: f64,
+        u: f64,
+        v: f64,
+        material: &'a dyn Material,
+    ) -> HitRecord<'a> {
+        let front_face = r.direction.dot(self.normal) < 0.0;
+        let normal = match front_face {
+            true => self.normal,
+            false => -self.normal,
+        };
+        HitRecord::new(u, v, t, r.point_at(t), normal, front_face, material)
+    }
+}
+
+/// Return two unit vectors perpendicular to each other and to the unit vector 'n', turning anticlockwise around it.
+/// For normals along Y they're X and -Z.
+pub(crate) fn perpendicular_basis(n: Vec3) -> (Vec3, Vec3) {
+    let a = match n.0.abs() > 0.9 {
+        true => Vec3(0.0, 1.0, 0.0),
+        false => Vec3(1.0, 0.0, 0.0),
+    };
+    let u = (a - n * n.dot(a)).normalise();
+    (u, n.cross(u))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColour;
+
+    fn matte() -> Lambertian<SolidColour> {
+        Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)))
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).mag() < 1e-9, "{a:?} isn't {b:?}");
+    }
+
+    /// Shoot a ray straight down the Z axis through (x, y) at 'shape', or straight up it if not 'from_above'. Returns
+    /// the UV and whether the front was hit, after checking the hit is on the ray and its normal faces back along it.
+    fn hit(shape: &dyn Hittable, x: f64, y: f64, from_above: bool) -> Option<(f64, f64, bool)> {
+        let z = if from_above { 1.0 } else { -1.0 };
+        let r = Ray::new(Vec3(x, y, 5.0 * z), Vec3(0.0, 0.0, -z), 0.0);
+        let rec = shape.hit(&r, 0.001, f64::MAX)?;
+        assert_close(rec.p, Vec3(x, y, rec.p.2));
+        assert_close(rec.normal, Vec3(0.0, 0.0, z));
+        Some((rec.u, rec.v, rec.front_face))
+    }
+
+    fn assert_uv(hit: Option<(f64, f64, bool)>, expected: (f64, f64, bool)) {
+        let (u, v, front_face) = hit.expect("missed");
+        assert!(
+            (u - expected.0).abs() < 1e-9
+                && (v - expected.1).abs() < 1e-9
+                && front_face == expected.2,
+            "got {:?}, not {:?}",
+            (u, v, front_face),
+            expected
+        );
+    }
+
+    #[test]
+    fn quad_uvs_are_along_its_edges() {
+        // A parallelogram facing +Z, leaning over towards +X.
+        let quad = Quad::new(
+            Vec3(1.0, 2.0, 0.0),
+            Vec3(2.0, 0.0, 0.0),
+            Vec3(1.0, 2.0, 0.0),
+            matte(),
+        );
+        assert_uv(hit(&quad, 2.5, 3.0, true), (0.5, 0.5, true));
+        assert_uv(hit(&quad, 1.5, 2.0, true), (0.25, 0.0, true));
+        assert_uv(hit(&quad, 4.0, 4.0, false), (1.0, 1.0, false));
+        // Inside the bounding rectangle, but outside the slanted edges.
+        assert!(hit(&quad, 1.2, 3.5, true).is_none());
+        assert!(hit(&quad, 3.8, 2.5, true).is_none());
+    }
+
+    #[test]
+    fn disk_uvs_go_around_and_out() {
+        let disk = Disk::new(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, 3.0), 2.0, matte());
+        assert_uv(hit(&disk, 1.0, 0.0, true), (0.0, 0.5, true));
+        assert_uv(hit(&disk, 0.0, 1.0, true), (0.25, 0.5, true));
+        assert_uv(hit(&disk, -2.0, 0.0, false), (0.5, 1.0, false));
+        assert_uv(hit(&disk, 0.0, -0.5, true), (0.75, 0.25, true));
+        // The centre is part of a disk, but the corners of its bounding box aren't.
+        assert!(hit(&disk, 0.0, 0.0, true).is_some());
+        assert!(hit(&disk, 1.5, 1.5, true).is_none());
+    }
+
+    #[test]
+    fn annulus_has_a_hole() {
+        let ring = Annulus::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), 1.0, 3.0, matte());
+        assert!(hit(&ring, 0.0, 0.0, true).is_none());
+        assert!(hit(&ring, 0.5, -0.5, true).is_none());
+        assert!(hit(&ring, 0.0, 0.9, false).is_none());
+        assert!(hit(&ring, 2.5, 2.5, true).is_none());
+
+        // 'v' runs across the ring, from the inner edge to the outer one.
+        assert_uv(hit(&ring, 1.0, 0.0, true), (0.0, 0.0, true));
+        assert_uv(hit(&ring, 0.0, 2.0, true), (0.25, 0.5, true));
+        assert_uv(hit(&ring, 0.0, -3.0, false), (0.75, 1.0, false));
+    }
+
+    #[test]
+    fn tilted_disk_bounding_box() {
+        // Facing along X and Y equally, so it's tipped 45 degrees away from both of them.
+        let disk = Disk::new(Vec3(1.0, 2.0, 3.0), Vec3(1.0, 1.0, 0.0), 2.0, matte());
+        let bb = disk.bounding_box(0.0, 1.0).unwrap();
+        let half = 2f64.sqrt();
+        assert!((bb.min - Vec3(1.0 - half, 2.0 - half, 1.0)).mag() < 1e-3);
+        assert!((bb.max - Vec3(1.0 + half, 2.0 + half, 5.0)).mag() < 1e-3);
+    }
+
+    #[test]
+    fn infinite_plane_uvs_are_distances() {
+        // Facing up, UVs are measured along X and -Z.
+        let plane = InfinitePlane::new(Vec3(1.0, 0.0, 1.0), Vec3(0.0, 2.0, 0.0), matte());
+        let r = Ray::new(Vec3(4.0, 10.0, -1.0), Vec3(0.0, -1.0, 0.0), 0.0);
+        let rec = plane.hit(&r, 0.001, f64::MAX).unwrap();
+        assert_close(rec.p, Vec3(4.0, 0.0, -1.0));
+        assert!((rec.u - 3.0).abs() < 1e-9 && (rec.v - 2.0).abs() < 1e-9);
+        assert!(rec.front_face);
+        assert_close(rec.normal, Vec3(0.0, 1.0, 0.0));
+    }
+}
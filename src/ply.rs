@@ -0,0 +1,602 @@
+//! Loading triangle meshes from PLY files, as used for scanned data and the Stanford models.
+//!
+//! ASCII, binary little endian and binary big endian files are read. Vertices may have positions ('x', 'y', 'z'),
+//! normals ('nx', 'ny', 'nz'), texture coordinates ('u', 'v', or 's', 't') and colours ('red', 'green', 'blue').
+//! Faces are lists of vertex indices ('vertex_indices' or 'vertex_index') and are split into fans of triangles. Any
+//! other elements and properties are skipped.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::colour::Colour;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::tonemap::Transfer;
+use crate::vec::Vec3;
+
+/// Error returned when a PLY file can't be read or doesn't make sense.
+#[derive(Clone, Debug)]
+pub struct PlyError {
+    pub path: PathBuf,
+    pub line: Option<usize>, // Line the problem is on, starting from 1. Binary data doesn't have lines.
+    pub message: String,
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+/// Load the PLY mesh at 'path' and give it 'material'. Vertex colours are attached to the mesh, so a material with a
+/// VertexColourTexture shows them.
+pub fn load_ply<M: Material>(path: &Path, material: M) -> Result<TriangleMesh<M>, PlyError> {
+    let bytes = fs::read(path).map_err(|e| PlyError {
+        path: path.to_path_buf(),
+        line: None,
+        message: e.to_string(),
+    })?;
+    let ply = parse(&bytes).map_err(|(line, message)| PlyError {
+        path: path.to_path_buf(),
+        line,
+        message,
+    })?;
+
+    let mut mesh = TriangleMesh::new(ply.positions, ply.faces, material);
+    if let Some(normals) = ply.normals {
+        mesh = mesh.with_normals(normals);
+    }
+    if let Some(uvs) = ply.uvs {
+        mesh = mesh.with_uvs(uvs);
+    }
+    if let Some(colours) = ply.colours {
+        mesh = mesh.with_colours(colours);
+    }
+    Ok(mesh)
+}
+
+/// An error message and the line it's on, if it's on one.
+type ParseError = (Option<usize>, String);
+
+/// The ways the body of a PLY file can be stored.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// The types a property can have.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// Return the largest value of an integer type, used to scale colours to [0, 1]. Floats are already in range.
+    fn max(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    scalar: Scalar,
+    list: Option<Scalar>, // Type of the item count, if the property is a list.
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// The vertex buffers and triangles read from a PLY file. Faces have been checked to only refer to vertices that
+/// exist.
+struct PlyData {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    colours: Option<Vec<Colour>>,
+    faces: Vec<[u32; 3]>,
+}
+
+/// Parse a whole PLY file.
+fn parse(bytes: &[u8]) -> Result<PlyData, ParseError> {
+    let (format, elements, body_start, lines) = parse_header(bytes)?;
+    let mut body = Body {
+        format,
+        bytes,
+        pos: body_start,
+        line: lines + 1,
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colours = Vec::new();
+    let mut faces = Vec::new();
+    let (mut has_normals, mut has_uvs, mut has_colours) = (false, false, false);
+
+    for element in &elements {
+        let index_of = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|p| names.contains(&p.name.as_str()))
+        };
+
+        match element.name.as_str() {
+            "vertex" => {
+                let position = [index_of(&["x"]), index_of(&["y"]), index_of(&["z"])];
+                let normal = [index_of(&["nx"]), index_of(&["ny"]), index_of(&["nz"])];
+                let uv = [
+                    index_of(&["u", "s", "texture_u", "texture_s"]),
+                    index_of(&["v", "t", "texture_v", "texture_t"]),
+                ];
+                let colour = [
+                    index_of(&["red", "diffuse_red", "r"]),
+                    index_of(&["green", "diffuse_green", "g"]),
+                    index_of(&["blue", "diffuse_blue", "b"]),
+                ];
+                let [Some(x), Some(y), Some(z)] = position else {
+                    return Err((
+                        None,
+                        "vertices need 'x', 'y' and 'z' properties".to_string(),
+                    ));
+                };
+                has_normals = normal.iter().all(Option::is_some);
+                has_uvs = uv.iter().all(Option::is_some);
+                has_colours = colour.iter().all(Option::is_some);
+
+                let mut values = vec![0.0; element.properties.len()];
+                for _ in 0..element.count {
+                    for (value, property) in values.iter_mut().zip(&element.properties) {
+                        *value = match property.list {
+                            None => body.read(property.scalar)?,
+                            Some(count) => {
+                                body.skip_list(count, property.scalar)?;
+                                0.0
+                            }
+                        };
+                    }
+
+                    positions.push(Vec3(values[x], values[y], values[z]));
+                    if let [Some(nx), Some(ny), Some(nz)] = normal {
+                        normals.push(Vec3(values[nx], values[ny], values[nz]));
+                    }
+                    if let [Some(u), Some(v)] = uv {
+                        uvs.push((values[u], values[v]));
+                    }
+                    if let [Some(r), Some(g), Some(b)] = colour {
+                        // Colours are stored sRGB encoded, like in an image.
+                        let channel = |i: usize| {
+                            let max = element.properties[i].scalar.max();
+                            Transfer::Srgb.decode((values[i] / max).clamp(0.0, 1.0))
+                        };
+                        colours.push(Colour::new(channel(r), channel(g), channel(b)));
+                    }
+                }
+            }
+            "face" => {
+                let indices = index_of(&["vertex_indices", "vertex_index"])
+                    .ok_or((None, "faces need a 'vertex_indices' property".to_string()))?;
+                for _ in 0..element.count {
+                    for (k, property) in element.properties.iter().enumerate() {
+                        match (property.list, k == indices) {
+                            (Some(count), true) => {
+                                let face = body.read_list(count, property.scalar)?;
+                                if face.len() < 3 {
+                                    return Err((
+                                        body.line_of(body.line),
+                                        "a face needs at least 3 vertices".to_string(),
+                                    ));
+                                }
+                                for j in 1..face.len() - 1 {
+                                    faces.push([face[0], face[j], face[j + 1]]);
+                                }
+                            }
+                            (Some(count), false) => body.skip_list(count, property.scalar)?,
+                            (None, true) => {
+                                return Err((None, "'vertex_indices' must be a list".to_string()))
+                            }
+                            (None, false) => {
+                                body.read(property.scalar)?;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property.list {
+                            None => {
+                                body.read(property.scalar)?;
+                            }
+                            Some(count) => body.skip_list(count, property.scalar)?,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Check the indices here, where there's an error to return, rather than letting the mesh panic.
+    if let Some(&i) = faces
+        .iter()
+        .flatten()
+        .find(|&&i| i as usize >= positions.len())
+    {
+        return Err((
+            None,
+            format!(
+                "a face refers to vertex {} but there are only {}",
+                i,
+                positions.len()
+            ),
+        ));
+    }
+
+    Ok(PlyData {
+        positions,
+        normals: has_normals.then_some(normals),
+        uvs: has_uvs.then_some(uvs),
+        colours: has_colours.then_some(colours),
+        faces,
+    })
+}
+
+/// Parse the header, returning the format, the elements, where the body starts and how many lines the header took.
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize, usize), ParseError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut pos = 0;
+    let mut number = 0;
+
+    loop {
+        let end = bytes[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or((None, "the header has no 'end_header'".to_string()))?;
+        let line = String::from_utf8_lossy(&bytes[pos..pos + end]);
+        pos += end + 1;
+        number += 1;
+        let error = |message: String| (Some(number), message);
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if number == 1 {
+            if tokens != ["ply"] {
+                return Err(error("not a PLY file".to_string()));
+            }
+            continue;
+        }
+
+        match tokens[..] {
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(error(format!("unknown format '{}'", name))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("'{}' isn't a valid element count", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    scalar: parse_scalar(item).map_err(error)?,
+                    list: Some(parse_scalar(count).map_err(error)?),
+                });
+            }
+            ["property", scalar, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    scalar: parse_scalar(scalar).map_err(error)?,
+                    list: None,
+                });
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => (),
+            _ => return Err(error(format!("can't understand '{}'", line.trim()))),
+        }
+    }
+
+    let format = format.ok_or((None, "the header has no 'format' line".to_string()))?;
+    Ok((format, elements, pos, number))
+}
+
+fn parse_scalar(name: &str) -> Result<Scalar, String> {
+    Scalar::parse(name).ok_or_else(|| format!("unknown property type '{}'", name))
+}
+
+/// A cursor over the body of a PLY file.
+struct Body<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize, // Line of the next ASCII value.
+}
+
+impl<'a> Body<'a> {
+    /// Read one value of type 'scalar'.
+    fn read(&mut self, scalar: Scalar) -> Result<f64, ParseError> {
+        if self.format == Format::Ascii {
+            let token = self.next_token()?;
+            return token
+                .parse()
+                .map_err(|_| (Some(self.line), format!("'{}' isn't a number", token)));
+        }
+
+        let size = scalar.size();
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + size)
+            .ok_or((None, "the file ends part way through the data".to_string()))?;
+        self.pos += size;
+
+        let mut buf = [0; 8];
+        buf[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            buf[..size].reverse();
+        }
+        let [a, b, c, d, ..] = buf;
+        Ok(match scalar {
+            Scalar::I8 => a as i8 as f64,
+            Scalar::U8 => a as f64,
+            Scalar::I16 => i16::from_le_bytes([a, b]) as f64,
+            Scalar::U16 => u16::from_le_bytes([a, b]) as f64,
+            Scalar::I32 => i32::from_le_bytes([a, b, c, d]) as f64,
+            Scalar::U32 => u32::from_le_bytes([a, b, c, d]) as f64,
+            Scalar::F32 => f32::from_le_bytes([a, b, c, d]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+
+    /// Read a list of vertex indices.
+    fn read_list(&mut self, count: Scalar, item: Scalar) -> Result<Vec<u32>, ParseError> {
+        let n = self.read(count)?;
+        (0..n as usize)
+            .map(|_| {
+                let i = self.read(item)?;
+                match i >= 0.0 && i <= u32::MAX as f64 && i.fract() == 0.0 {
+                    true => Ok(i as u32),
+                    false => Err((
+                        self.line_of(self.line),
+                        format!("'{}' isn't a valid vertex index", i),
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    fn skip_list(&mut self, count: Scalar, item: Scalar) -> Result<(), ParseError> {
+        let n = self.read(count)? as usize;
+        for _ in 0..n {
+            self.read(item)?;
+        }
+        Ok(())
+    }
+
+    /// Return 'line' for errors in ASCII files. Binary files don't have lines.
+    fn line_of(&self, line: usize) -> Option<usize> {
+        match self.format {
+            Format::Ascii => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Return the next whitespace separated token of an ASCII body, keeping count of lines.
+    fn next_token(&mut self) -> Result<&'a str, ParseError> {
+        while let Some(&b) = self.bytes.get(self.pos) {
+            if !b.is_ascii_whitespace() {
+                break;
+            }
+            if b == b'\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err((
+                Some(self.line),
+                "the file ends part way through the data".to_string(),
+            ));
+        }
+        let bytes = self.bytes;
+        std::str::from_utf8(&bytes[start..self.pos])
+            .map_err(|_| (Some(self.line), "invalid text in the data".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property double y
+property int z
+property uchar red
+property ushort green
+property uchar blue
+element edge 1
+property int vertex1
+property int vertex2
+element face 1
+property list uchar int vertex_indices
+property short flags
+end_header
+";
+
+    /// Write a square, with a coloured vertex at each corner, in 'format'. Every kind of value is negative or more
+    /// than a byte long somewhere, so getting the byte order wrong changes it.
+    fn square(format: Format) -> Vec<u8> {
+        let name = match format {
+            Format::Ascii => "ascii",
+            Format::LittleEndian => "binary_little_endian",
+            Format::BigEndian => "binary_big_endian",
+        };
+        let mut bytes =
+            format!("ply\nformat {} 1.0\ncomment a square\n{}", name, HEADER).into_bytes();
+        macro_rules! put {
+            ($($value:expr),*) => {{
+                $(match format {
+                    Format::Ascii => bytes.extend(format!("{} ", $value).bytes()),
+                    Format::LittleEndian => bytes.extend($value.to_le_bytes()),
+                    Format::BigEndian => bytes.extend($value.to_be_bytes()),
+                })*
+                if format == Format::Ascii {
+                    bytes.push(b'\n');
+                }
+            }};
+        }
+
+        put!(0.0f32, 0.0f64, -300i32, 255u8, 0u16, 0u8);
+        put!(1.0f32, 0.0f64, -300i32, 0u8, 65535u16, 0u8);
+        put!(1.0f32, 1.5f64, -300i32, 0u8, 0u16, 51u8);
+        put!(0.0f32, 1.5f64, -300i32, 128u8, 32768u16, 0u8);
+        put!(0i32, 1i32);
+        put!(4u8, 0i32, 1i32, 2i32, 3i32, -2i16);
+        bytes
+    }
+
+    /// Return the components of each vector, to compare them.
+    fn components(vectors: &[Vec3]) -> Vec<(f64, f64, f64)> {
+        vectors.iter().map(|v| (v.0, v.1, v.2)).collect()
+    }
+
+    fn parse_error(bytes: &[u8]) -> ParseError {
+        match parse(bytes) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn every_format_gives_the_same_mesh() {
+        let srgb = |x: f64| Transfer::Srgb.decode(x);
+        for format in [Format::Ascii, Format::LittleEndian, Format::BigEndian] {
+            let ply = parse(&square(format)).unwrap();
+            assert_eq!(
+                components(&ply.positions),
+                vec![
+                    (0.0, 0.0, -300.0),
+                    (1.0, 0.0, -300.0),
+                    (1.0, 1.5, -300.0),
+                    (0.0, 1.5, -300.0)
+                ]
+            );
+            // Quads are split into a fan of triangles around their first vertex.
+            assert_eq!(ply.faces, vec![[0, 1, 2], [0, 2, 3]]);
+
+            // Integer colours are scaled by the largest value of their type.
+            assert_eq!(
+                ply.colours.unwrap(),
+                vec![
+                    Colour::new(1.0, 0.0, 0.0),
+                    Colour::new(0.0, 1.0, 0.0),
+                    Colour::new(0.0, 0.0, srgb(51.0 / 255.0)),
+                    Colour::new(srgb(128.0 / 255.0), srgb(32768.0 / 65535.0), 0.0),
+                ]
+            );
+            assert!(ply.normals.is_none() && ply.uvs.is_none());
+        }
+    }
+
+    #[test]
+    fn faces_must_refer_to_vertices_that_exist() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 2\nproperty list uchar uint vertex_indices\nend_header\n\
+                    0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n3 0 2 3\n";
+        assert_eq!(
+            parse_error(text.as_bytes()),
+            (
+                None,
+                "a face refers to vertex 3 but there are only 3".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line_they_are_on() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+                    end_header\n0 0 0\n1 zero 0\n";
+        assert_eq!(parse_error(text.as_bytes()).0, Some(9));
+
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float w x\nend_header\n";
+        assert_eq!(parse_error(text.as_bytes()).0, Some(4));
+
+        // Binary data doesn't have lines.
+        let mut bytes = square(Format::LittleEndian);
+        bytes.truncate(bytes.len() - 3);
+        assert_eq!(
+            parse_error(&bytes),
+            (None, "the file ends part way through the data".to_string())
+        );
+    }
+}
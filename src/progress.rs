@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How far a render has got.
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    pub pass: u32,             // Number of the pass being rendered, starting from 1.
+    pub tiles_done: u32,       // Tiles finished in the current pass.
+    pub tiles: u32,            // Tiles in each pass.
+    pub samples: u64,          // Samples taken so far, including any before resuming.
+    pub total_samples: u64,    // Samples expected in all. Adaptive renders may take fewer.
+    pub elapsed: Duration,     // Time spent since the render was started or resumed.
+    pub eta: Option<Duration>, // Estimated time left, once there's enough to go on.
+}
+
+impl Progress {
+    /// Return the fraction of the render that is done, in [0, 1].
+    pub fn fraction(&self) -> f64 {
+        if self.total_samples == 0 {
+            return 1.0;
+        }
+        (self.samples as f64 / self.total_samples as f64).min(1.0)
+    }
+}
+
+/// A RenderObserver is told about the progress of a render. Tiles are rendered in parallel, so its methods are
+/// called from several threads at once.
+pub trait RenderObserver: Sync {
+    /// Called each time a tile is finished.
+    fn tile_finished(&self, _progress: &Progress) {}
+
+    /// Called each time a whole pass is finished.
+    fn pass_finished(&self, _progress: &Progress) {}
+}
+
+/// A CancelToken stops a render from another thread. Clones share the same flag, so one can be handed to a signal
+/// handler and another to the render.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the render to stop. Tiles that have already started are finished, but nothing else is started.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,893 @@
+//! Curved primitives beyond the sphere: cylinders, cones, paraboloids, tori and capsules.
+//!
+//! All but the capsule stand upright along +Y from the point they're placed at; use an Instance to tilt them. UVs
+//! run around the Y axis in 'u', anticlockwise seen from above starting from +X, and up the shape in 'v'. Flat caps
+//! get the UVs of a Disk.
+
+use std::f64::consts::PI;
+
+use crate::aabb::AABB;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::planar::perpendicular_basis;
+use crate::ray::Ray;
+
+use crate::vec::Vec3;
+
+/// A cylinder of 'radius' standing 'height' tall on the centre of its base. It's closed with flat caps unless it's
+/// made 'open'.
+#[derive(Debug, Clone, Copy)]
+pub struct Cylinder<M: Material> {
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: M,
+}
+
+impl<M: Material> Cylinder<M> {
+    pub fn new(base: Vec3, radius: f64, height: f64, material: M) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            capped: true,
+            material,
+        }
+    }
+
+    /// Leave the ends open, making a tube.
+    pub fn open(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+impl<M: Material> Hittable for Cylinder<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = (r.origin - self.base, r.direction);
+        let mut closest = Closest::new(t_min, t_max);
+
+        let a = d.0 * d.0 + d.2 * d.2;
+        let b = 2.0 * (o.0 * d.0 + o.2 * d.2);
+        let c = o.0 * o.0 + o.2 * o.2 - self.radius * self.radius;
+        for t in quadratic(a, b, c).into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+            let p = o + d * t;
+            if (0.0..=self.height).contains(&p.1) {
+                closest.offer(t, Vec3(p.0, 0.0, p.2), angle(p), p.1 / self.height);
+            }
+        }
+        if self.capped {
+            cap(&mut closest, o, d, 0.0, self.radius, false);
+            cap(&mut closest, o, d, self.height, self.radius, true);
+        }
+
+        closest.record(r, &self.material)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        Some(upright_bounds(self.base, self.radius, self.height))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// A cone with a base of 'radius' and its tip 'height' above the centre of the base. The base is closed with a flat
+/// cap unless it's made 'open'.
+#[derive(Debug, Clone, Copy)]
+pub struct Cone<M: Material> {
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: M,
+}
+
+impl<M: Material> Cone<M> {
+    pub fn new(base: Vec3, radius: f64, height: f64, material: M) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            capped: true,
+            material,
+        }
+    }
+
+    /// Leave the base open.
+    pub fn open(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+impl<M: Material> Hittable for Cone<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = (r.origin - self.base, r.direction);
+        let mut closest = Closest::new(t_min, t_max);
+
+        // The sides are where x^2 + z^2 = (k * (height - y))^2.
+        let k2 = (self.radius / self.height).powi(2);
+        let oh = self.height - o.1;
+        let a = d.0 * d.0 + d.2 * d.2 - k2 * d.1 * d.1;
+        let b = 2.0 * (o.0 * d.0 + o.2 * d.2 + k2 * oh * d.1);
+        let c = o.0 * o.0 + o.2 * o.2 - k2 * oh * oh;
+        for t in quadratic(a, b, c).into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+            let p = o + d * t;
+            if (0.0..=self.height).contains(&p.1) {
+                // The normal at the tip is undefined, so take it to point straight up.
+                let n = match p.1 < self.height {
+                    true => Vec3(p.0, k2 * (self.height - p.1), p.2),
+                    false => Vec3(0.0, 1.0, 0.0),
+                };
+                closest.offer(t, n, angle(p), p.1 / self.height);
+            }
+        }
+        if self.capped {
+            cap(&mut closest, o, d, 0.0, self.radius, false);
+        }
+
+        closest.record(r, &self.material)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        Some(upright_bounds(self.base, self.radius, self.height))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// A paraboloid bowl with its lowest point at 'vertex', widening to 'radius' at 'height' above it. The top is closed
+/// with a flat cap unless it's made 'open'.
+#[derive(Debug, Clone, Copy)]
+pub struct Paraboloid<M: Material> {
+    vertex: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: M,
+}
+
+impl<M: Material> Paraboloid<M> {
+    pub fn new(vertex: Vec3, radius: f64, height: f64, material: M) -> Self {
+        Self {
+            vertex,
+            radius,
+            height,
+            capped: true,
+            material,
+        }
+    }
+
+    /// Leave the top open, making a dish.
+    pub fn open(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+impl<M: Material> Hittable for Paraboloid<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = (r.origin - self.vertex, r.direction);
+        let mut closest = Closest::new(t_min, t_max);
+
+        // The sides are where x^2 + z^2 = s * y.
+        let s = self.radius * self.radius / self.height;
+        let a = d.0 * d.0 + d.2 * d.2;
+        let b = 2.0 * (o.0 * d.0 + o.2 * d.2) - s * d.1;
+        let c = o.0 * o.0 + o.2 * o.2 - s * o.1;
+        for t in quadratic(a, b, c).into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+            let p = o + d * t;
+            if (0.0..=self.height).contains(&p.1) {
+                closest.offer(
+                    t,
+                    Vec3(2.0 * p.0, -s, 2.0 * p.2),
+                    angle(p),
+                    p.1 / self.height,
+                );
+            }
+        }
+        if self.capped {
+            cap(&mut closest, o, d, self.height, self.radius, true);
+        }
+
+        closest.record(r, &self.material)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        Some(upright_bounds(self.vertex, self.radius, self.height))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// A torus lying flat around 'centre': a tube of radius 'minor' swept around a circle of radius 'major'. 'v' runs
+/// around the tube, starting from its outer edge and going over the top.
+#[derive(Debug, Clone, Copy)]
+pub struct Torus<M: Material> {
+    centre: Vec3,
+    major: f64,
+    minor: f64,
+    material: M,
+}
+
+impl<M: Material> Torus<M> {
+    pub fn new(centre: Vec3, major: f64, minor: f64, material: M) -> Self {
+        Self {
+            centre,
+            major,
+            minor,
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Torus<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (major, minor) = (self.major, self.minor);
+        let length = r.direction.mag();
+        let d = r.direction / length;
+        let o = r.origin - self.centre;
+
+        // Only look for hits inside the torus's bounding sphere, measured from where the ray enters it. Keeping the
+        // origin close to the torus keeps the quartic's coefficients small enough to solve accurately. The sphere is
+        // a little bigger than the torus so that the torus's outer edge, where they touch, isn't at the very end.
+        let bound = (major + minor) * (1.0 + 1e-6);
+        let (enter, exit) = quadratic(1.0, 2.0 * o.dot(d), o.mag_sqr() - bound * bound)?;
+        let start = enter.max(t_min * length);
+        let end = exit.min(t_max * length);
+        if start >= end {
+            return None;
+        }
+        let o = o + d * start;
+
+        // Points on the torus satisfy (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2).
+        let f = o.dot(d);
+        let e = o.mag_sqr() + major * major - minor * minor;
+        let four_r2 = 4.0 * major * major;
+        let coefficients = [
+            e * e - four_r2 * (o.0 * o.0 + o.2 * o.2),
+            4.0 * f * e - 2.0 * four_r2 * (o.0 * d.0 + o.2 * d.2),
+            4.0 * f * f + 2.0 * e - four_r2 * (d.0 * d.0 + d.2 * d.2),
+            4.0 * f,
+        ];
+        let s = first_quartic_root(coefficients, 0.0, end - start)?;
+
+        let p = o + d * s;
+        let ring = Vec3(p.0, 0.0, p.2).normalise() * major;
+        let n = p - ring;
+        let around = p.1.atan2(Vec3(p.0, 0.0, p.2).mag() - major);
+        let mut closest = Closest::new(t_min, t_max);
+        closest.offer(
+            (start + s) / length,
+            n,
+            angle(p),
+            around.rem_euclid(2.0 * PI) / (2.0 * PI),
+        );
+        closest.record(r, &self.material)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let extent = Vec3(self.major + self.minor, self.minor, self.major + self.minor);
+        Some(AABB {
+            min: self.centre - extent,
+            max: self.centre + extent,
+        })
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// A capsule: the points within 'radius' of the line from 'p0' to 'p1', a cylinder with hemispheres on its ends.
+/// 'u' runs around the line and 'v' along it, from the bottom of the hemisphere around 'p0' to the top of the one
+/// around 'p1'.
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule<M: Material> {
+    p0: Vec3,
+    axes: [Vec3; 3], // A frame with Y along the capsule, like the other shapes stand in.
+    length: f64,
+    radius: f64,
+    material: M,
+}
+
+impl<M: Material> Capsule<M> {
+    /// Create a capsule around the line from 'p0' to 'p1'. If they're the same point it's a sphere.
+    pub fn new(p0: Vec3, p1: Vec3, radius: f64, material: M) -> Self {
+        let length = (p1 - p0).mag();
+        let y = match length > 0.0 {
+            true => (p1 - p0) / length,
+            false => Vec3(0.0, 1.0, 0.0),
+        };
+        let (x, _) = perpendicular_basis(y);
+        Self {
+            p0,
+            axes: [x, y, x.cross(y)],
+            length,
+            radius,
+            material,
+        }
+    }
+
+    /// Express 'v' in the capsule's frame.
+    fn local(&self, v: Vec3) -> Vec3 {
+        Vec3(
+            self.axes[0].dot(v),
+            self.axes[1].dot(v),
+            self.axes[2].dot(v),
+        )
+    }
+}
+
+impl<M: Material> Hittable for Capsule<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let o = self.local(r.origin - self.p0);
+        let d = self.local(r.direction);
+        let (length, radius) = (self.length, self.radius);
+        let v = |y: f64| (y + radius) / (length + 2.0 * radius);
+        let mut closest = Closest::new(t_min, t_max);
+
+        let a = d.0 * d.0 + d.2 * d.2;
+        let b = 2.0 * (o.0 * d.0 + o.2 * d.2);
+        let c = o.0 * o.0 + o.2 * o.2 - radius * radius;
+        for t in quadratic(a, b, c).into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+            let p = o + d * t;
+            if (0.0..=length).contains(&p.1) {
+                closest.offer(t, Vec3(p.0, 0.0, p.2), angle(p), v(p.1));
+            }
+        }
+
+        // Each end only counts on its own side of the cylinder.
+        for (y, below) in [(0.0, true), (length, false)] {
+            let oc = o - Vec3(0.0, y, 0.0);
+            let roots = quadratic(d.mag_sqr(), 2.0 * oc.dot(d), oc.mag_sqr() - radius * radius);
+            for t in roots.into_iter().flat_map(|(t0, t1)| [t0, t1]) {
+                let p = o + d * t;
+                if (p.1 < y) == below {
+                    closest.offer(t, p - Vec3(0.0, y, 0.0), angle(p), v(p.1));
+                }
+            }
+        }
+
+        // Turn the normal back out of the capsule's frame.
+        let (t, n, u, v) = closest.hit?;
+        let n = self.axes[0] * n.0 + self.axes[1] * n.1 + self.axes[2] * n.2;
+        Some(record(r, t, n, u, v, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let p1 = self.p0 + self.axes[1] * self.length;
+        let r = Vec3(self.radius, self.radius, self.radius);
+        Some(AABB {
+            min: self.p0.zip_with(p1, f64::min) - r,
+            max: self.p0.zip_with(p1, f64::max) + r,
+        })
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// The nearest hit found so far among the surfaces making up a shape.
+struct Closest {
+    t_min: f64,
+    t_max: f64,
+    hit: Option<(f64, Vec3, f64, f64)>, // Distance, outward normal and UV of the hit.
+}
+
+impl Closest {
+    fn new(t_min: f64, t_max: f64) -> Self {
+        Self {
+            t_min,
+            t_max,
+            hit: None,
+        }
+    }
+
+    /// Keep the hit if it's in range and nearer than any so far. The normal doesn't need to be normalised.
+    fn offer(&mut self, t: f64, outward: Vec3, u: f64, v: f64) {
+        if t > self.t_min && t < self.t_max {
+            self.t_max = t;
+            self.hit = Some((t, outward, u, v));
+        }
+    }
+
+    fn record<'a>(&self, r: &Ray, material: &'a dyn Material) -> Option<HitRecord<'a>> {
+        let (t, n, u, v) = self.hit?;
+        Some(record(r, t, n, u, v, material))
+    }
+}
+
+fn record<'a>(
+    r: &Ray,
+    t: f64,
+    outward: Vec3,
+    u: f64,
+    v: f64,
+    material: &'a dyn Material,
+) -> HitRecord<'a> {
+    let outward = outward.normalise();
+    let front_face = r.direction.dot(outward) < 0.0;
+    let normal = match front_face {
+        true => outward,
+        false => -outward,
+    };
+    HitRecord::new(u, v, t, r.point_at(t), normal, front_face, material)
+}
+
+/// Offer the hit with the flat cap of 'radius' at height 'y', which faces up if 'top' is set and down otherwise.
+fn cap(closest: &mut Closest, o: Vec3, d: Vec3, y: f64, radius: f64, top: bool) {
+    if d.1 == 0.0 {
+        return;
+    }
+    let t = (y - o.1) / d.1;
+    let p = o + d * t;
+    let distance = p.0.hypot(p.2);
+    if distance <= radius {
+        let n = Vec3(0.0, if top { 1.0 } else { -1.0 }, 0.0);
+        closest.offer(t, n, angle(p), distance / radius);
+    }
+}
+
+/// Return the angle of 'p' around the Y axis as a fraction of a turn, anticlockwise seen from above from +X.
+fn angle(p: Vec3) -> f64 {
+    (-p.2).atan2(p.0).rem_euclid(2.0 * PI) / (2.0 * PI)
+}
+
+fn upright_bounds(base: Vec3, radius: f64, height: f64) -> AABB {
+    AABB {
+        min: base - Vec3(radius, 0.0, radius),
+        max: base + Vec3(radius, height, radius),
+    }
+}
+
+/// Return the real roots of a t^2 + b t + c = 0, smallest first. If 'a' is zero the equation is linear and both are
+/// its one root.
+fn quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        return match b == 0.0 {
+            true => None,
+            false => Some((-c / b, -c / b)),
+        };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // Avoid subtracting nearly equal numbers, which loses precision when b^2 is much bigger than a c.
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    let (t0, t1) = match q == 0.0 {
+        true => (0.0, 0.0),
+        false => (q / a, c / q),
+    };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+/// Return the real roots of t^3 + a t^2 + b t + c = 0, in no particular order. A double root is returned twice, and a
+/// triple root once.
+fn cubic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Substitute t = x - a / 3 to get x^3 + p x + q = 0.
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let shift = -a / 3.0;
+    let discriminant = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+
+    if discriminant > 0.0 {
+        let s = discriminant.sqrt();
+        vec![(-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt() + shift]
+    } else if p == 0.0 {
+        // A zero discriminant with p = 0 means q = 0 too, so x = 0 is a triple root.
+        vec![shift]
+    } else {
+        // Three real roots, found with Viete's trigonometric method. When the discriminant is zero two of them are
+        // the same, so a double root is found too.
+        let m = 2.0 * (-p / 3.0).sqrt();
+        let theta = (3.0 * q / (p * m)).clamp(-1.0, 1.0).acos() / 3.0;
+        (0..3)
+            .map(|k| m * (theta - 2.0 * PI * k as f64 / 3.0).cos() + shift)
+            .collect()
+    }
+}
+
+/// Return the smallest root of t^4 + c3 t^3 + c2 t^2 + c1 t + c0 = 0 in (lo, hi), given the coefficients [c0, c1, c2,
+/// c3].
+///
+/// The quartic is monotonic between the roots of its derivative, so those split the range into pieces with at most
+/// one root each. Pieces where it changes sign are narrowed down by bisection, with Newton steps to speed it up. A
+/// double root, where the quartic touches zero without changing sign, is a root of the derivative too, so it's found
+/// as one of the pieces' ends.
+fn first_quartic_root(c: [f64; 4], lo: f64, hi: f64) -> Option<f64> {
+    let f = |t: f64| (((t + c[3]) * t + c[2]) * t + c[1]) * t + c[0];
+    let df = |t: f64| ((4.0 * t + 3.0 * c[3]) * t + 2.0 * c[2]) * t + c[1];
+    // Whether f(t) is zero to within the rounding error of working it out.
+    let is_zero = |t: f64| {
+        let terms = t.powi(4)
+            + (c[3] * t.powi(3)).abs()
+            + (c[2] * t * t).abs()
+            + (c[1] * t).abs()
+            + c[0].abs();
+        f(t).abs() <= 1e-12 * terms
+    };
+
+    let mut bounds = cubic_roots(0.75 * c[3], 0.5 * c[2], 0.25 * c[1]);
+    bounds.retain(|&t| t > lo && t < hi);
+    bounds.sort_by(f64::total_cmp);
+    bounds.insert(0, lo);
+    bounds.push(hi);
+
+    for piece in bounds.windows(2) {
+        let (mut a, mut b) = (piece[0], piece[1]);
+        let (fa, fb) = (f(a), f(b));
+        if a > lo && is_zero(a) {
+            return Some(a);
+        }
+        if fa.signum() == fb.signum() {
+            continue;
+        }
+
+        let rising = fa < 0.0;
+        let mut t = 0.5 * (a + b);
+        for _ in 0..100 {
+            let ft = f(t);
+            if ft == 0.0 {
+                break;
+            }
+            if (ft < 0.0) == rising {
+                a = t;
+            } else {
+                b = t;
+            }
+            // Take a Newton step if it stays inside the bracket, and bisect otherwise.
+            let step = t - ft / df(t);
+            let next = match step > a && step < b {
+                true => step,
+                false => 0.5 * (a + b),
+            };
+            let tolerance = 1e-12 * (1.0 + t.abs());
+            let converged = (next - t).abs() <= tolerance || b - a <= tolerance;
+            t = next;
+            if converged {
+                break;
+            }
+        }
+        if t > lo {
+            return Some(t);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColour;
+
+    type Matte = Lambertian<SolidColour>;
+
+    fn matte() -> Matte {
+        Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)))
+    }
+
+    fn cylinder() -> Cylinder<Matte> {
+        Cylinder::new(Vec3(0.0, 0.0, 0.0), 1.0, 2.0, matte())
+    }
+
+    fn cone() -> Cone<Matte> {
+        Cone::new(Vec3(0.0, 0.0, 0.0), 1.0, 2.0, matte())
+    }
+
+    fn paraboloid() -> Paraboloid<Matte> {
+        Paraboloid::new(Vec3(0.0, 0.0, 0.0), 1.0, 1.0, matte())
+    }
+
+    fn torus() -> Torus<Matte> {
+        Torus::new(Vec3(0.0, 0.0, 0.0), 2.0, 0.5, matte())
+    }
+
+    /// A capsule along the diagonal from (1, 1, 1) to (3, 3, 1). Its frame has X along (1, -1, 0) and Z along +Z.
+    fn capsule() -> Capsule<Matte> {
+        Capsule::new(Vec3(1.0, 1.0, 1.0), Vec3(3.0, 3.0, 1.0), 0.5, matte())
+    }
+
+    /// The distance, front, normal and UV of the first hit along the ray past 't_min'.
+    type Hit = (f64, bool, Vec3, f64, f64);
+
+    fn hit_from(object: &impl Hittable, origin: Vec3, direction: Vec3, t_min: f64) -> Option<Hit> {
+        let rec = object.hit(&Ray::new(origin, direction, 0.0), t_min, f64::MAX)?;
+        Some((rec.t, rec.front_face, rec.normal, rec.u, rec.v))
+    }
+
+    fn hit(object: &impl Hittable, origin: Vec3, direction: Vec3) -> Option<Hit> {
+        hit_from(object, origin, direction, 0.0)
+    }
+
+    /// Check a hit's distance, front, normal and UV.
+    fn check(hit: Option<Hit>, t: f64, front_face: bool, normal: Vec3, uv: (f64, f64)) {
+        let (hit_t, hit_front_face, hit_normal, u, v) = hit.expect("expected a hit");
+        let tolerance = 1e-6;
+        assert!((hit_t - t).abs() < tolerance, "t is {hit_t}, expected {t}");
+        assert_eq!(hit_front_face, front_face);
+        assert!(
+            (hit_normal - normal).mag() < tolerance,
+            "normal is {hit_normal:?}, expected {normal:?}"
+        );
+        assert!(
+            (u - uv.0).abs() < tolerance && (v - uv.1).abs() < tolerance,
+            "UV is ({u}, {v}), expected {uv:?}"
+        );
+    }
+
+    #[test]
+    fn cylinder_side() {
+        let rec = hit(&cylinder(), Vec3(-3.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        check(rec, 2.0, true, Vec3(-1.0, 0.0, 0.0), (0.5, 0.5));
+    }
+
+    #[test]
+    fn cylinder_cap() {
+        let rec = hit(&cylinder(), Vec3(0.0, 5.0, -0.5), Vec3(0.0, -1.0, 0.0));
+        check(rec, 3.0, true, Vec3(0.0, 1.0, 0.0), (0.25, 0.5));
+    }
+
+    #[test]
+    fn open_cylinder_inside_wall() {
+        // Going in through the top, the ray would hit the cap at t = 0.5, but without it hits the far wall inside.
+        let (origin, direction) = (Vec3(0.0, 3.0, 0.0), Vec3(1.0, -2.0, 0.0));
+        let rec = hit(&cylinder(), origin, direction);
+        check(rec, 0.5, true, Vec3(0.0, 1.0, 0.0), (0.0, 0.5));
+        let rec = hit(&cylinder().open(), origin, direction);
+        check(rec, 1.0, false, Vec3(-1.0, 0.0, 0.0), (0.0, 0.5));
+    }
+
+    #[test]
+    fn cylinder_from_inside() {
+        let rec = hit(&cylinder(), Vec3(0.0, 1.0, 0.0), Vec3(0.0, 0.0, 1.0));
+        check(rec, 1.0, false, Vec3(0.0, 0.0, -1.0), (0.75, 0.5));
+    }
+
+    #[test]
+    fn cylinder_parallel_to_axis() {
+        let rec = hit(&cylinder(), Vec3(0.5, -3.0, 0.5), Vec3(0.0, 1.0, 0.0));
+        check(rec, 3.0, true, Vec3(0.0, -1.0, 0.0), (0.875, 0.5f64.sqrt()));
+        assert!(hit(
+            &cylinder().open(),
+            Vec3(0.5, -3.0, 0.5),
+            Vec3(0.0, 1.0, 0.0)
+        )
+        .is_none());
+        assert!(hit(&cylinder(), Vec3(2.0, -3.0, 0.0), Vec3(0.0, 1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn cylinder_miss() {
+        assert!(hit(&cylinder(), Vec3(-3.0, 3.0, 0.0), Vec3(1.0, 0.0, 0.0)).is_none());
+        assert!(hit(&cylinder(), Vec3(-3.0, 1.0, 0.0), Vec3(-1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn cone_side() {
+        // Halfway up, the cone is half as wide as its base.
+        let rec = hit(&cone(), Vec3(-3.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let normal = Vec3(-0.5, 0.25, 0.0).normalise();
+        check(rec, 2.5, true, normal, (0.5, 0.5));
+    }
+
+    #[test]
+    fn cone_base() {
+        let (origin, direction) = (Vec3(0.0, -3.0, 0.5), Vec3(0.0, 1.0, 0.0));
+        let rec = hit(&cone(), origin, direction);
+        check(rec, 3.0, true, Vec3(0.0, -1.0, 0.0), (0.75, 0.5));
+        // Without the base, the ray carries on up to the side from inside.
+        let rec = hit(&cone().open(), origin, direction);
+        let normal = -Vec3(0.0, 0.25, 0.5).normalise();
+        check(rec, 4.0, false, normal, (0.75, 0.5));
+    }
+
+    #[test]
+    fn cone_tip() {
+        let rec = hit(&cone(), Vec3(0.0, 5.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        check(rec, 3.0, true, Vec3(0.0, 1.0, 0.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn cone_parallel_to_side() {
+        // Parallel to the slant, the ray's quadratic is linear and it only crosses the side once.
+        let rec = hit(&cone(), Vec3(-1.0, 2.0, 0.0), Vec3(1.0, -2.0, 0.0));
+        let normal = Vec3(-0.5, 0.25, 0.0).normalise();
+        check(rec, 0.5, true, normal, (0.5, 0.5));
+        // This one only crosses the cone's mirror image above the tip.
+        assert!(hit(&cone(), Vec3(0.0, 4.0, 0.0), Vec3(1.0, -2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn paraboloid_side() {
+        let rec = hit(&paraboloid(), Vec3(-3.0, 0.25, 0.0), Vec3(1.0, 0.0, 0.0));
+        let normal = Vec3(-1.0, -1.0, 0.0).normalise();
+        check(rec, 2.5, true, normal, (0.5, 0.25));
+    }
+
+    #[test]
+    fn paraboloid_from_inside() {
+        let rec = hit(&paraboloid(), Vec3(0.0, 0.5, 0.0), Vec3(1.0, 0.0, 0.0));
+        let normal = Vec3(-2.0f64.sqrt(), 1.0, 0.0).normalise();
+        check(rec, 0.5f64.sqrt(), false, normal, (0.0, 0.5));
+    }
+
+    #[test]
+    fn paraboloid_cap_and_open() {
+        let (origin, direction) = (Vec3(0.5, 3.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        let rec = hit(&paraboloid(), origin, direction);
+        check(rec, 2.0, true, Vec3(0.0, 1.0, 0.0), (0.0, 0.5));
+        // Without the cap, the ray falls into the dish and hits it from inside.
+        let rec = hit(&paraboloid().open(), origin, direction);
+        let normal = Vec3(-1.0, 1.0, 0.0).normalise();
+        check(rec, 2.75, false, normal, (0.0, 0.25));
+    }
+
+    #[test]
+    fn capsule_cylinder() {
+        // Towards the middle of the axis along the capsule's X, 2 sqrt(2) away.
+        let rec = hit(&capsule(), Vec3(4.0, 0.0, 1.0), Vec3(-1.0, 1.0, 0.0));
+        let t = 2.0 - 0.5 / 2.0f64.sqrt();
+        let normal = Vec3(1.0, -1.0, 0.0).normalise();
+        let v = (2.0f64.sqrt() + 0.5) / (8.0f64.sqrt() + 1.0);
+        check(rec, t, true, normal, (0.0, v));
+    }
+
+    #[test]
+    fn capsule_ends() {
+        // Straight down onto the hemisphere around p0, 0.3 below it along the capsule, where it's 0.4 high.
+        let below = Vec3(1.0, 1.0, 0.0).normalise() * -0.3;
+        let rec = hit(
+            &capsule(),
+            Vec3(1.0, 1.0, 4.0) + below,
+            Vec3(0.0, 0.0, -1.0),
+        );
+        let normal = below * 2.0 + Vec3(0.0, 0.0, 0.8);
+        let v = 0.2 / (8.0f64.sqrt() + 1.0);
+        check(rec, 2.6, true, normal, (0.75, v));
+
+        // Along the axis onto the far end of the hemisphere around p1.
+        let rec = hit(&capsule(), Vec3(5.0, 5.0, 1.0), Vec3(-1.0, -1.0, 0.0));
+        let normal = Vec3(1.0, 1.0, 0.0).normalise();
+        check(rec, 2.0 - 0.5 / 2.0f64.sqrt(), true, normal, (0.0, 1.0));
+    }
+
+    #[test]
+    fn capsule_seam() {
+        // Straight down onto p1, where the cylinder and the hemisphere meet.
+        let rec = hit(&capsule(), Vec3(3.0, 3.0, 4.0), Vec3(0.0, 0.0, -1.0));
+        let length = 8.0f64.sqrt();
+        let v = (length + 0.5) / (length + 1.0);
+        check(rec, 2.5, true, Vec3(0.0, 0.0, 1.0), (0.75, v));
+    }
+
+    #[test]
+    fn capsule_with_one_point_is_a_sphere() {
+        let sphere = Capsule::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0, matte());
+        let rec = hit(&sphere, Vec3(-3.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        check(rec, 2.0, true, Vec3(-1.0, 0.0, 0.0), (0.5, 0.5));
+        let rec = hit(&sphere, Vec3(0.0, 3.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        check(rec, 2.0, true, Vec3(0.0, 1.0, 0.0), (0.0, 1.0));
+        let rec = hit(&sphere, Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0));
+        check(rec, 1.0, false, Vec3(0.0, 0.0, -1.0), (0.75, 0.5));
+    }
+
+    #[test]
+    fn bounding_boxes() {
+        let assert_box = |object: &dyn Hittable, min: Vec3, max: Vec3| {
+            let b = object.bounding_box(0.0, 1.0).unwrap();
+            assert!(
+                (b.min - min).mag() < 1e-9 && (b.max - max).mag() < 1e-9,
+                "{b:?} isn't from {min:?} to {max:?}"
+            );
+        };
+        let base = Vec3(1.0, 2.0, 3.0);
+        let (min, max) = (Vec3(0.0, 2.0, 2.0), Vec3(2.0, 4.0, 4.0));
+        assert_box(&Cylinder::new(base, 1.0, 2.0, matte()), min, max);
+        assert_box(&Cone::new(base, 1.0, 2.0, matte()), min, max);
+        assert_box(&Paraboloid::new(base, 1.0, 2.0, matte()), min, max);
+        assert_box(&torus(), Vec3(-2.5, -0.5, -2.5), Vec3(2.5, 0.5, 2.5));
+        let capsule = Capsule::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, -2.0, 3.0), 0.5, matte());
+        assert_box(&capsule, Vec3(-0.5, -2.5, -0.5), Vec3(1.5, 0.5, 3.5));
+    }
+
+    #[test]
+    fn torus_outer_edge_and_through_tube() {
+        let (origin, direction) = (Vec3(-5.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        check(
+            hit(&torus(), origin, direction),
+            2.5,
+            true,
+            Vec3(-1.0, 0.0, 0.0),
+            (0.5, 0.0),
+        );
+        // Leaving the tube through its inner rim, into the hole.
+        let rec = hit_from(&torus(), origin, direction, 3.0);
+        check(rec, 3.5, false, Vec3(-1.0, 0.0, 0.0), (0.5, 0.5));
+    }
+
+    #[test]
+    fn torus_inner_rim_from_hole() {
+        let rec = hit(&torus(), Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        check(rec, 1.5, true, Vec3(-1.0, 0.0, 0.0), (0.0, 0.5));
+    }
+
+    #[test]
+    fn torus_top() {
+        let rec = hit(&torus(), Vec3(2.0, 5.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        check(rec, 4.5, true, Vec3(0.0, 1.0, 0.0), (0.0, 0.25));
+    }
+
+    #[test]
+    fn torus_through_hole() {
+        assert!(hit(&torus(), Vec3(0.0, 5.0, 0.0), Vec3(0.0, -1.0, 0.0)).is_none());
+        assert!(hit(&torus(), Vec3(1.0, 5.0, 0.5), Vec3(0.0, -1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn quartic_distinct_roots() {
+        // (t - 1)(t - 2)(t - 3)(t - 4)
+        let c = [24.0, -50.0, 35.0, -10.0];
+        let root = |lo, hi| first_quartic_root(c, lo, hi);
+        assert!((root(0.0, 10.0).unwrap() - 1.0).abs() < 1e-9);
+        assert!((root(1.5, 10.0).unwrap() - 2.0).abs() < 1e-9);
+        assert!((root(3.5, 10.0).unwrap() - 4.0).abs() < 1e-9);
+        assert!(root(4.5, 10.0).is_none());
+        assert!(root(1.2, 1.8).is_none());
+    }
+
+    #[test]
+    fn quartic_double_root() {
+        // (t - 1)^2 (t - 3)(t - 4), which touches zero at 1 without crossing it.
+        let c = [12.0, -31.0, 27.0, -9.0];
+        let root = |lo, hi| first_quartic_root(c, lo, hi);
+        assert!((root(0.0, 10.0).unwrap() - 1.0).abs() < 1e-6);
+        assert!((root(2.0, 10.0).unwrap() - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cubic_repeated_roots() {
+        let roots = |a, b, c| {
+            let mut roots = cubic_roots(a, b, c);
+            roots.sort_by(f64::total_cmp);
+            roots
+        };
+        let assert_roots = |got: Vec<f64>, expected: &[f64]| {
+            assert_eq!(got.len(), expected.len(), "{got:?} isn't {expected:?}");
+            for (g, e) in got.iter().zip(expected) {
+                assert!((g - e).abs() < 1e-6, "{got:?} isn't {expected:?}");
+            }
+        };
+
+        // (t - 1)^2 (t + 2), whose discriminant is exactly zero.
+        assert_roots(roots(0.0, -3.0, 2.0), &[-2.0, 1.0, 1.0]);
+        // (t - 2)^2 (t - 5), the same with a shift.
+        assert_roots(roots(-9.0, 24.0, -20.0), &[2.0, 2.0, 5.0]);
+        // (t - 1)^3
+        assert_roots(roots(-3.0, 3.0, -1.0), &[1.0]);
+        // (t - 1)(t^2 + 1) and (t - 1)(t - 2)(t - 3)
+        assert_roots(roots(-1.0, 1.0, -1.0), &[1.0]);
+        assert_roots(roots(-6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn quartic_without_real_roots() {
+        // t^4 + 1
+        assert!(first_quartic_root([1.0, 0.0, 0.0, 0.0], -10.0, 10.0).is_none());
+        // (t^2 + 1)(t^2 + 4)
+        assert!(first_quartic_root([4.0, 0.0, 5.0, 0.0], -10.0, 10.0).is_none());
+    }
+}
@@ -0,0 +1,947 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::aov::Aov;
+use crate::camera::Camera;
+use crate::checkpoint;
+use crate::colour::Colour;
+use crate::film::{CropWindow, Film, FilmTile, Filter};
+use crate::hittable::{HitRecord, Hittable};
+use crate::image::Image;
+use crate::progress::{CancelToken, Progress, RenderObserver};
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::tile::{Tile, TileSettings};
+
+/// Settings controlling the size and quality of a render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,   // Number of rays traced per pixel.
+    pub max_depth: u32, // Maximum number of bounces before a path is terminated.
+    pub seed: u64,      // Renders with the same seed produce the same image.
+    pub sampler: SamplerKind,
+    pub aovs: Vec<Aov>, // Extra buffers to fill in alongside the beauty pass.
+    pub adaptive: Option<AdaptiveSettings>, // Spend 'samples' per pixel on average, rather than on every pixel.
+    pub filter: Filter, // Reconstruction filter the beauty pass is developed with.
+    pub crop: Option<CropWindow>, // Only render this part of the image.
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 300,
+            height: 300,
+            samples: 200,
+            max_depth: 50,
+            seed: 0,
+            sampler: SamplerKind::Independent,
+            aovs: Vec::new(),
+            adaptive: None,
+            filter: Filter::default(),
+            crop: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Return the part of the image being rendered: the crop window, clipped to the image, or the whole image.
+    pub fn crop_window(&self) -> CropWindow {
+        self.crop
+            .unwrap_or_else(|| CropWindow::full(self.width, self.height))
+            .clamp(self.width, self.height)
+    }
+}
+
+/// Settings for adaptive sampling, which stops sampling pixels once they are below a noise threshold and spends the
+/// samples saved on the pixels that are still noisy.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSettings {
+    pub min_samples: u32, // Samples taken in every pixel before checking whether it has converged. At least 2.
+    pub max_samples: u32, // Most samples any one pixel may take.
+    pub threshold: f64, // Standard error of a pixel's luminance, relative to its square root, at which it has converged.
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: 1024,
+            threshold: 0.05,
+        }
+    }
+}
+
+/// Limits for an open-ended render, which keeps adding passes until one of them is reached rather than stopping at a
+/// fixed number of samples. 'RenderSettings::samples' still caps the samples in each pixel, so set it high.
+///
+/// A pass isn't started if it looks like it would overrun the time, but the first is always rendered so that there's
+/// an image to show. The noise is the RMS over all pixels of the
+/// relative error that adaptive sampling uses.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderBudget {
+    pub time: Option<Duration>, // Wall-clock time to render for.
+    pub noise: Option<f64>,     // Noise to stop at.
+}
+
+/// The images produced by a render: the beauty pass, its variance and one image for each requested AOV. They cover
+/// the crop window, if there is one, rather than the whole image.
+///
+/// The beauty pass is developed with the render's reconstruction filter. The variance and the AOVs only ever use the
+/// samples taken inside each pixel, as if the filter were the default box.
+#[derive(Clone, Debug)]
+pub struct RenderOutput {
+    pub beauty: Image,
+    pub variance: Image, // Estimated variance of each pixel, per channel. Zero with 1 spp.
+    pub aovs: Vec<(Aov, Image)>,
+}
+
+impl RenderOutput {
+    /// Return the image rendered for 'aov', if it was requested.
+    pub fn aov(&self, aov: Aov) -> Option<&Image> {
+        self.aovs
+            .iter()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, image)| image)
+    }
+}
+
+/// Trace a ray through the world, returning the Colour of the light travelling back along it.
+pub fn ray_colour(
+    r: &Ray,
+    bg: Colour,
+    world: &dyn Hittable,
+    sampler: &mut dyn Sampler,
+    depth: u32,
+) -> Colour {
+    let hit = world.hit(r, 0.001, f64::MAX);
+    shade(r, hit.as_ref(), bg, world, sampler, depth)
+}
+
+/// Return the Colour of the light travelling back along a ray that has already been intersected with the world.
+fn shade(
+    r: &Ray,
+    hit: Option<&HitRecord>,
+    bg: Colour,
+    world: &dyn Hittable,
+    sampler: &mut dyn Sampler,
+    depth: u32,
+) -> Colour {
+    if let Some(hit) = hit {
+        let emitted = hit.material.emitted(hit.u, hit.v, hit.p);
+        if depth > 0 {
+            sampler.start_bounce();
+            if let Some((scattered, attenuation)) = hit.material.scatter(hit, r, sampler) {
+                return emitted
+                    + attenuation * ray_colour(&scattered, bg, world, sampler, depth - 1);
+            }
+        }
+        emitted
+    } else {
+        bg
+    }
+}
+
+/// Render the world as seen by the camera. Returns the average linear Colour of each pixel.
+pub fn render(world: &dyn Hittable, camera: &Camera, settings: &RenderSettings) -> Image {
+    render_with_aovs(world, camera, settings).beauty
+}
+
+/// Render the world as seen by the camera, filling in the AOVs requested by 'settings' as well as the beauty pass.
+pub fn render_with_aovs(
+    world: &dyn Hittable,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> RenderOutput {
+    let mut progressive = ProgressiveRender::new(world, camera, settings);
+    while progressive.render_pass() {}
+    progressive.output()
+}
+
+/// A render that is built up over a series of passes, each adding a few samples to every pixel that needs them. The
+/// image can be looked at between passes, and the render saved to a checkpoint and resumed later.
+///
+/// Each sample is seeded from the render's seed, its pixel and its index, so the accumulated totals and the number of
+/// samples in each pixel are all the state a render has. Splitting a render into passes, or stopping and resuming
+/// it, gives exactly the same image as rendering it in one go.
+///
+/// Alongside the totals of each pixel, samples are splatted into a Film with the render's reconstruction filter to
+/// develop the beauty pass. Tiles are merged into the film in the same order every pass, so it doesn't depend on
+/// which tiles finish first either.
+pub struct ProgressiveRender<'a> {
+    renderer: Renderer<'a>,
+    pixels: Vec<PixelState>, // Totals for each pixel of the crop window, row by row from its top left.
+    film: Film,
+    tiles: TileSettings,
+    observer: Option<&'a dyn RenderObserver>,
+    cancel: CancelToken,
+    budget: RenderBudget,
+    pass: u32,                        // Passes finished since starting or resuming.
+    last_pass_time: Option<Duration>, // How long the last pass took.
+    start: Instant,                   // When the render was started or resumed.
+    start_samples: u64,               // Samples taken before the render was resumed.
+}
+
+impl<'a> ProgressiveRender<'a> {
+    /// Start a new render with no samples.
+    pub fn new(world: &'a dyn Hittable, camera: &'a Camera, settings: &'a RenderSettings) -> Self {
+        let crop = settings.crop_window();
+        let pixels =
+            vec![PixelState::new(settings.aovs.len()); (crop.width() * crop.height()) as usize];
+        let film = Film::new(
+            settings.width,
+            settings.height,
+            settings.filter,
+            settings.crop,
+        );
+        Self::with_pixels(world, camera, settings, pixels, film)
+    }
+
+    /// Continue a render from a checkpoint written by 'write_checkpoint'. The settings must match the ones the
+    /// checkpoint was made with, except that 'samples' may be raised to keep adding samples when the sampler isn't
+    /// stratified and the render isn't adaptive. The scene must be the same too, but that can't be checked.
+    pub fn resume(
+        world: &'a dyn Hittable,
+        camera: &'a Camera,
+        settings: &'a RenderSettings,
+        input: &mut impl Read,
+    ) -> io::Result<Self> {
+        let (pixels, film_pixels) = checkpoint::read(settings, input)?;
+        let mut film = Film::new(
+            settings.width,
+            settings.height,
+            settings.filter,
+            settings.crop,
+        );
+        film.set_pixels(film_pixels);
+        Ok(Self::with_pixels(world, camera, settings, pixels, film))
+    }
+
+    fn with_pixels(
+        world: &'a dyn Hittable,
+        camera: &'a Camera,
+        settings: &'a RenderSettings,
+        pixels: Vec<PixelState>,
+        film: Film,
+    ) -> Self {
+        let start_samples = pixels.iter().map(|p| p.samples as u64).sum();
+        Self {
+            renderer: Renderer {
+                world,
+                camera,
+                settings,
+            },
+            pixels,
+            film,
+            tiles: TileSettings::default(),
+            observer: None,
+            cancel: CancelToken::new(),
+            budget: RenderBudget::default(),
+            pass: 0,
+            last_pass_time: None,
+            start: Instant::now(),
+            start_samples,
+        }
+    }
+
+    /// Split each pass into tiles with 'tiles' rather than the default settings.
+    pub fn with_tiles(mut self, tiles: TileSettings) -> Self {
+        self.tiles = tiles;
+        self
+    }
+
+    /// Report progress to 'observer' as tiles and passes finish.
+    pub fn with_observer(mut self, observer: &'a dyn RenderObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Keep adding passes until 'budget' is used up, rather than stopping once every pixel has 'samples' samples.
+    pub fn with_budget(mut self, budget: RenderBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Stop rendering when 'cancel' is cancelled.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Save the state of the render so it can be resumed later.
+    pub fn write_checkpoint(&self, out: &mut impl Write) -> io::Result<()> {
+        checkpoint::write(
+            self.renderer.settings,
+            &self.pixels,
+            &self.film.pixels(),
+            out,
+        )
+    }
+
+    /// Render the next pass, tile by tile. Returns false, without changing the render, once it is finished or has
+    /// been cancelled.
+    ///
+    /// A pass that is cancelled part way through is thrown away, so the render is always left at the end of a whole
+    /// pass and a checkpoint of it still resumes to the same image.
+    pub fn render_pass(&mut self) -> bool {
+        let settings = self.renderer.settings;
+        let samples = self.next_pass_samples();
+        if samples == 0 || self.cancel.is_cancelled() || self.budget_reached() {
+            return false;
+        }
+        let pass_start = Instant::now();
+        let max_samples = match settings.adaptive {
+            None => settings.samples,
+            Some(adaptive) => adaptive.max_samples.max(adaptive.min_samples.max(2)),
+        };
+
+        let crop = self.film.crop;
+        let width = crop.width() as usize;
+        let tiles: Vec<Tile> = self
+            .tiles
+            .tiles(crop.width(), crop.height())
+            .into_iter()
+            .map(|t| Tile {
+                x0: t.x0 + crop.x0,
+                y0: t.y0 + crop.y0,
+                x1: t.x1 + crop.x0,
+                y1: t.y1 + crop.y0,
+            })
+            .collect();
+        let tiles_done = AtomicU32::new(0);
+        let samples_done = AtomicU64::new(self.samples_taken());
+
+        let pixels = &self.pixels;
+        let mut rendered: Vec<(Tile, Vec<PixelState>, FilmTile)> = tiles
+            .iter()
+            .par_bridge()
+            .filter_map(|tile| {
+                if self.cancel.is_cancelled() {
+                    return None;
+                }
+
+                // Render a copy of the tile's pixels, so nothing changes if the pass is cancelled.
+                let mut states: Vec<PixelState> = (tile.y0..tile.y1)
+                    .flat_map(|y| {
+                        let row = (y - crop.y0) as usize * width;
+                        let x0 = (tile.x0 - crop.x0) as usize;
+                        pixels[row + x0..row + x0 + tile.width() as usize].iter()
+                    })
+                    .cloned()
+                    .collect();
+                let mut film_tile = self.film.tile(tile);
+                let taken = self.renderer.render_tile(
+                    tile,
+                    &mut states,
+                    &mut film_tile,
+                    samples,
+                    max_samples,
+                );
+
+                let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                let taken = samples_done.fetch_add(taken, Ordering::Relaxed) + taken;
+                if let Some(observer) = self.observer {
+                    observer.tile_finished(&self.progress(done, tiles.len() as u32, taken));
+                }
+                Some((*tile, states, film_tile))
+            })
+            .collect();
+
+        if rendered.len() != tiles.len() {
+            return false;
+        }
+        // Tiles overlap on the film when the filter is wider than a pixel, so merge them in a fixed order to keep
+        // the sums the same from run to run. It's row by row rather than the order they were rendered in, which
+        // would make the image depend on the tile order.
+        rendered.sort_by_key(|(tile, ..)| (tile.y0, tile.x0));
+        for (tile, states, film_tile) in rendered {
+            for (y, row) in (tile.y0..tile.y1).zip(states.chunks(tile.width() as usize)) {
+                let start = (y - crop.y0) as usize * width + (tile.x0 - crop.x0) as usize;
+                self.pixels[start..start + row.len()].clone_from_slice(row);
+            }
+            self.film.merge_tile(&film_tile);
+        }
+        if let Some(adaptive) = settings.adaptive {
+            self.update_converged(&adaptive);
+        }
+
+        self.last_pass_time = Some(pass_start.elapsed());
+        if let Some(observer) = self.observer {
+            let n = tiles.len() as u32;
+            observer.pass_finished(&self.progress(n, n, self.samples_taken()));
+        }
+        self.pass += 1;
+        true
+    }
+
+    /// Whether the render has used up its time budget or reached its noise target.
+    fn budget_reached(&self) -> bool {
+        if let (Some(time), Some(next_pass)) = (self.budget.time, self.last_pass_time) {
+            if self.start.elapsed() + next_pass > time {
+                return true;
+            }
+        }
+        match (self.budget.noise, self.noise()) {
+            (Some(target), Some(noise)) => noise <= target,
+            _ => false,
+        }
+    }
+
+    /// Return the RMS over all pixels of their relative error, or None if some pixels don't have enough samples to
+    /// tell yet.
+    pub fn noise(&self) -> Option<f64> {
+        if self.pixels.is_empty() || self.pixels.iter().any(|p| p.samples < 2) {
+            return None;
+        }
+        let sum_sq: f64 = self.pixels.iter().map(|p| p.error().powi(2)).sum();
+        Some((sum_sq / self.pixels.len() as f64).sqrt())
+    }
+
+    /// Describe the progress of the render, part way through a pass.
+    fn progress(&self, tiles_done: u32, tiles: u32, samples: u64) -> Progress {
+        let settings = self.renderer.settings;
+        let elapsed = self.start.elapsed();
+        let rate = (samples - self.start_samples) as f64 / elapsed.as_secs_f64();
+
+        // Guess how many samples the render will end up taking from whichever limit it'll reach first. Noise falls
+        // with the square root of the number of samples, so the noise target is reached after (noise / target)^2
+        // times as many samples as have been taken.
+        let mut total_samples = self.pixels.len() as u64 * settings.samples as u64;
+        if let (Some(time), true) = (self.budget.time, rate.is_finite()) {
+            let left = time.saturating_sub(elapsed).as_secs_f64();
+            total_samples = total_samples.min(samples + (rate * left) as u64);
+        }
+        if let (Some(target), Some(noise)) = (self.budget.noise, self.noise()) {
+            let taken = self.samples_taken() as f64;
+            total_samples = total_samples.min((taken * (noise / target).powi(2)) as u64);
+        }
+        let total_samples = total_samples.max(samples);
+
+        // Estimate the time left from the rate samples have been taken at since the render started or resumed. Until
+        // there's a rate to go on, the estimate isn't a finite number of seconds and there's no ETA.
+        let eta = Duration::try_from_secs_f64((total_samples - samples) as f64 / rate).ok();
+
+        Progress {
+            pass: self.pass + 1,
+            tiles_done,
+            tiles,
+            samples,
+            total_samples,
+            elapsed,
+            eta,
+        }
+    }
+
+    /// Whether every pixel has all the samples it needs, or the budget has been reached.
+    pub fn is_finished(&self) -> bool {
+        self.next_pass_samples() == 0 || self.budget_reached()
+    }
+
+    /// Return the total number of samples taken so far.
+    pub fn samples_taken(&self) -> u64 {
+        self.pixels.iter().map(|p| p.samples as u64).sum()
+    }
+
+    /// Return the images as they are after the passes rendered so far.
+    pub fn output(&self) -> RenderOutput {
+        RenderOutput {
+            beauty: self.film.image(),
+            ..self.renderer.output(&self.pixels)
+        }
+    }
+
+    /// Return the number of samples the next pass adds to each pixel that needs more.
+    ///
+    /// Without adaptive sampling every pixel gets the same number of samples, PASS_SAMPLES at a time. With it, every
+    /// pixel first gets 'min_samples', then what's left of the budget is spread evenly over the pixels that haven't
+    /// converged, at most 'min_samples' at a time.
+    fn next_pass_samples(&self) -> u32 {
+        let settings = self.renderer.settings;
+        let adaptive = match settings.adaptive {
+            None => {
+                let taken = self.pixels.first().map_or(settings.samples, |p| p.samples);
+                return settings.samples.saturating_sub(taken).min(PASS_SAMPLES);
+            }
+            Some(adaptive) => adaptive,
+        };
+
+        let min_samples = adaptive.min_samples.max(2);
+        if self.pixels.iter().any(|p| p.samples < min_samples) {
+            return min_samples;
+        }
+
+        let active = self.pixels.iter().filter(|p| !p.converged).count() as u64;
+        if active == 0 {
+            return 0;
+        }
+        let budget = self.pixels.len() as u64 * settings.samples as u64;
+        (budget.saturating_sub(self.samples_taken()) / active).min(min_samples as u64) as u32
+    }
+
+    /// Mark the pixels that need no more samples after an adaptive pass.
+    fn update_converged(&mut self, adaptive: &AdaptiveSettings) {
+        let max_samples = adaptive.max_samples.max(adaptive.min_samples.max(2));
+
+        // A pixel whose samples have all been black so far looks converged, even when it's in a dimly lit area
+        // that just hasn't found the light yet. Taking the worst error of its neighbours catches most of these.
+        let errors: Vec<f64> = self.pixels.iter().map(|s| s.error()).collect();
+        let width = self.film.crop.width() as usize;
+        let height = self.pixels.len() / width;
+
+        for (k, state) in self.pixels.iter_mut().enumerate() {
+            if state.converged {
+                continue;
+            }
+            let (x, y) = (k % width, k / width);
+            let error = (y.saturating_sub(1)..(y + 2).min(height))
+                .flat_map(|y| (x.saturating_sub(1)..(x + 2).min(width)).map(move |x| (x, y)))
+                .map(|(x, y)| errors[y * width + x])
+                .fold(0.0, f64::max);
+            state.converged = state.samples >= max_samples || error < adaptive.threshold;
+        }
+    }
+}
+
+/// Number of samples each pass of a render without adaptive sampling adds to every pixel.
+const PASS_SAMPLES: u32 = 16;
+
+/// Running totals for one pixel of a render in progress.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PixelState {
+    pub(crate) sum: Colour,
+    pub(crate) sum_sq: Colour, // Sum of the squares of each sample, for the variance.
+    pub(crate) samples: u32,
+    pub(crate) aovs: Vec<Colour>, // Sums of the filtered AOVs. Unfiltered AOVs hold the value of the first sample.
+    pub(crate) converged: bool,   // Set by adaptive sampling once the pixel needs no more samples.
+}
+
+impl PixelState {
+    pub(crate) fn new(aovs: usize) -> Self {
+        Self {
+            sum: Colour::new(0.0, 0.0, 0.0),
+            sum_sq: Colour::new(0.0, 0.0, 0.0),
+            samples: 0,
+            aovs: vec![Colour::new(0.0, 0.0, 0.0); aovs],
+            converged: false,
+        }
+    }
+
+    fn mean(&self) -> Colour {
+        if self.samples == 0 {
+            return Colour::new(0.0, 0.0, 0.0);
+        }
+        self.sum / self.samples as f64
+    }
+
+    fn variance(&self) -> Colour {
+        variance_of_mean(self.mean(), self.sum_sq, self.samples)
+    }
+
+    /// Estimate the error of the pixel's luminance relative to its square root, so that dark pixels, where noise is
+    /// more visible, need to be less noisy to count as converged.
+    fn error(&self) -> f64 {
+        let mean = self.mean().luminance().max(0.0);
+        let std_err = self.variance().luminance().max(0.0).sqrt();
+        if std_err == 0.0 {
+            return 0.0;
+        }
+        std_err / (mean.sqrt() + 1e-4)
+    }
+}
+
+/// The world, camera and settings of a render, shared by every pass.
+struct Renderer<'a> {
+    world: &'a dyn Hittable,
+    camera: &'a Camera,
+    settings: &'a RenderSettings,
+}
+
+impl Renderer<'_> {
+    /// Add 'samples' more samples to every pixel of 'tile' that hasn't converged, stopping at 'max_samples'. 'states'
+    /// holds the tile's pixels row by row, and each sample is also splatted into 'film'. Returns the number of
+    /// samples taken.
+    ///
+    /// When the tile is on the edge of a crop window, the pixels outside the window that the filter reaches from it
+    /// are sampled too, into the film only, so the crop comes out the same as that part of a render of the whole
+    /// image. Adaptive renders don't do this, as how many samples those pixels would have taken isn't known.
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        states: &mut [PixelState],
+        film: &mut FilmTile,
+        samples: u32,
+        max_samples: u32,
+    ) -> u64 {
+        let RenderSettings {
+            width,
+            height,
+            seed,
+            sampler,
+            ..
+        } = *self.settings;
+        let mut sampler = sampler.create(seed, self.settings.samples);
+        let mut taken = 0;
+        let first = states.first().map_or(0, |s| s.samples);
+
+        for (y, row) in (tile.y0..tile.y1).zip(states.chunks_mut(tile.width() as usize)) {
+            let j = height - 1 - y;
+            for (i, state) in (tile.x0..tile.x1).zip(row) {
+                if state.converged {
+                    continue;
+                }
+                let end = state.samples.saturating_add(samples).min(max_samples);
+                taken += end.saturating_sub(state.samples) as u64;
+                while state.samples < end {
+                    self.sample_pixel(state, film, i, j, sampler.as_mut());
+                }
+            }
+        }
+
+        if self.settings.adaptive.is_none() {
+            let crop = self.settings.crop_window();
+            let reach = self.settings.filter.reach();
+            let outside = Tile {
+                x0: match tile.x0 == crop.x0 {
+                    true => tile.x0.saturating_sub(reach),
+                    false => tile.x0,
+                },
+                y0: match tile.y0 == crop.y0 {
+                    true => tile.y0.saturating_sub(reach),
+                    false => tile.y0,
+                },
+                x1: match tile.x1 == crop.x1 {
+                    true => (tile.x1 + reach).min(width),
+                    false => tile.x1,
+                },
+                y1: match tile.y1 == crop.y1 {
+                    true => (tile.y1 + reach).min(height),
+                    false => tile.y1,
+                },
+            };
+            let end = first.saturating_add(samples).min(max_samples);
+            let mut scratch = PixelState::new(self.settings.aovs.len());
+            for y in outside.y0..outside.y1 {
+                for i in outside.x0..outside.x1 {
+                    if crop.x0 <= i && i < crop.x1 && crop.y0 <= y && y < crop.y1 {
+                        continue;
+                    }
+                    scratch.samples = first;
+                    while scratch.samples < end {
+                        self.sample_pixel(&mut scratch, film, i, height - 1 - y, sampler.as_mut());
+                    }
+                }
+            }
+        }
+        taken
+    }
+
+    /// Trace the next sample of the pixel at column 'i' and row 'j', counting rows from the bottom of the image.
+    fn sample_pixel(
+        &self,
+        state: &mut PixelState,
+        film: &mut FilmTile,
+        i: u32,
+        j: u32,
+        sampler: &mut dyn Sampler,
+    ) {
+        let RenderSettings {
+            width,
+            height,
+            max_depth,
+            ref aovs,
+            ..
+        } = *self.settings;
+
+        sampler.start_sample(i, j, state.samples);
+        let (du, dv) = sampler.next_2d();
+        let u = (i as f64 + du) / width as f64;
+        let v = (j as f64 + dv) / height as f64;
+        let r = self.camera.get_ray(u, v, sampler);
+
+        // Intersect the camera ray here, rather than in ray_colour, so the first hit can fill the AOVs.
+        let mut hit = self.world.hit(&r, 0.001, f64::MAX);
+        if let Some(hit) = &mut hit {
+            // Material IDs are only looked up here, for the one sample of the pixel that's kept.
+            if state.samples == 0 && aovs.contains(&Aov::MaterialId) {
+                hit.material_id = self.world.material_id(hit.material);
+            }
+            for (value, aov) in state.aovs.iter_mut().zip(aovs) {
+                match aov.value(&r, hit) {
+                    Some(v) if aov.is_filtered() => *value += v,
+                    Some(v) if state.samples == 0 => *value = v,
+                    _ => (),
+                }
+            }
+        }
+
+        let sample = shade(
+            &r,
+            hit.as_ref(),
+            self.camera.bg,
+            self.world,
+            sampler,
+            max_depth,
+        );
+        film.add_sample(i as f64 + du, j as f64 + dv, sample);
+        state.sum += sample;
+        state.sum_sq += sample * sample;
+        state.samples += 1;
+    }
+
+    /// Turn the accumulated totals of the crop window into images.
+    fn output(&self, pixels: &[PixelState]) -> RenderOutput {
+        let crop = self.settings.crop_window();
+        let aovs = &self.settings.aovs;
+        let image = |f: &dyn Fn(&PixelState) -> Colour| Image {
+            width: crop.width(),
+            height: crop.height(),
+            pixels: pixels.iter().map(f).collect(),
+        };
+
+        let aovs = aovs
+            .iter()
+            .enumerate()
+            .map(|(k, aov)| {
+                let image = match aov {
+                    Aov::Samples => image(&|p| {
+                        let n = p.samples as f64;
+                        Colour::new(n, n, n)
+                    }),
+                    aov if aov.is_filtered() => image(&|p| p.aovs[k] / p.samples.max(1) as f64),
+                    _ => image(&|p| p.aovs[k]),
+                };
+                (*aov, image)
+            })
+            .collect();
+
+        RenderOutput {
+            beauty: image(&|p| p.mean()),
+            variance: image(&|p| p.variance()),
+            aovs,
+        }
+    }
+}
+
+/// Estimate the variance of the mean of 'n' samples from their mean and the sum of their squares.
+fn variance_of_mean(mean: Colour, sum_sq: Colour, n: u32) -> Colour {
+    if n < 2 {
+        return Colour::new(0.0, 0.0, 0.0);
+    }
+    let n = n as f64;
+    let var = |mean: f64, sum_sq: f64| ((sum_sq - n * mean * mean) / (n - 1.0)).max(0.0) / n;
+    Colour::new(
+        var(mean.r, sum_sq.r),
+        var(mean.g, sum_sq.g),
+        var(mean.b, sum_sq.b),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing::{cornell_box_world, small_render};
+
+    /// Settings for a small adaptive render of 16 samples per pixel on average, with a count of each pixel's samples.
+    fn adaptive_render(min_samples: u32, max_samples: u32, threshold: f64) -> RenderSettings {
+        RenderSettings {
+            aovs: vec![Aov::Samples],
+            adaptive: Some(AdaptiveSettings {
+                min_samples,
+                max_samples,
+                threshold,
+            }),
+            ..small_render(16, 5)
+        }
+    }
+
+    #[test]
+    fn constant_region_stops_sampling() {
+        // A strip down the left edge of the Cornell box. Its first two columns look past the box, so every sample
+        // there is the black background.
+        let (world, camera) = cornell_box_world();
+        let settings = RenderSettings {
+            width: 128,
+            height: 128,
+            crop: Some(CropWindow {
+                x0: 0,
+                y0: 0,
+                x1: 8,
+                y1: 128,
+            }),
+            ..adaptive_render(4, 64, 0.05)
+        };
+
+        let mut progressive = ProgressiveRender::new(&world, &camera, &settings);
+        let mut passes = 0;
+        while progressive.render_pass() {
+            passes += 1;
+            for row in progressive.pixels.chunks(8) {
+                assert!(row[0].converged);
+                assert_eq!(row[0].samples, 4);
+                assert_eq!(row[0].sum, Colour::new(0.0, 0.0, 0.0));
+            }
+        }
+        assert!(passes > 1);
+        assert!(progressive.pixels.iter().any(|p| p.samples > 4));
+    }
+
+    #[test]
+    fn never_more_than_max_samples() {
+        let (world, camera) = cornell_box_world();
+        for settings in [adaptive_render(4, 64, 0.05), adaptive_render(4, 8, 0.0)] {
+            let max_samples = settings.adaptive.unwrap().max_samples;
+            let mut progressive = ProgressiveRender::new(&world, &camera, &settings);
+            let pixels = progressive.pixels.len() as u64;
+            while progressive.render_pass() {
+                assert!(progressive.samples_taken() <= max_samples as u64 * pixels);
+                assert!(progressive.samples_taken() <= settings.samples as u64 * pixels);
+                assert!(progressive.pixels.iter().all(|p| p.samples <= max_samples));
+            }
+        }
+
+        // With nothing converging, every pixel stops at the cap well short of the average it could have had.
+        let settings = adaptive_render(4, 8, 0.0);
+        let output = render_with_aovs(&world, &camera, &settings);
+        let samples = output.aov(Aov::Samples).unwrap();
+        assert!(samples.pixels.iter().all(|c| c.r == 8.0));
+    }
+
+    #[test]
+    fn zero_threshold_is_uniform() {
+        let (world, camera) = cornell_box_world();
+        let adaptive = render_with_aovs(&world, &camera, &adaptive_render(4, 64, 0.0));
+        let uniform = render_with_aovs(
+            &world,
+            &camera,
+            &RenderSettings {
+                aovs: vec![Aov::Samples],
+                ..small_render(16, 5)
+            },
+        );
+
+        let counts = |output: &RenderOutput| output.aov(Aov::Samples).unwrap().pixels.clone();
+        assert_eq!(counts(&adaptive), counts(&uniform));
+        assert!(counts(&adaptive).iter().all(|c| c.r == 16.0));
+        for (a, u) in adaptive.beauty.pixels.iter().zip(&uniform.beauty.pixels) {
+            assert!((a.r - u.r).abs() + (a.g - u.g).abs() + (a.b - u.b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn samples_aov_matches_counts() {
+        let (world, camera) = cornell_box_world();
+        let settings = adaptive_render(4, 64, 0.05);
+        let mut progressive = ProgressiveRender::new(&world, &camera, &settings);
+        while progressive.render_pass() {
+            let output = progressive.output();
+            let counts = output.aov(Aov::Samples).unwrap();
+            for (c, p) in counts.pixels.iter().zip(&progressive.pixels) {
+                assert_eq!(
+                    *c,
+                    Colour::new(p.samples as f64, p.samples as f64, p.samples as f64)
+                );
+            }
+        }
+        // Adaptive sampling moved samples around, so the counts aren't all the same.
+        let counts = &progressive.output().aovs[0].1;
+        assert!(counts.pixels.iter().any(|c| c.r != counts.pixels[0].r));
+    }
+
+    /// Render passes until the render finishes, returning how many there were.
+    fn render_passes(progressive: &mut ProgressiveRender) -> u32 {
+        let mut passes = 0;
+        while progressive.render_pass() {
+            passes += 1;
+        }
+        passes
+    }
+
+    #[test]
+    fn noise_target() {
+        let (world, camera) = cornell_box_world();
+        let settings = small_render(64, 5);
+        let full = 64 * (settings.width * settings.height) as u64;
+
+        let budget = |noise| RenderBudget {
+            noise: Some(noise),
+            ..RenderBudget::default()
+        };
+        let mut loose =
+            ProgressiveRender::new(&world, &camera, &settings).with_budget(budget(10.0));
+        assert_eq!(render_passes(&mut loose), 1);
+        assert!(loose.is_finished());
+        assert!(loose.samples_taken() < full);
+
+        let mut strict =
+            ProgressiveRender::new(&world, &camera, &settings).with_budget(budget(1e-6));
+        render_passes(&mut strict);
+        assert_eq!(strict.samples_taken(), full);
+        assert!(strict.noise().unwrap() > 1e-6);
+    }
+
+    #[test]
+    fn zero_time_budget_renders_one_pass() {
+        let (world, camera) = cornell_box_world();
+        let settings = small_render(64, 5);
+        let budget = RenderBudget {
+            time: Some(Duration::ZERO),
+            ..RenderBudget::default()
+        };
+        let mut progressive =
+            ProgressiveRender::new(&world, &camera, &settings).with_budget(budget);
+        assert!(!progressive.is_finished());
+        assert_eq!(render_passes(&mut progressive), 1);
+        let pixels = (settings.width * settings.height) as u64;
+        assert_eq!(progressive.samples_taken(), PASS_SAMPLES as u64 * pixels);
+    }
+
+    /// Keeps every Progress it's told about.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Progress>>);
+
+    impl RenderObserver for Recorder {
+        fn tile_finished(&self, progress: &Progress) {
+            self.0.lock().unwrap().push(*progress);
+        }
+
+        fn pass_finished(&self, progress: &Progress) {
+            self.0.lock().unwrap().push(*progress);
+        }
+    }
+
+    #[test]
+    fn progress_of_first_pass() {
+        let (world, camera) = cornell_box_world();
+        let settings = small_render(64, 5);
+        let recorder = Recorder::default();
+        let budget = RenderBudget {
+            time: Some(Duration::from_secs(60)),
+            noise: Some(0.01),
+        };
+        let mut progressive = ProgressiveRender::new(&world, &camera, &settings)
+            .with_tiles(TileSettings {
+                size: 4,
+                ..TileSettings::default()
+            })
+            .with_budget(budget)
+            .with_observer(&recorder);
+        assert!(progressive.render_pass());
+
+        // The first tiles can finish before any time seems to have passed, and estimating the time left from a rate
+        // of NaN or infinity would have panicked.
+        let progress = recorder.0.into_inner().unwrap();
+        assert_eq!(progress.len(), 37);
+        for p in &progress {
+            assert_eq!(p.pass, 1);
+            assert!(p.samples <= p.total_samples);
+            assert!((0.0..=1.0).contains(&p.fraction()));
+        }
+        assert!(progress.last().unwrap().eta.is_some());
+    }
+}
@@ -0,0 +1,598 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::named::named_enum;
+
+/// Number of dimensions used to generate a camera ray: two for the film position, two for the lens and one for time.
+pub const CAMERA_DIMENSIONS: u32 = 5;
+
+/// Number of dimensions reserved for each bounce of a path. Materials must not use more than this per scatter.
+pub const BOUNCE_DIMENSIONS: u32 = 3;
+
+/// A Sampler supplies the random numbers used while rendering. Each sample of each pixel is seeded independently so
+/// a render with a given seed produces the same image regardless of the order pixels are rendered in.
+///
+/// The values of a sample are split into dimensions. The first CAMERA_DIMENSIONS are used by the camera and each
+/// bounce of the path then gets its own block of BOUNCE_DIMENSIONS. Keeping the same dimensions for the same purpose
+/// in every sample is what lets low-discrepancy samplers spread their samples evenly.
+pub trait Sampler {
+    /// Prepare to generate sample number 'index' of the pixel at column 'x' and row 'y'.
+    fn start_sample(&mut self, x: u32, y: u32, index: u32);
+
+    /// Skip to the block of dimensions reserved for the next bounce of the current path.
+    fn start_bounce(&mut self);
+
+    /// Return the next dimension of the current sample. Values are in the range [0, 1).
+    fn next_1d(&mut self) -> f64;
+
+    /// Return the next two dimensions of the current sample. Values are in the range [0, 1).
+    fn next_2d(&mut self) -> (f64, f64) {
+        let u = self.next_1d();
+        let v = self.next_1d();
+        (u, v)
+    }
+}
+
+/// The kinds of Sampler available to the renderer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    /// Create a Sampler of this kind for a render taking 'samples' samples per pixel.
+    pub fn create(self, seed: u64, samples: u32) -> Box<dyn Sampler + Send> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+named_enum!(SamplerKind, "sampler");
+
+/// Mix the bits of 'v' so that similar inputs produce very different outputs. This is the SplitMix64 finaliser.
+#[inline]
+pub fn mix_bits(mut v: u64) -> u64 {
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    v ^ (v >> 31)
+}
+
+/// Combine a seed with a pixel position and sample index into a single well mixed value.
+#[inline]
+pub fn hash_sample(seed: u64, x: u32, y: u32, index: u32) -> u64 {
+    let h = mix_bits(seed ^ ((x as u64) << 32 | y as u64));
+    mix_bits(h ^ index as u64)
+}
+
+/// Convert the top 53 bits of a hash into a value in the range [0, 1).
+#[inline]
+fn hash_to_f64(h: u64) -> f64 {
+    (h >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+/// Tracks which dimension of the current sample will be handed out next.
+#[derive(Copy, Clone, Debug, Default)]
+struct Dimensions {
+    next: u32,
+    bounce: u32,
+}
+
+impl Dimensions {
+    fn reset(&mut self) {
+        self.next = 0;
+        self.bounce = 0;
+    }
+
+    fn start_bounce(&mut self) {
+        self.next = CAMERA_DIMENSIONS + self.bounce * BOUNCE_DIMENSIONS;
+        self.bounce += 1;
+    }
+
+    /// Return the next dimension and advance past it.
+    fn take(&mut self) -> u32 {
+        let dim = self.next;
+        self.next += 1;
+        dim
+    }
+}
+
+/// An IndependentSampler returns uniformly distributed pseudo-random values with no correlation between samples.
+#[derive(Clone, Debug)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = Pcg32::seed_from_u64(hash_sample(self.seed, x, y, index));
+    }
+
+    fn start_bounce(&mut self) {}
+
+    fn next_1d(&mut self) -> f64 {
+        self.rng.gen::<f64>()
+    }
+}
+
+/// Shuffle 'i' within the range 0..'n' using a permutation chosen by 'seed'. Each seed gives a different
+/// permutation and every index is visited exactly once. From Kensler's "Correlated Multi-Jittered Sampling".
+fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+/// A StratifiedSampler splits each dimension into one stratum per sample and places a jittered sample in each.
+/// Pairs of dimensions are stratified on a 2D grid when the number of samples is a square number.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    pixel_seed: u64,
+    index: u32,
+    dims: Dimensions,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples: u32) -> Self {
+        Self {
+            seed,
+            samples: samples.max(1),
+            pixel_seed: 0,
+            index: 0,
+            dims: Dimensions::default(),
+        }
+    }
+
+    /// Return the stratum of the current sample in dimension 'dim', and a hash for jittering within it.
+    fn stratum(&self, dim: u32) -> (u32, u64) {
+        let h = mix_bits(self.pixel_seed ^ dim as u64);
+        let index = self.index % self.samples;
+        let stratum = permute(index, self.samples, h as u32);
+        (stratum, mix_bits(h ^ self.index as u64))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel_seed = hash_sample(self.seed, x, y, 0);
+        self.index = index;
+        self.dims.reset();
+    }
+
+    fn start_bounce(&mut self) {
+        self.dims.start_bounce();
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dim = self.dims.take();
+        let (stratum, h) = self.stratum(dim);
+        (stratum as f64 + hash_to_f64(h)) / self.samples as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let side = (self.samples as f64).sqrt() as u32;
+        if side * side != self.samples {
+            let u = self.next_1d();
+            let v = self.next_1d();
+            return (u, v);
+        }
+
+        let dim = self.dims.take();
+        self.dims.take();
+
+        let (stratum, h) = self.stratum(dim);
+        let u = ((stratum % side) as f64 + hash_to_f64(h)) / side as f64;
+        let v = ((stratum / side) as f64 + hash_to_f64(mix_bits(h))) / side as f64;
+        (u, v)
+    }
+}
+
+/// Return the first 'n' prime numbers.
+fn primes(n: usize) -> Vec<u32> {
+    let mut primes: Vec<u32> = Vec::with_capacity(n);
+    let mut candidate = 2;
+    while primes.len() < n {
+        if primes
+            .iter()
+            .take_while(|&&p| p * p <= candidate)
+            .all(|&p| candidate % p != 0)
+        {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+/// A HaltonSampler uses the radical inverse of the sample index in a different prime base for each dimension. Digits
+/// are randomly permuted per pixel and dimension so neighbouring pixels don't share the same pattern. Permuting rather
+/// than shifting them also breaks up the correlation between dimensions with large bases, whose first few points
+/// otherwise lie along a line.
+#[derive(Clone, Debug)]
+pub struct HaltonSampler {
+    seed: u64,
+    primes: Vec<u32>,
+    pixel_seed: u64,
+    index: u32,
+    dims: Dimensions,
+    fallback: Pcg32,
+}
+
+impl HaltonSampler {
+    /// Number of dimensions with their own prime base. Deeper dimensions fall back to independent random values.
+    const MAX_DIMENSIONS: usize = 256;
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            primes: primes(Self::MAX_DIMENSIONS),
+            pixel_seed: 0,
+            index: 0,
+            dims: Dimensions::default(),
+            fallback: Pcg32::seed_from_u64(seed),
+        }
+    }
+
+    /// Radical inverse of the sample index in 'base', with each digit permuted by a permutation chosen by 'seed'.
+    fn scrambled_radical_inverse(&self, base: u32, seed: u64) -> f64 {
+        let inv_base = 1.0 / base as f64;
+        let mut index = self.index as u64;
+        let mut inv_base_n = 1.0;
+        let mut result = 0.0;
+        let mut digit_seed = seed;
+
+        // Keep going past the last non-zero digit so the trailing zeros are permuted too.
+        while inv_base_n > 1e-16 {
+            let digit = index % base as u64;
+            digit_seed = mix_bits(digit_seed);
+            let permuted = permute(digit as u32, base, digit_seed as u32) as u64;
+
+            inv_base_n *= inv_base;
+            result += permuted as f64 * inv_base_n;
+            index /= base as u64;
+        }
+        result.min(1.0 - f64::EPSILON)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel_seed = hash_sample(self.seed, x, y, 0);
+        self.index = index;
+        self.dims.reset();
+        self.fallback = Pcg32::seed_from_u64(hash_sample(self.seed, x, y, index));
+    }
+
+    fn start_bounce(&mut self) {
+        self.dims.start_bounce();
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dim = self.dims.take() as usize;
+        match self.primes.get(dim) {
+            Some(&base) => {
+                let seed = mix_bits(self.pixel_seed ^ dim as u64);
+                self.scrambled_radical_inverse(base, seed)
+            }
+            None => self.fallback.gen::<f64>(),
+        }
+    }
+}
+
+/// Generator matrices for the first four dimensions of the Sobol sequence, one direction number per bit.
+const SOBOL_DIRECTIONS: [[u32; 32]; 4] = sobol_directions();
+
+/// Build the Sobol direction numbers from their primitive polynomials and initial values (Joe and Kuo).
+const fn sobol_directions() -> [[u32; 32]; 4] {
+    // (degree, coefficients, initial direction numbers) for dimensions 1 to 3. Dimension 0 is the van der Corput
+    // sequence.
+    const POLYNOMIALS: [(usize, u32, [u32; 3]); 3] =
+        [(1, 0, [1, 0, 0]), (2, 1, [1, 3, 0]), (3, 1, [1, 3, 1])];
+
+    let mut directions = [[0u32; 32]; 4];
+
+    let mut i = 0;
+    while i < 32 {
+        directions[0][i] = 1 << (31 - i);
+        i += 1;
+    }
+
+    let mut d = 0;
+    while d < 3 {
+        let (s, a, m) = POLYNOMIALS[d];
+        let v = &mut directions[d + 1];
+
+        let mut i = 0;
+        while i < 32 {
+            if i < s {
+                v[i] = m[i] << (31 - i);
+            } else {
+                v[i] = v[i - s] ^ (v[i - s] >> s);
+                let mut k = 1;
+                while k < s {
+                    if (a >> (s - 1 - k)) & 1 == 1 {
+                        v[i] ^= v[i - k];
+                    }
+                    k += 1;
+                }
+            }
+            i += 1;
+        }
+        d += 1;
+    }
+    directions
+}
+
+/// Return dimension 'dim' of Sobol point number 'index' as a fixed point fraction.
+fn sobol(mut index: u32, dim: usize) -> u32 {
+    let mut x = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            x ^= SOBOL_DIRECTIONS[dim][bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    x
+}
+
+/// Hash based Owen scrambling of a fixed point fraction. Flips each bit based on the bits above it.
+/// From Burley's "Practical Hash-based Owen Scrambling", using Vegdahl's improved permutation.
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x ^= x.wrapping_mul(0x3d20adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x05526c56);
+    x ^= x.wrapping_mul(0x53a22864);
+    x.reverse_bits()
+}
+
+/// A SobolSampler uses an Owen scrambled Sobol sequence. Dimensions are handed out in groups of at most four, and
+/// each group shuffles the sample index separately so the groups aren't correlated with each other. The camera's
+/// dimensions are split into the film and lens, then the time, and each bounce gets a group of its own, so the pairs
+/// drawn with next_2d never straddle two groups.
+#[derive(Clone, Debug)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dims: Dimensions,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dims: Dimensions::default(),
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel_seed = hash_sample(self.seed, x, y, 0);
+        self.index = index;
+        self.dims.reset();
+    }
+
+    fn start_bounce(&mut self) {
+        self.dims.start_bounce();
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dim = self.dims.take();
+        let (group, offset) = match dim.checked_sub(CAMERA_DIMENSIONS) {
+            None => (dim / 4, dim % 4),
+            Some(d) => (2 + d / BOUNCE_DIMENSIONS, d % BOUNCE_DIMENSIONS),
+        };
+
+        let group_seed = mix_bits(self.pixel_seed ^ group as u64);
+        let index = owen_scramble(self.index, group_seed as u32);
+
+        let dim_seed = mix_bits(group_seed ^ (offset + 1) as u64);
+        let x = owen_scramble(sobol(index, offset as usize), dim_seed as u32);
+        x as f64 * (1.0 / 4_294_967_296.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render, RenderSettings};
+    use crate::testing::{cornell_box_world, mse, small_render};
+
+    /// Draw the values of one sample, over the camera dimensions and a few bounces.
+    fn draw(sampler: &mut dyn Sampler, x: u32, y: u32, index: u32) -> Vec<f64> {
+        sampler.start_sample(x, y, index);
+        let mut values = Vec::new();
+        let (u, v) = sampler.next_2d();
+        values.extend([u, v]);
+        for _ in 2..CAMERA_DIMENSIONS {
+            values.push(sampler.next_1d());
+        }
+        for _ in 0..100 {
+            sampler.start_bounce();
+            let (u, v) = sampler.next_2d();
+            values.extend([u, v, sampler.next_1d()]);
+        }
+        values
+    }
+
+    #[test]
+    fn values_are_in_range() {
+        for kind in SamplerKind::ALL {
+            let mut sampler = kind.create(7, 16);
+            for (x, y) in [(0, 0), (3, 1), (250, 199)] {
+                for index in 0..40 {
+                    for value in draw(sampler.as_mut(), x, y, index) {
+                        assert!(
+                            (0.0..1.0).contains(&value),
+                            "{kind} sampler gave {value} for sample {index} of ({x}, {y})"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_deterministic() {
+        for kind in SamplerKind::ALL {
+            let mut a = kind.create(7, 16);
+            let mut b = kind.create(7, 16);
+            let first = draw(a.as_mut(), 3, 1, 5);
+
+            // Other samples in between don't change it, and nor does which sampler draws it.
+            draw(a.as_mut(), 4, 1, 6);
+            assert_eq!(draw(a.as_mut(), 3, 1, 5), first, "{kind} sampler");
+            draw(b.as_mut(), 0, 0, 0);
+            assert_eq!(draw(b.as_mut(), 3, 1, 5), first, "{kind} sampler");
+
+            // Changing the pixel, index or seed changes the values.
+            assert_ne!(draw(a.as_mut(), 1, 3, 5), first, "{kind} sampler");
+            assert_ne!(draw(a.as_mut(), 3, 1, 6), first, "{kind} sampler");
+            assert_ne!(
+                draw(kind.create(8, 16).as_mut(), 3, 1, 5),
+                first,
+                "{kind} sampler"
+            );
+        }
+    }
+
+    #[test]
+    fn sobol_stratifies_pairs_of_later_bounces() {
+        let mut sampler = SobolSampler::new(7);
+        for bounce in [0, 2, 5] {
+            let points: Vec<(f64, f64)> = (0..16)
+                .map(|index| {
+                    sampler.start_sample(3, 1, index);
+                    for _ in 0..=bounce {
+                        sampler.start_bounce();
+                    }
+                    sampler.next_2d()
+                })
+                .collect();
+
+            // Sixteen points of a (0, 2) sequence put one point in every elementary interval of area 1/16.
+            for k in 0..=4 {
+                let (nx, ny) = (1 << k, 1 << (4 - k));
+                let mut counts = vec![0; 16];
+                for &(u, v) in &points {
+                    let cell = (u * nx as f64) as usize + (v * ny as f64) as usize * nx;
+                    counts[cell] += 1;
+                }
+                assert!(
+                    counts.iter().all(|&c| c == 1),
+                    "bounce {bounce} isn't stratified on a {nx}x{ny} grid: {counts:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_beat_independent() {
+        let (world, camera) = cornell_box_world();
+        let settings = |sampler, samples, seed| RenderSettings {
+            sampler,
+            ..small_render(samples, seed)
+        };
+
+        let reference = render(
+            &world,
+            &camera,
+            &settings(SamplerKind::Independent, 1024, 1),
+        );
+        // One image's error depends a lot on its seed, so average over a few.
+        let error = |kind| {
+            let seeds = [2, 3, 4, 5, 6, 7, 8, 9];
+            let total: f64 = seeds
+                .iter()
+                .map(|&seed| {
+                    mse(
+                        &render(&world, &camera, &settings(kind, 16, seed)),
+                        &reference,
+                    )
+                })
+                .sum();
+            total / seeds.len() as f64
+        };
+        let independent = error(SamplerKind::Independent);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let e = error(kind);
+            assert!(
+                e < independent,
+                "{kind} sampler has an MSE of {e}, independent has {independent}"
+            );
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use rand::Rng;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use crate::texture::{CheckeredTexture, NoiseTexture, SolidColour};
 
@@ -8,24 +11,73 @@ use crate::hittable::HittableList;
 use crate::material::{Dielectric, Lambertian, Metal, DiffuseLight};
 
 use crate::cuboid::Cuboid;
+use crate::instance::Instance;
+use crate::planar::InfinitePlane;
 use crate::rect::{XYRect, XZRect, YZRect};
 use crate::sphere::Sphere;
+use crate::transform::Transform;
 
+use crate::named::named_enum;
 use crate::vec::Vec3;
-use crate::vec::Axis::{self, *};
 
-pub fn random_scene(aspect_ratio: f64) -> (HittableList, Camera) {
+/// The built-in scenes, selectable by name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scene {
+    RandomScene,
+    TwoCheckeredSpheres,
+    TwoPerlinSpheres,
+    SimpleLight,
+    CornellBox,
+}
+
+impl Scene {
+    pub const ALL: [Scene; 5] = [
+        Scene::RandomScene,
+        Scene::TwoCheckeredSpheres,
+        Scene::TwoPerlinSpheres,
+        Scene::SimpleLight,
+        Scene::CornellBox,
+    ];
+
+    /// The name used to select the scene, e.g. from the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Scene::RandomScene => "random_scene",
+            Scene::TwoCheckeredSpheres => "two_checkered_spheres",
+            Scene::TwoPerlinSpheres => "two_perlin_spheres",
+            Scene::SimpleLight => "simple_light",
+            Scene::CornellBox => "cornell_box",
+        }
+    }
+
+    /// Build the scene's world and camera. Scenes with random content are generated from 'seed'.
+    pub fn build(self, aspect_ratio: f64, seed: u64) -> (HittableList, Camera) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Scene::RandomScene => random_scene(aspect_ratio, &mut rng),
+            Scene::TwoCheckeredSpheres => two_checkered_spheres(aspect_ratio),
+            Scene::TwoPerlinSpheres => two_perlin_spheres(aspect_ratio, &mut rng),
+            Scene::SimpleLight => simple_light(aspect_ratio),
+            Scene::CornellBox => cornell_box(aspect_ratio),
+        }
+    }
+}
+
+named_enum!(Scene, "scene");
+
+pub fn random_scene(aspect_ratio: f64, rng: &mut impl Rng) -> (HittableList, Camera) {
     let mut world = HittableList::new();
-    let mut rng = rand::thread_rng();
 
     let white = SolidColour::new(Colour::new(0.9, 0.9, 0.9));
     let green = SolidColour::new(Colour::new(0.2, 0.3, 0.1));
     let checker = CheckeredTexture::new(white, green);
 
+    // The checker pattern comes from the sines of the coordinates, which are all zero at y = 0, so the ground sits
+    // just below it.
     let ground = Lambertian::new(checker);
-    world.push(Box::new(Sphere::new(
-        Vec3(0.0, -1000.0, 0.0),
-        1000.0,
+    world.push(Box::new(InfinitePlane::new(
+        Vec3(0.0, -0.001, 0.0),
+        Vec3(0.0, 1.0, 0.0),
         ground,
     )));
 
@@ -102,8 +154,9 @@ pub fn two_checkered_spheres(aspect_ratio: f64) -> (HittableList, Camera) {
     let green = SolidColour::new(Colour::new(0.2, 0.3, 0.1));
     let checker = CheckeredTexture::new(white, green);
 
-    let mat = Lambertian::new(checker);
-    world.push(Box::new(Sphere::new(Vec3(0.0, -10.0, 0.0), 10.0, mat)));
+    // Both spheres share the material, so they have the same material ID.
+    let mat = Arc::new(Lambertian::new(checker));
+    world.push(Box::new(Sphere::new(Vec3(0.0, -10.0, 0.0), 10.0, mat.clone())));
     world.push(Box::new(Sphere::new(Vec3(0.0, 10.0, 0.0), 10.0, mat)));
 
     let camera = Camera::new(
@@ -122,11 +175,11 @@ pub fn two_checkered_spheres(aspect_ratio: f64) -> (HittableList, Camera) {
     (world, camera)
 }
 
-pub fn two_perlin_spheres(aspect_ratio: f64) -> (HittableList, Camera) {
+pub fn two_perlin_spheres(aspect_ratio: f64, rng: &mut impl Rng) -> (HittableList, Camera) {
     let mut world = HittableList::new();
 
-    let perlin_1 = NoiseTexture::new();
-    let perlin_2 = NoiseTexture::new();
+    let perlin_1 = NoiseTexture::new(rng);
+    let perlin_2 = NoiseTexture::new(rng);
 
     world.push(Box::new(Sphere::new(
         Vec3(0.0, -1000.0, 0.0),
@@ -194,7 +247,7 @@ pub fn cornell_box(aspect_ratio: f64) -> (HittableList, Camera) {
     let mut world = HittableList::new();
 
     let red = Lambertian::new(SolidColour::new(Colour::new(0.65, 0.05, 0.05)));
-    let white = Lambertian::new(SolidColour::new(Colour::new(0.73, 0.73, 0.73)));
+    let white = Arc::new(Lambertian::new(SolidColour::new(Colour::new(0.73, 0.73, 0.73))));
     let green = Lambertian::new(SolidColour::new(Colour::new(0.12, 0.45, 0.15)));
 
     let light = DiffuseLight::new(SolidColour::new(Colour::new(15.0, 15.0, 15.0)));
@@ -202,12 +255,20 @@ pub fn cornell_box(aspect_ratio: f64) -> (HittableList, Camera) {
 
     world.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red))); // right
     world.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green))); // left
-    world.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white))); // bottom
-    world.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white))); // top
-    world.push(Box::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white))); // back
-
-    world.push(Box::new(Cuboid::new(Vec3(130.0, 0.0, 65.0), Vec3(296.0, 165.0, 230.0), white)));
-    world.push(Box::new(Cuboid::new(Vec3(265.0, 0.0, 295.0), Vec3(430.0, 330.0, 460.0), white)));
+    world.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone()))); // bottom
+    world.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()))); // top
+    world.push(Box::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()))); // back
+
+    let tall = Cuboid::new(Vec3(0.0, 0.0, 0.0), Vec3(165.0, 330.0, 165.0), white.clone());
+    world.push(Box::new(Instance::new(
+        tall,
+        Transform::rotate_y(15.0).then(Transform::translate(Vec3(265.0, 0.0, 295.0))),
+    )));
+    let short = Cuboid::new(Vec3(0.0, 0.0, 0.0), Vec3(165.0, 165.0, 165.0), white);
+    world.push(Box::new(Instance::new(
+        short,
+        Transform::rotate_y(-18.0).then(Transform::translate(Vec3(130.0, 0.0, 65.0))),
+    )));
 
     let camera = Camera::new(
         Colour::new(0.0, 0.0, 0.0),
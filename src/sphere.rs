@@ -35,7 +35,7 @@ impl<M: Material> Sphere<M> {
     }
 
     /// Create HitRecord for an intersection with a ray. Helper for Hittable trait.
-    fn hit_helper(&self, t: f64, r: &Ray) -> HitRecord {
+    fn hit_helper(&self, t: f64, r: &Ray) -> HitRecord<'_> {
         let p = r.point_at(t);
 
         let outward_norm = (p - self.center) / self.radius;
@@ -57,7 +57,7 @@ impl<M: Material> Sphere<M> {
 
 impl<M: Material> Hittable for Sphere<M> {
     /// Calculate roots for an Sphere intersection using quadratic formula.
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = r.origin - self.center;
 
         // Calculate discriminant
@@ -94,6 +94,10 @@ impl<M: Material> Hittable for Sphere<M> {
         };
         Some(aabb)
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -133,7 +137,7 @@ impl<M: Material> MovingSphere<M> {
     }
 
     /// Create HitRecord for an intersection with a ray. Helper for Hittable trait.
-    fn hit_helper(&self, t: f64, r: &Ray) -> HitRecord {
+    fn hit_helper(&self, t: f64, r: &Ray) -> HitRecord<'_> {
         let p = r.point_at(t);
 
         let outward_norm = (p - self.center(r.time)) / self.radius;
@@ -154,7 +158,7 @@ impl<M: Material> MovingSphere<M> {
 }
 
 impl<M: Material> Hittable for MovingSphere<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = r.origin - self.center(r.time);
 
         // Calculate discriminant
@@ -203,4 +207,8 @@ impl<M: Material> Hittable for MovingSphere<M> {
         let output_box = aabb1.merge(aabb2);
         Some(output_box)
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
 }
@@ -0,0 +1,36 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::bvh::BVH;
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::render::RenderSettings;
+use crate::scenes::cornell_box;
+
+/// Return the Cornell box scene in a BVH, with its camera.
+pub(crate) fn cornell_box_world() -> (BVH, Camera) {
+    let (world, camera) = cornell_box(1.0);
+    (BVH::new(world.list, 0.0, 1.0), camera)
+}
+
+/// Return the settings for a small, quick render of a test scene with 'samples' samples per pixel.
+pub(crate) fn small_render(samples: u32, seed: u64) -> RenderSettings {
+    RenderSettings {
+        width: 24,
+        height: 24,
+        samples,
+        max_depth: 10,
+        seed,
+        ..RenderSettings::default()
+    }
+}
+
+/// Return the mean squared error between two images, over every channel of every pixel.
+pub(crate) fn mse(a: &Image, b: &Image) -> f64 {
+    let total: f64 = a
+        .pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(a, b)| (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2))
+        .sum();
+    total / (3 * a.pixels.len()) as f64
+}
@@ -1,12 +1,28 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::Rng;
+
 use crate::colour::Colour;
+use crate::hittable::HitRecord;
+use crate::image::Image;
+use crate::tonemap::Transfer;
 
 use crate::perlin::Perlin;
 
 use crate::vec::Axis::*;
 use crate::vec::Vec3;
 
-pub trait Texture: Sync {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Colour;
+
+    /// Return the colour at a hit. Materials use this when they have the whole HitRecord, so textures can use more
+    /// than its (u, v) and point.
+    fn value_at(&self, rec: &HitRecord) -> Colour {
+        self.value(rec.u, rec.v, rec.p)
+    }
 }
 
 /// A 'Texture' made from a single colour.
@@ -27,6 +43,29 @@ impl Texture for SolidColour {
     }
 }
 
+/// A 'Texture' that takes the vertex colours of the mesh it's on, interpolated across each face. Surfaces without
+/// vertex colours get a fixed colour instead.
+#[derive(Copy, Clone)]
+pub struct VertexColourTexture {
+    fallback: Colour,
+}
+
+impl VertexColourTexture {
+    pub fn new(fallback: Colour) -> Self {
+        Self { fallback }
+    }
+}
+
+impl Texture for VertexColourTexture {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Colour {
+        self.fallback
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Colour {
+        rec.colour.unwrap_or(self.fallback)
+    }
+}
+
 /// A checkered 'Texture' made from two alternating colours.
 #[derive(Copy, Clone)]
 pub struct CheckeredTexture<T: Texture> {
@@ -59,9 +98,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut impl Rng) -> Self {
         Self {
-            noise: Perlin::new(),
+            noise: Perlin::new(rng),
         }
     }
 }
@@ -71,3 +110,86 @@ impl Texture for NoiseTexture {
         Colour::new(1.0, 1.0, 1.0) * self.noise.noise(p)
     }
 }
+
+/// A 'Texture' that looks up the colour at (u, v) in an image, with (0, 0) at the bottom left. The image repeats
+/// outside [0, 1]. Clones share the same image.
+#[derive(Clone)]
+pub struct ImageTexture {
+    image: Arc<Image>,
+}
+
+impl ImageTexture {
+    /// Create a texture from an image of linear Colours.
+    pub fn new(image: Image) -> Self {
+        Self {
+            image: Arc::new(image),
+        }
+    }
+
+    /// Load a texture from a PNG file. The PNG is assumed to be sRGB encoded and is converted to linear Colours.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let invalid =
+            |e: png::DecodingError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(invalid)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(invalid)?;
+
+        let channels = info.color_type.samples();
+        let max = match info.bit_depth {
+            png::BitDepth::Sixteen => 65535.0,
+            _ => 255.0,
+        };
+        let sample = |i: usize| -> f64 {
+            let x = match info.bit_depth {
+                png::BitDepth::Sixteen => u16::from_be_bytes([buf[2 * i], buf[2 * i + 1]]) as f64,
+                _ => buf[i] as f64,
+            };
+            Transfer::Srgb.decode(x / max)
+        };
+
+        // Grey images have one colour channel and the rest have three. Any alpha channel is ignored.
+        let pixels = (0..(info.width * info.height) as usize)
+            .map(|p| {
+                let i = p * channels;
+                match channels {
+                    1 | 2 => Colour::new(sample(i), sample(i), sample(i)),
+                    _ => Colour::new(sample(i), sample(i + 1), sample(i + 2)),
+                }
+            })
+            .collect();
+
+        Ok(Self::new(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        }))
+    }
+
+    /// Return the texture with every colour multiplied by 'factor'. The image is only copied if 'factor' isn't white.
+    pub fn tinted(&self, factor: Colour) -> Self {
+        if factor == Colour::new(1.0, 1.0, 1.0) {
+            return self.clone();
+        }
+        Self::new(Image {
+            pixels: self.image.pixels.iter().map(|&c| c * factor).collect(),
+            ..*self.image
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Colour {
+        let (width, height) = (self.image.width, self.image.height);
+        if width == 0 || height == 0 {
+            return Colour::new(0.0, 0.0, 0.0);
+        }
+
+        // Image rows start at the top, but v starts at the bottom.
+        let x = (u.rem_euclid(1.0) * width as f64) as u32;
+        let y = ((1.0 - v).rem_euclid(1.0) * height as f64) as u32;
+        self.image.get(x.min(width - 1), y.min(height - 1))
+    }
+}
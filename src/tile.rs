@@ -0,0 +1,191 @@
+use crate::named::named_enum;
+
+/// A rectangle of pixels rendered as one unit of work. Rows count from the top of the image and 'x1' and 'y1' are
+/// exclusive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+}
+
+/// The orders tiles can be rendered in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    Scanline, // Row by row from the top left.
+    Spiral,   // Outwards from the centre of the image, where the subject usually is.
+    Hilbert,  // Along a Hilbert curve, so consecutive tiles are always next to each other.
+}
+
+impl TileOrder {
+    pub const ALL: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        }
+    }
+}
+
+named_enum!(TileOrder, "tile order");
+
+/// Settings controlling how a render is split into tiles. They change the order pixels are rendered in, but never
+/// the image.
+#[derive(Copy, Clone, Debug)]
+pub struct TileSettings {
+    pub size: u32, // Width and height of each tile in pixels. Tiles at the right and bottom edges may be smaller.
+    pub order: TileOrder,
+}
+
+impl Default for TileSettings {
+    fn default() -> Self {
+        Self {
+            size: 32,
+            order: TileOrder::Spiral,
+        }
+    }
+}
+
+impl TileSettings {
+    /// Split an image into tiles, in the order they should be rendered.
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<Tile> {
+        let size = self.size.max(1);
+        let columns = width.div_ceil(size);
+        let rows = height.div_ceil(size);
+
+        let mut grid: Vec<(u32, u32)> = (0..rows)
+            .flat_map(|ty| (0..columns).map(move |tx| (tx, ty)))
+            .collect();
+
+        match self.order {
+            TileOrder::Scanline => (),
+            TileOrder::Spiral => {
+                // Sort by ring around the centre tile, then by angle within each ring.
+                let cx = (columns as f64 - 1.0) / 2.0;
+                let cy = (rows as f64 - 1.0) / 2.0;
+                let key = |&(tx, ty): &(u32, u32)| {
+                    let (dx, dy) = (tx as f64 - cx, ty as f64 - cy);
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                grid.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+            }
+            TileOrder::Hilbert => {
+                let n = columns.max(rows).next_power_of_two();
+                grid.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+            }
+        }
+
+        grid.into_iter()
+            .map(|(tx, ty)| Tile {
+                x0: tx * size,
+                y0: ty * size,
+                x1: ((tx + 1) * size).min(width),
+                y1: ((ty + 1) * size).min(height),
+            })
+            .collect()
+    }
+}
+
+/// Return the distance along a Hilbert curve filling an 'n' by 'n' grid of the cell (x, y). 'n' is a power of two.
+fn hilbert_index(n: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        // Rotate the quadrant so the curve inside it lines up with the ones around it.
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::film::{Filter, FilterKind};
+    use crate::progress::CancelToken;
+    use crate::render::{ProgressiveRender, RenderSettings};
+    use crate::testing::{cornell_box_world, small_render};
+
+    #[test]
+    fn every_tile_once() {
+        // 7 by 5 tiles, with narrower ones down the right and bottom edges.
+        let (width, height) = (65, 45);
+        for order in TileOrder::ALL {
+            let tiles = TileSettings { size: 10, order }.tiles(width, height);
+            assert_eq!(tiles.len(), 35, "{order:?}");
+
+            let mut covered = vec![0; (width * height) as usize];
+            for tile in &tiles {
+                for y in tile.y0..tile.y1 {
+                    for x in tile.x0..tile.x1 {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&n| n == 1), "{order:?}");
+        }
+    }
+
+    #[test]
+    fn image_is_the_same_in_any_order() {
+        // With a filter wider than a pixel, tiles overlap on the film, so the order they're merged in matters.
+        let (world, camera) = cornell_box_world();
+        let settings = RenderSettings {
+            filter: Filter::new(FilterKind::Gaussian),
+            ..small_render(4, 2)
+        };
+        let render = |order| {
+            let mut progressive = ProgressiveRender::new(&world, &camera, &settings)
+                .with_tiles(TileSettings { size: 5, order });
+            while progressive.render_pass() {}
+            progressive.output().beauty.pixels
+        };
+
+        let scanline = render(TileOrder::Scanline);
+        assert_eq!(render(TileOrder::Spiral), scanline);
+        assert_eq!(render(TileOrder::Hilbert), scanline);
+    }
+
+    #[test]
+    fn cancelled_before_first_pass() {
+        let (world, camera) = cornell_box_world();
+        let settings = small_render(4, 2);
+        let cancel = CancelToken::new();
+        cancel.cancel();
+
+        let mut progressive =
+            ProgressiveRender::new(&world, &camera, &settings).with_cancel(cancel);
+        assert!(!progressive.render_pass());
+        assert_eq!(progressive.samples_taken(), 0);
+        let output = progressive.output();
+        assert!(output
+            .beauty
+            .pixels
+            .iter()
+            .all(|&c| c == Colour::new(0.0, 0.0, 0.0)));
+    }
+}
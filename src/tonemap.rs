@@ -0,0 +1,244 @@
+use crate::colour::Colour;
+use crate::image::Image;
+use crate::named::named_enum;
+
+/// Operators for compressing linear HDR values into the displayable range [0, 1].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMap {
+    Clamp,            // Leave values alone. Anything above 1 is clipped when quantised.
+    Reinhard,         // x / (1 + x). Never reaches white.
+    ExtendedReinhard, // Reinhard scaled so that the white point maps to 1.
+    Aces,             // Narkowicz's fit of the ACES filmic curve.
+    Uncharted2,       // John Hable's filmic curve from Uncharted 2.
+}
+
+impl ToneMap {
+    pub const ALL: [ToneMap; 5] = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ExtendedReinhard,
+        ToneMap::Aces,
+        ToneMap::Uncharted2,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "clamp",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::ExtendedReinhard => "extended-reinhard",
+            ToneMap::Aces => "aces",
+            ToneMap::Uncharted2 => "uncharted2",
+        }
+    }
+
+    /// Map a linear channel value. 'white' is the smallest value mapped to 1 by the operators that use it, and must be
+    /// greater than 0: zero divides by zero, and a negative white point turns the Uncharted 2 curve upside down.
+    pub fn apply(self, x: f64, white: f64) -> f64 {
+        match self {
+            ToneMap::Clamp => x,
+            ToneMap::Reinhard => x / (1.0 + x),
+            // Written so that x = white gives (white + 1) / (1 + white), which is exactly 1.
+            ToneMap::ExtendedReinhard => (x + x * x / (white * white)) / (1.0 + x),
+            ToneMap::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            ToneMap::Uncharted2 => hable(2.0 * x) / hable(2.0 * white),
+        }
+    }
+}
+
+/// The curve used by the Uncharted 2 operator, before normalising by the white point.
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15; // Shoulder strength
+    const B: f64 = 0.50; // Linear strength
+    const C: f64 = 0.10; // Linear angle
+    const D: f64 = 0.20; // Toe strength
+    const E: f64 = 0.02; // Toe numerator
+    const F: f64 = 0.30; // Toe denominator
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+named_enum!(ToneMap, "tone map");
+
+/// Transfer functions for encoding tone mapped values for display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    Linear, // No encoding.
+    Gamma2, // Square root. What the renderer originally used.
+    Srgb,   // The piecewise sRGB curve.
+}
+
+impl Transfer {
+    pub const ALL: [Transfer; 3] = [Transfer::Linear, Transfer::Gamma2, Transfer::Srgb];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transfer::Linear => "linear",
+            Transfer::Gamma2 => "gamma2",
+            Transfer::Srgb => "srgb",
+        }
+    }
+
+    /// Encode a linear channel value in the range [0, 1].
+    pub fn encode(self, x: f64) -> f64 {
+        match self {
+            Transfer::Linear => x,
+            Transfer::Gamma2 => x.sqrt(),
+            Transfer::Srgb => {
+                if x <= 0.0031308 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }
+        }
+    }
+
+    /// Decode an encoded channel value in the range [0, 1] back to linear. The inverse of 'encode'.
+    pub fn decode(self, x: f64) -> f64 {
+        match self {
+            Transfer::Linear => x,
+            Transfer::Gamma2 => x * x,
+            Transfer::Srgb => {
+                if x <= 0.04045 {
+                    x / 12.92
+                } else {
+                    ((x + 0.055) / 1.055).powf(2.4)
+                }
+            }
+        }
+    }
+}
+
+named_enum!(Transfer, "transfer function");
+
+/// PostProcess turns a linear framebuffer into display values: exposure, then tone mapping, then the transfer
+/// function. The result is clamped to [0, 1] and ready to be quantised.
+#[derive(Copy, Clone, Debug)]
+pub struct PostProcess {
+    pub exposure: f64, // Exposure compensation in stops. Each stop doubles the brightness.
+    pub tone_map: ToneMap,
+    pub white_point: f64, // Linear value mapped to white by the extended Reinhard and Uncharted 2 operators. Must be > 0.
+    pub transfer: Transfer,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            white_point: 11.2,
+            transfer: Transfer::Srgb,
+        }
+    }
+}
+
+impl PostProcess {
+    /// Map a single linear channel value to a display value in [0, 1].
+    pub fn apply_channel(&self, x: f64) -> f64 {
+        let x = x.max(0.0) * 2f64.powf(self.exposure);
+        let x = self.tone_map.apply(x, self.white_point).clamp(0.0, 1.0);
+        self.transfer.encode(x).clamp(0.0, 1.0)
+    }
+
+    /// Map a linear Colour to display values in [0, 1].
+    pub fn apply(&self, c: Colour) -> Colour {
+        Colour::new(
+            self.apply_channel(c.r),
+            self.apply_channel(c.g),
+            self.apply_channel(c.b),
+        )
+    }
+
+    /// Return a copy of the image mapped to display values.
+    pub fn apply_image(&self, image: &Image) -> Image {
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.iter().map(|c| self.apply(*c)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Linear values from 0 to 'max' in small steps.
+    fn steps(max: f64) -> impl Iterator<Item = f64> {
+        (0..=1000).map(move |i| max * i as f64 / 1000.0)
+    }
+
+    #[test]
+    fn black_stays_black() {
+        for tone_map in ToneMap::ALL {
+            assert!(tone_map.apply(0.0, 11.2).abs() < 1e-12, "{tone_map:?}");
+            for transfer in Transfer::ALL {
+                let post = PostProcess {
+                    tone_map,
+                    transfer,
+                    ..PostProcess::default()
+                };
+                assert_eq!(post.apply_channel(0.0), 0.0, "{tone_map:?} {transfer:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn white_point_maps_to_one() {
+        for white in [1.0, 2.5, 4.0, 11.2] {
+            assert_eq!(ToneMap::ExtendedReinhard.apply(white, white), 1.0);
+            assert!((ToneMap::Uncharted2.apply(white, white) - 1.0).abs() < 1e-12);
+        }
+        assert!(ToneMap::Reinhard.apply(1e6, 11.2) < 1.0);
+    }
+
+    #[test]
+    fn filmic_curves_are_monotonic() {
+        let white = 11.2;
+        for tone_map in [ToneMap::Aces, ToneMap::Uncharted2] {
+            let values: Vec<f64> = steps(white).map(|x| tone_map.apply(x, white)).collect();
+            assert!(
+                values.windows(2).all(|w| w[0] < w[1]),
+                "{tone_map:?} isn't increasing"
+            );
+        }
+        // Uncharted 2 stays in range up to the white point. The ACES fit overshoots 1 a little past about 7.2.
+        assert!(steps(white)
+            .map(|x| ToneMap::Uncharted2.apply(x, white))
+            .all(|y| (0.0..=1.0 + 1e-12).contains(&y)));
+        assert!(steps(7.0)
+            .map(|x| ToneMap::Aces.apply(x, white))
+            .all(|y| (0.0..=1.0).contains(&y)));
+
+        // Whatever the operator, the display values are clamped to [0, 1].
+        for tone_map in ToneMap::ALL {
+            let post = PostProcess {
+                tone_map,
+                ..PostProcess::default()
+            };
+            for x in [-1.0, 0.5, 7.5, 100.0, 1e9] {
+                assert!((0.0..=1.0).contains(&post.apply_channel(x)));
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        // Include the linear segment near black, below 0.0031308 linear and 0.04045 encoded.
+        let values = steps(1.0).chain(steps(0.005));
+        for x in values {
+            let srgb = Transfer::Srgb;
+            let encoded = srgb.encode(x);
+            assert!((0.0..=1.0).contains(&encoded));
+            assert!(
+                (srgb.decode(encoded) - x).abs() < 1e-12,
+                "{x} didn't round trip"
+            );
+            assert!(
+                (srgb.encode(srgb.decode(x)) - x).abs() < 1e-12,
+                "{x} didn't round trip"
+            );
+        }
+        assert_eq!(Transfer::Srgb.encode(0.002), 12.92 * 0.002);
+        assert!((Transfer::Srgb.encode(1.0) - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,328 @@
+use std::ops::Mul;
+
+use crate::aabb::AABB;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+
+/// A 4x4 matrix stored row by row.
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// An affine Transform made of translations, rotations and scales, kept together with its inverse so that neither
+/// direction needs a matrix inversion when it's used.
+///
+/// Transforms compose like matrices: 'a * b' applies 'b' first, then 'a'. 'then' composes them in the order they're
+/// written instead, which reads better when building one up step by step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    /// The Transform that leaves everything where it is.
+    pub fn identity() -> Self {
+        Self {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    /// Create a Transform from a matrix, given row by row. The bottom row is assumed to be (0, 0, 0, 1).
+    ///
+    /// Returns None if the matrix can't be inverted, e.g. because it scales an axis to nothing.
+    pub fn from_matrix(m: [[f64; 4]; 4]) -> Option<Self> {
+        let row = |i: usize| Vec3(m[i][0], m[i][1], m[i][2]);
+        let (r0, r1, r2) = (row(0), row(1), row(2));
+        let det = r0.dot(r1.cross(r2));
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        // The columns of the inverse of the upper 3x3 are the cross products of pairs of its rows.
+        let c0 = r1.cross(r2) / det;
+        let c1 = r2.cross(r0) / det;
+        let c2 = r0.cross(r1) / det;
+        let t = Vec3(m[0][3], m[1][3], m[2][3]);
+        let inv_t = -(c0 * t.0 + c1 * t.1 + c2 * t.2);
+
+        let mut m = m;
+        m[3] = [0.0, 0.0, 0.0, 1.0];
+        let inv = [
+            [c0.0, c1.0, c2.0, inv_t.0],
+            [c0.1, c1.1, c2.1, inv_t.1],
+            [c0.2, c1.2, c2.2, inv_t.2],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        Some(Self { m, inv })
+    }
+
+    /// Move by 'offset'.
+    pub fn translate(offset: Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for (i, x) in [offset.0, offset.1, offset.2].into_iter().enumerate() {
+            m[i][3] = x;
+            inv[i][3] = -x;
+        }
+        Self { m, inv }
+    }
+
+    /// Scale by the given factor along each axis. Negative factors mirror along that axis.
+    ///
+    /// Panics if any of the factors is zero.
+    pub fn scale(factors: Vec3) -> Self {
+        assert!(
+            factors.0 != 0.0 && factors.1 != 0.0 && factors.2 != 0.0,
+            "can't scale an axis by zero"
+        );
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for (i, x) in [factors.0, factors.1, factors.2].into_iter().enumerate() {
+            m[i][i] = x;
+            inv[i][i] = 1.0 / x;
+        }
+        Self { m, inv }
+    }
+
+    /// Rotate anticlockwise by 'degrees' when looking down the X axis towards the origin.
+    pub fn rotate_x(degrees: f64) -> Self {
+        Self::rotate(Vec3(1.0, 0.0, 0.0), degrees)
+    }
+
+    /// Rotate anticlockwise by 'degrees' when looking down the Y axis towards the origin.
+    pub fn rotate_y(degrees: f64) -> Self {
+        Self::rotate(Vec3(0.0, 1.0, 0.0), degrees)
+    }
+
+    /// Rotate anticlockwise by 'degrees' when looking down the Z axis towards the origin.
+    pub fn rotate_z(degrees: f64) -> Self {
+        Self::rotate(Vec3(0.0, 0.0, 1.0), degrees)
+    }
+
+    /// Rotate anticlockwise by 'degrees' about 'axis', which doesn't need to be normalised.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        let Vec3(x, y, z) = axis.normalise();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        let m = [
+            [
+                cos + x * x * k,
+                x * y * k - z * sin,
+                x * z * k + y * sin,
+                0.0,
+            ],
+            [
+                y * x * k + z * sin,
+                cos + y * y * k,
+                y * z * k - x * sin,
+                0.0,
+            ],
+            [
+                z * x * k - y * sin,
+                z * y * k + x * sin,
+                cos + z * z * k,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        // A rotation's inverse is its transpose.
+        Self {
+            m,
+            inv: transpose(&m),
+        }
+    }
+
+    /// Return the Transform that undoes this one.
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    /// Return the Transform that applies this one and then 'next'.
+    pub fn then(self, next: Transform) -> Self {
+        next * self
+    }
+
+    /// Return the matrix of the Transform, row by row.
+    pub fn matrix(&self) -> [[f64; 4]; 4] {
+        self.m
+    }
+
+    /// Whether the Transform mirrors space, turning a left-handed set of axes into a right-handed one. Faces whose
+    /// front is found from their winding are turned inside out by such a Transform.
+    pub fn swaps_handedness(&self) -> bool {
+        let row = |i: usize| Vec3(self.m[i][0], self.m[i][1], self.m[i][2]);
+        row(0).dot(row(1).cross(row(2))) < 0.0
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        apply(&self.m, p, 1.0)
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        apply(&self.m, v, 0.0)
+    }
+
+    /// Transform a surface normal. Normals are transformed by the inverse transpose so they stay perpendicular to
+    /// the surface under non-uniform scales. The result isn't normalised.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        apply(&transpose(&self.inv), n, 0.0)
+    }
+
+    /// Transform a ray. Its direction isn't normalised, so a point is at the same 't' along both rays.
+    pub fn ray(&self, r: &Ray) -> Ray {
+        Ray::new(self.point(r.origin), self.vector(r.direction), r.time)
+    }
+
+    /// Return the box around the transformed corners of 'bb'.
+    pub fn bounding_box(&self, bb: AABB) -> AABB {
+        let corner = |i: usize| {
+            self.point(Vec3(
+                if i & 1 == 0 { bb.min.0 } else { bb.max.0 },
+                if i & 2 == 0 { bb.min.1 } else { bb.max.1 },
+                if i & 4 == 0 { bb.min.2 } else { bb.max.2 },
+            ))
+        };
+        (1..8).fold(
+            AABB {
+                min: corner(0),
+                max: corner(0),
+            },
+            |acc, i| {
+                let p = corner(i);
+                AABB {
+                    min: acc.min.zip_with(p, f64::min),
+                    max: acc.max.zip_with(p, f64::max),
+                }
+            },
+        )
+    }
+}
+
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform {
+            m: multiply(&self.m, &other.m),
+            inv: multiply(&other.inv, &self.inv),
+        }
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = a[j][i];
+        }
+    }
+    m
+}
+
+/// Multiply the column vector (v, w) by 'm'.
+fn apply(m: &Matrix, v: Vec3, w: f64) -> Vec3 {
+    let row = |i: usize| m[i][0] * v.0 + m[i][1] * v.1 + m[i][2] * v.2 + m[i][3] * w;
+    Vec3(row(0), row(1), row(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).mag() < 1e-9, "{:?} isn't {:?}", a, b);
+    }
+
+    #[test]
+    fn products_apply_the_right_hand_side_first() {
+        let translate = Transform::translate(Vec3(1.0, 0.0, 0.0));
+        let rotate = Transform::rotate_z(90.0);
+        let p = Vec3(1.0, 0.0, 0.0);
+
+        // Rotating (1, 0, 0) a quarter turn about Z gives (0, 1, 0), and moving it along then gives (1, 1, 0).
+        assert_close((translate * rotate).point(p), Vec3(1.0, 1.0, 0.0));
+        assert_close(rotate.then(translate).point(p), Vec3(1.0, 1.0, 0.0));
+        // Moving it first gives (2, 0, 0), which turns to (0, 2, 0).
+        assert_close((rotate * translate).point(p), Vec3(0.0, 2.0, 0.0));
+        assert_close(translate.then(rotate).point(p), Vec3(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let t = Transform::translate(Vec3(1.0, -2.0, 3.0))
+            * Transform::rotate(Vec3(1.0, 1.0, 0.0), 30.0)
+            * Transform::scale(Vec3(2.0, 0.5, -1.0));
+        for p in [
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 2.0, 3.0),
+            Vec3(-4.0, 0.5, 7.0),
+        ] {
+            assert_close(t.inverse().point(t.point(p)), p);
+            assert_close(t.point(t.inverse().point(p)), p);
+        }
+        assert!(t.swaps_handedness());
+
+        // Inverting the matrix directly gives the same inverse as building it up.
+        let m = Transform::from_matrix(t.matrix()).unwrap();
+        assert_close(
+            m.inverse().point(Vec3(1.0, 2.0, 3.0)),
+            t.inverse().point(Vec3(1.0, 2.0, 3.0)),
+        );
+        // A matrix that flattens space can't be undone.
+        let mut flat = Transform::identity().matrix();
+        flat[1][1] = 0.0;
+        assert!(Transform::from_matrix(flat).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scales() {
+        let t = Transform::scale(Vec3(2.0, 1.0, 1.0));
+        // The plane x = y has this normal and tangent.
+        let normal = Vec3(1.0, -1.0, 0.0);
+        let tangent = Vec3(1.0, 1.0, 0.0);
+
+        let (normal, tangent) = (t.normal(normal), t.vector(tangent));
+        assert!(normal.dot(tangent).abs() < 1e-12);
+        assert_close(normal, Vec3(0.5, -1.0, 0.0));
+        // Transforming the normal like any other vector would tilt it off the surface.
+        assert!(t.vector(Vec3(1.0, -1.0, 0.0)).dot(tangent).abs() > 1.0);
+    }
+
+    #[test]
+    fn bounding_box_holds_the_transformed_box() {
+        let t = Transform::translate(Vec3(5.0, 0.0, 0.0)) * Transform::rotate_y(45.0);
+        let bb = t.bounding_box(AABB {
+            min: Vec3(-1.0, -1.0, -1.0),
+            max: Vec3(1.0, 2.0, 1.0),
+        });
+        let half = 2f64.sqrt();
+        assert_close(bb.min, Vec3(5.0 - half, -1.0, -half));
+        assert_close(bb.max, Vec3(5.0 + half, 2.0, half));
+    }
+}
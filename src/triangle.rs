@@ -0,0 +1,382 @@
+use crate::aabb::AABB;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+
+use crate::vec::Axis::{self, *};
+use crate::vec::Vec3;
+
+/// A triangle, optionally with a normal and UV coordinate at each vertex that are interpolated across its face.
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle<M: Material> {
+    vertices: [Vec3; 3],
+    normals: Option<[Vec3; 3]>, // Shading normals. The geometric normal is used if there aren't any.
+    uvs: [(f64, f64); 3],
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    /// Create a flat triangle with the UVs (0, 0), (1, 0) and (0, 1) at its vertices. Its front face is the one the
+    /// vertices wind anticlockwise around.
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, material: M) -> Self {
+        Self {
+            vertices: [p0, p1, p2],
+            normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            material,
+        }
+    }
+
+    /// Interpolate the given normals across the triangle, so it shades as if it were curved.
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals.map(Vec3::normalise));
+        self
+    }
+
+    /// Use the given UV coordinates at the vertices instead of the defaults.
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let hit = intersect(&self.vertices, r, t_min, t_max)?;
+        Some(hit.record(
+            r,
+            &self.vertices,
+            self.normals.as_ref(),
+            Some(&self.uvs),
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        Some(AABB::padded_around(&self.vertices))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// Where a ray crossed a triangle.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TriangleHit {
+    pub(crate) t: f64,
+    pub(crate) b: [f64; 3], // Barycentric coordinates, the weight of each vertex at the hit point.
+}
+
+impl TriangleHit {
+    /// Build the HitRecord for the hit, interpolating the normals and UVs at the vertices if there are any.
+    pub(crate) fn record<'a>(
+        &self,
+        r: &Ray,
+        p: &[Vec3; 3],
+        normals: Option<&[Vec3; 3]>,
+        uvs: Option<&[(f64, f64); 3]>,
+        material: &'a dyn Material,
+    ) -> HitRecord<'a> {
+        let [b0, b1, b2] = self.b;
+        let outward_norm = (p[1] - p[0]).cross(p[2] - p[0]).normalise();
+        let front_face = r.direction.dot(outward_norm) < 0.0;
+
+        // Keep the shading normal on the same side as the surface, or rays would scatter into it.
+        let mut norm = match normals {
+            Some(n) => {
+                let n = (n[0] * b0 + n[1] * b1 + n[2] * b2).normalise();
+                match n.dot(outward_norm) < 0.0 {
+                    true => -n,
+                    false => n,
+                }
+            }
+            None => outward_norm,
+        };
+        if !front_face {
+            norm = -norm;
+        }
+
+        let (u, v) = match uvs {
+            Some(uv) => (
+                b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0,
+                b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1,
+            ),
+            None => (b1, b2),
+        };
+        let hit_point = p[0] * b0 + p[1] * b1 + p[2] * b2;
+
+        HitRecord::new(u, v, self.t, hit_point, norm, front_face, material)
+    }
+}
+
+/// Intersect a ray with the triangle 'p' using the watertight test from "Watertight Ray/Triangle Intersection" by
+/// Woop et al. Rays that cross an edge shared by two triangles hit exactly one of them, so meshes have no cracks and
+/// no surfaces found twice.
+///
+/// The triangle is moved into a space where the ray starts at the origin and runs along +Z, where the test is just
+/// whether the origin is inside its 2D projection. The edge functions there are exact for points on the edges, so
+/// neighbouring triangles agree on which side of an edge a ray passed.
+pub(crate) fn intersect(p: &[Vec3; 3], r: &Ray, t_min: f64, t_max: f64) -> Option<TriangleHit> {
+    const AXES: [Axis; 3] = [X, Y, Z];
+
+    // Permute the axes so the ray's largest component is Z, keeping the winding the same.
+    let d = r.direction;
+    let kz = (0..3)
+        .max_by(|&a, &b| d[AXES[a]].abs().total_cmp(&d[AXES[b]].abs()))
+        .unwrap();
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if d[AXES[kz]] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let (kx, ky, kz) = (AXES[kx], AXES[ky], AXES[kz]);
+
+    // Shear the ray onto +Z.
+    let sx = -d[kx] / d[kz];
+    let sy = -d[ky] / d[kz];
+    let sz = 1.0 / d[kz];
+
+    let a = p[0] - r.origin;
+    let b = p[1] - r.origin;
+    let c = p[2] - r.origin;
+    let (ax, ay) = (a[kx] + sx * a[kz], a[ky] + sy * a[kz]);
+    let (bx, by) = (b[kx] + sx * b[kz], b[ky] + sy * b[kz]);
+    let (cx, cy) = (c[kx] + sx * c[kz], c[ky] + sy * c[kz]);
+
+    // Edge functions. The ray misses unless they all have the same sign as their sum.
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    // A ray exactly on an edge counts as inside if it would be after moving it a tiny step along X, or along Y for
+    // edges along X. Neighbouring triangles see the edge the other way round, so only one of them is hit, and the
+    // same goes for a ray through a vertex that several triangles share. 'dx' and 'dy' are how an edge function
+    // changes as the ray moves.
+    let inside = |e: f64, dx: f64, dy: f64| {
+        let e = match e == 0.0 {
+            true => match dx == 0.0 {
+                true => dy,
+                false => dx,
+            },
+            false => e,
+        };
+        e * det > 0.0
+    };
+    if !(inside(u, cy - by, bx - cx) && inside(v, ay - cy, cx - ax) && inside(w, by - ay, ax - bx))
+    {
+        return None;
+    }
+
+    // Scaled distance along the ray, divided by 'det' only once it's known to be in range.
+    let t_scaled = u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz];
+    let t = t_scaled / det;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+
+    Some(TriangleHit {
+        t,
+        b: [u / det, v / det, w / det],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColour;
+
+    type Matte = Lambertian<SolidColour>;
+
+    fn triangle(p0: Vec3, p1: Vec3, p2: Vec3) -> Triangle<Matte> {
+        let matte = Lambertian::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)));
+        Triangle::new(p0, p1, p2, matte)
+    }
+
+    /// The triangle with its right angle at the origin and its other corners at +X and +Y, facing +Z.
+    fn corner() -> Triangle<Matte> {
+        triangle(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+        )
+    }
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray::new(origin, direction, 0.0)
+    }
+
+    fn down_onto(x: f64, y: f64) -> Ray {
+        ray(Vec3(x, y, 1.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).mag() < 1e-9, "{a:?} isn't {b:?}");
+    }
+
+    #[test]
+    fn hit_inside() {
+        let tri = corner();
+        let rec = tri.hit(&down_onto(0.25, 0.5), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert_close(rec.p, Vec3(0.25, 0.5, 0.0));
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn miss_just_outside() {
+        let tri = corner();
+        for (x, y) in [
+            (0.5, 0.5 + 1e-9),
+            (-1e-9, 0.5),
+            (0.5, -1e-9),
+            (1.0 + 1e-9, 0.0),
+        ] {
+            assert!(tri.hit(&down_onto(x, y), 0.0, f64::MAX).is_none());
+        }
+        assert!(tri.hit(&down_onto(0.25, 0.25), 0.0, 0.5).is_none());
+    }
+
+    #[test]
+    fn front_face_on_both_sides() {
+        let tri = corner();
+        let rec = tri.hit(&down_onto(0.25, 0.25), 0.0, f64::MAX).unwrap();
+        assert!(rec.front_face);
+        assert_close(rec.normal, Vec3(0.0, 0.0, 1.0));
+
+        let r = ray(Vec3(0.25, 0.25, -1.0), Vec3(0.0, 0.0, 1.0));
+        let rec = tri.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!(!rec.front_face);
+        assert_close(rec.normal, Vec3(0.0, 0.0, -1.0));
+    }
+
+    /// Count how many of 'triangles' a ray hits.
+    fn hits(triangles: &[Triangle<Matte>], r: &Ray) -> usize {
+        triangles
+            .iter()
+            .filter(|tri| tri.hit(r, 0.0, f64::MAX).is_some())
+            .count()
+    }
+
+    #[test]
+    fn shared_edge_hit_once() {
+        // A unit square split along its diagonal from (1, 0) to (0, 1).
+        let square = [
+            corner(),
+            triangle(
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(1.0, 1.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+            ),
+        ];
+        for t in [0.5, 0.25, 0.1, 0.9] {
+            let on_edge = Vec3(1.0 - t, t, 0.0);
+            for direction in [
+                Vec3(0.0, 0.0, -1.0),
+                Vec3(0.0, 0.0, 1.0),
+                Vec3(0.3, -0.2, -1.0),
+                Vec3(-0.5, 0.5, 1.0),
+            ] {
+                let r = ray(on_edge - direction, direction);
+                assert_eq!(
+                    hits(&square, &r),
+                    1,
+                    "ray through {on_edge:?} along {direction:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shared_vertex_hit_once() {
+        // A unit square split into four triangles meeting at its centre.
+        let centre = Vec3(0.5, 0.5, 0.0);
+        let corners = [
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(1.0, 1.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+        ];
+        let fan: Vec<_> = (0..4)
+            .map(|i| triangle(centre, corners[i], corners[(i + 1) % 4]))
+            .collect();
+        for direction in [
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 0.0, 1.0),
+            Vec3(0.2, 0.1, -1.0),
+            Vec3(-0.4, 0.3, 1.0),
+        ] {
+            let r = ray(centre - direction, direction);
+            assert_eq!(hits(&fan, &r), 1, "ray along {direction:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_triangle() {
+        let line = triangle(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(2.0, 0.0, 0.0),
+        );
+        assert!(line.hit(&down_onto(0.5, 0.0), 0.0, f64::MAX).is_none());
+        let point = triangle(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 0.0),
+        );
+        assert!(point.hit(&down_onto(0.0, 0.0), 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn interpolated_uvs_and_normals() {
+        let s = 0.5f64.sqrt();
+        let tri = corner()
+            .with_uvs([(0.1, 0.2), (0.9, 0.2), (0.1, 0.8)])
+            .with_normals([
+                Vec3(0.0, 0.0, 1.0),
+                Vec3(1.0, 0.0, 1.0),
+                Vec3(0.0, 1.0, 1.0),
+            ]);
+
+        // The point where the vertices have weights 0.5, 0.25 and 0.25.
+        let rec = tri.hit(&down_onto(0.25, 0.25), 0.0, f64::MAX).unwrap();
+        assert!((rec.u - 0.3).abs() < 1e-12, "u is {}", rec.u);
+        assert!((rec.v - 0.35).abs() < 1e-12, "v is {}", rec.v);
+        let normal = Vec3(0.25 * s, 0.25 * s, 0.5 + 0.5 * s).normalise();
+        assert_close(rec.normal, normal);
+
+        // From behind, the shading normal is turned to face the ray like the geometric one.
+        let r = ray(Vec3(0.25, 0.25, -1.0), Vec3(0.0, 0.0, 1.0));
+        let rec = tri.hit(&r, 0.0, f64::MAX).unwrap();
+        assert_close(rec.normal, -normal);
+    }
+
+    #[test]
+    fn axis_aligned_bounds_are_padded() {
+        let bb = corner().bounding_box(0.0, 1.0).unwrap();
+        assert_close(Vec3(bb.min.0, bb.min.1, 0.0), Vec3(0.0, 0.0, 0.0));
+        assert_close(Vec3(bb.max.0, bb.max.1, 0.0), Vec3(1.0, 1.0, 0.0));
+        assert!(bb.min.2 < 0.0 && bb.max.2 > 0.0);
+        assert!(bb.hit(&down_onto(0.25, 0.25), 0.0, f64::MAX));
+
+        let wall = triangle(
+            Vec3(3.0, 0.0, 0.0),
+            Vec3(3.0, 1.0, 0.0),
+            Vec3(3.0, 0.0, 1.0),
+        );
+        let bb = wall.bounding_box(0.0, 1.0).unwrap();
+        assert!(bb.min.0 < 3.0 && bb.max.0 > 3.0);
+        assert!(bb.hit(
+            &ray(Vec3(0.0, 0.25, 0.25), Vec3(1.0, 0.0, 0.0)),
+            0.0,
+            f64::MAX
+        ));
+    }
+}
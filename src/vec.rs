@@ -1,32 +1,43 @@
-use rand::distributions::{Distribution, Standard, Uniform};
+use rand::distributions::{Distribution, Standard};
 use rand::prelude::*;
 
+use std::f64::consts::PI;
+
+use crate::sampler::Sampler;
+
 #[derive(Copy, Clone, Default, Debug)]
 pub struct Vec3(pub f64, pub f64, pub f64);
 
 impl Vec3 {
     /// Generates a random Vec3 within a unit radius. Magnitude of result is between 0 and 1.
-    /// Uniform<f64> must be for range 0..1
-    pub fn random_in_unit_sphere(dist: &Uniform<f64>, rng: &mut impl Rng) -> Self {
-        loop {
-            let v =
-                2.0 * Vec3(dist.sample(rng), dist.sample(rng), dist.sample(rng)) - Vec3::from(1.0);
-            if v.mag_sqr() < 1.0 {
-                return v;
-            }
-        }
+    /// Always uses three dimensions of the sampler: two for the direction and one for the distance from the center.
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Self {
+        let (u, v) = sampler.next_2d();
+        let r = sampler.next_1d().cbrt();
+
+        let z = 1.0 - 2.0 * u;
+        let phi = 2.0 * PI * v;
+        let s = (1.0 - z * z).max(0.0).sqrt();
+        Vec3(s * phi.cos(), s * phi.sin(), z) * r
     }
 
     /// Generates a random Vec3 within a unit disc with the radius being in the XY plane.
     /// Magnitude of result is between 0 and 1. Z component is 0.
-    /// Uniform<f64> must be for range 0..1
-    pub fn random_in_unit_disc(dist: &Uniform<f64>, rng: &mut impl Rng) -> Self {
-        loop {
-            let v = 2.0 * Vec3(dist.sample(rng), dist.sample(rng), 0.0) - Vec3(1.0, 1.0, 0.0);
-            if v.mag_sqr() < 1.0 {
-                return v;
-            }
+    /// Uses Shirley and Chiu's concentric mapping so that evenly spread samples stay evenly spread on the disc.
+    pub fn random_in_unit_disc(sampler: &mut dyn Sampler) -> Self {
+        let (u, v) = sampler.next_2d();
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+
+        if a == 0.0 && b == 0.0 {
+            return Vec3(0.0, 0.0, 0.0);
         }
+
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vec3(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     /// Calculate the dot product between 'self' and another vector.
@@ -169,7 +180,7 @@ impl std::ops::Add<f64> for Vec3 {
 
     #[inline]
     fn add(self, rhs: f64) -> Self::Output {
-        rhs * self
+        rhs + self
     }
 }
 
@@ -203,10 +214,11 @@ impl From<f64> for Vec3 {
 
 /// Names for vector components when used as a colour. Allows indexing by Channel.
 /// ```
-/// let v = Vec3(1.0, 2.0, 3.0)
-/// assert_eq!(v[R], 1.0)
-/// assert_eq!(v[G], 2.0)
-/// assert_eq!(v[B], 3.0)
+/// # use ray_tracing_with_rust::vec::{Vec3, Channel::*};
+/// let v = Vec3(1.0, 2.0, 3.0);
+/// assert_eq!(v[R], 1.0);
+/// assert_eq!(v[G], 2.0);
+/// assert_eq!(v[B], 3.0);
 /// ```
 #[derive(Copy, Clone, Debug)]
 pub enum Channel {
@@ -252,10 +264,12 @@ impl Distribution<Channel> for Standard {
 }
 
 /// Names for vector components when used as a co-ordinate. Allows indexing by Axis.
-/// let v = Vec3(1.0, 2.0, 3.0)
-/// assert_eq!(v[X], 1.0)
-/// assert_eq!(v[Y], 2.0)
-/// assert_eq!(v[Z], 3.0)
+/// ```
+/// # use ray_tracing_with_rust::vec::{Vec3, Axis::*};
+/// let v = Vec3(1.0, 2.0, 3.0);
+/// assert_eq!(v[X], 1.0);
+/// assert_eq!(v[Y], 2.0);
+/// assert_eq!(v[Z], 3.0);
 /// ```
 #[derive(Copy, Clone, Debug)]
 pub enum Axis {
@@ -321,3 +335,16 @@ pub fn refract(v: Vec3, n: Vec3, ni_over_nt: f64) -> Option<Vec3> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adding_a_scalar_adds_it_to_each_component() {
+        let v = Vec3(1.0, 2.0, 3.0) + 1.0;
+        assert_eq!((v.0, v.1, v.2), (2.0, 3.0, 4.0));
+        let v = 1.0 + Vec3(1.0, 2.0, 3.0);
+        assert_eq!((v.0, v.1, v.2), (2.0, 3.0, 4.0));
+    }
+}